@@ -0,0 +1,211 @@
+use crate::theme::*;
+use crate::{LocalizedText, TabIndex, Widget};
+use bevy::input::ButtonState;
+use bevy::input::keyboard::{Key, KeyboardInput};
+use bevy::input_focus::{FocusedInput, InputFocus};
+use bevy::prelude::*;
+
+/// Themed constructors. Colours come from [`UiTheme`] once [`UiThemePlugin`]
+/// is added, so these only take the layout: a [`Node`], or the output of
+/// [`NodeBuilder::styled`](crate::NodeBuilder::styled).
+impl Widget {
+    pub fn button(node: impl Bundle, text: impl Into<WidgetText>) -> Self {
+        Widget::new((
+            node,
+            Button,
+            ThemedButton,
+            TabIndex(0),
+            TextLayout::new_with_justify(JustifyText::Center),
+        ))
+        .text(text)
+    }
+
+    pub fn label(text: impl Into<WidgetText>) -> Self {
+        Widget::new(ThemedLabel).text(text)
+    }
+
+    pub fn panel(node: impl Bundle) -> Self {
+        Widget::new((node, ThemedPanel))
+    }
+
+    pub fn title_bar(node: impl Bundle, title: impl Into<WidgetText>) -> Self {
+        Widget::new((node, ThemedTitleBar)).child(Widget::new(ThemedTitle).text(title))
+    }
+
+    /// An unsigned integer input. Click or tab to it, then type digits;
+    /// Backspace removes the last one.
+    pub fn number_field(node: impl Bundle, value: u32) -> Self {
+        Widget::new((
+            node,
+            NumberField { value, ..default() },
+            Interaction::default(),
+            TabIndex(0),
+            Text::default(),
+            TextLayout::new_with_justify(JustifyText::Center),
+        ))
+        .observe(field_focus)
+        .observe(number_field_input)
+    }
+
+    /// A single line of text. Click or tab to it, then type; Backspace removes
+    /// the last character.
+    pub fn text_field(node: impl Bundle, value: impl Into<String>) -> Self {
+        Widget::new((
+            node,
+            TextField {
+                value: value.into(),
+                ..default()
+            },
+            Interaction::default(),
+            TabIndex(0),
+            Text::default(),
+            TextLayout::new_with_justify(JustifyText::Left),
+        ))
+        .observe(field_focus)
+        .observe(text_field_input)
+    }
+
+    fn text(self, text: impl Into<WidgetText>) -> Self {
+        match text.into() {
+            WidgetText::Plain(text) => self.insert(Text::new(text)),
+            WidgetText::Localized(text) => self.insert((Text::default(), text)),
+        }
+    }
+}
+
+/// The text of a themed widget: either shown as is, or looked up in the
+/// active language.
+#[derive(Debug, Clone)]
+pub enum WidgetText {
+    Plain(String),
+    Localized(LocalizedText),
+}
+
+impl From<&str> for WidgetText {
+    fn from(text: &str) -> Self {
+        WidgetText::Plain(text.to_string())
+    }
+}
+
+impl From<String> for WidgetText {
+    fn from(text: String) -> Self {
+        WidgetText::Plain(text)
+    }
+}
+
+impl From<LocalizedText> for WidgetText {
+    fn from(text: LocalizedText) -> Self {
+        WidgetText::Localized(text)
+    }
+}
+
+#[derive(Component, Debug, Clone)]
+pub struct NumberField {
+    pub value: u32,
+    pub max_digits: usize,
+}
+
+impl Default for NumberField {
+    fn default() -> Self {
+        NumberField {
+            value: 0,
+            max_digits: 5,
+        }
+    }
+}
+
+#[derive(Component, Debug, Clone)]
+pub struct TextField {
+    pub value: String,
+    pub max_len: usize,
+}
+
+impl Default for TextField {
+    fn default() -> Self {
+        TextField {
+            value: String::new(),
+            max_len: 256,
+        }
+    }
+}
+
+#[allow(clippy::type_complexity)]
+fn field_focus(
+    click: Trigger<Pointer<Click>>,
+    mut focus: ResMut<InputFocus>,
+    fields: Query<(), (Or<(With<NumberField>, With<TextField>)>, Without<Disabled>)>,
+) {
+    if fields.contains(click.target()) {
+        focus.set(click.target());
+    }
+}
+
+fn number_field_input(
+    mut input: Trigger<FocusedInput<KeyboardInput>>,
+    mut fields: Query<&mut NumberField, Without<Disabled>>,
+) {
+    let Ok(mut field) = fields.get_mut(input.target()) else {
+        return;
+    };
+    if input.input.state != ButtonState::Pressed {
+        return;
+    }
+
+    let mut digits = field.value.to_string();
+    match &input.input.logical_key {
+        Key::Character(c) if c.chars().all(|c| c.is_ascii_digit()) => {
+            if digits == "0" {
+                digits.clear();
+            }
+            if digits.len() + c.len() <= field.max_digits {
+                digits.push_str(c);
+            }
+        }
+        Key::Backspace => {
+            digits.pop();
+        }
+        _ => return,
+    }
+    field.value = digits.parse().unwrap_or(0);
+    input.propagate(false);
+}
+
+fn text_field_input(
+    mut input: Trigger<FocusedInput<KeyboardInput>>,
+    mut fields: Query<&mut TextField, Without<Disabled>>,
+) {
+    let Ok(mut field) = fields.get_mut(input.target()) else {
+        return;
+    };
+    if input.input.state != ButtonState::Pressed {
+        return;
+    }
+
+    match &input.input.logical_key {
+        Key::Character(c) if !c.chars().any(char::is_control) => {
+            if field.value.chars().count() + c.chars().count() <= field.max_len {
+                field.value.push_str(c);
+            }
+        }
+        Key::Space if field.value.chars().count() < field.max_len => field.value.push(' '),
+        Key::Backspace => {
+            field.value.pop();
+        }
+        _ => return,
+    }
+    input.propagate(false);
+}
+
+pub(crate) fn number_field_display(
+    mut fields: Query<(&NumberField, &mut Text), Changed<NumberField>>,
+) {
+    for (field, mut text) in &mut fields {
+        **text = field.value.to_string();
+    }
+}
+
+pub(crate) fn text_field_display(mut fields: Query<(&TextField, &mut Text), Changed<TextField>>) {
+    for (field, mut text) in &mut fields {
+        text.0.clone_from(&field.value);
+    }
+}
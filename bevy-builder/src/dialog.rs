@@ -0,0 +1,232 @@
+use crate::{BuilderExt, FocusFirst, FocusPlugin, TabGroup, Widget};
+use bevy::ecs::system::SystemParam;
+use bevy::prelude::*;
+use std::any::TypeId;
+
+/// Dialogs start at this [`GlobalZIndex`] and stack upwards from it.
+const DIALOG_Z_INDEX: i32 = 1000;
+
+/// Keeps track of open dialogs, closes the top one on Escape and brings a
+/// dialog to the front when it is clicked.
+pub struct DialogPlugin;
+
+impl Plugin for DialogPlugin {
+    fn build(&self, app: &mut App) {
+        if !app.is_plugin_added::<FocusPlugin>() {
+            app.add_plugins(FocusPlugin);
+        }
+
+        app.init_resource::<DialogStack>()
+            .add_event::<DialogOpened>()
+            .add_event::<DialogClosed>()
+            .add_event::<DialogFocused>()
+            .add_systems(
+                Update,
+                (
+                    prune_dialogs_system,
+                    escape_dialog_system,
+                    dialog_z_index_system,
+                )
+                    .chain(),
+            );
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct DialogEntry {
+    kind: TypeId,
+    /// The backdrop for modal dialogs, otherwise the window itself.
+    root: Entity,
+    window: Entity,
+    modal: bool,
+}
+
+/// Open dialogs, bottom to top.
+#[derive(Resource, Debug, Default)]
+pub struct DialogStack(Vec<DialogEntry>);
+
+impl DialogStack {
+    pub fn top(&self) -> Option<Entity> {
+        self.0.last().map(|entry| entry.window)
+    }
+
+    /// While a modal dialog is open nothing underneath it should react to input.
+    pub fn has_modal(&self) -> bool {
+        self.0.iter().any(|entry| entry.modal)
+    }
+
+    pub fn is_open<D: Component>(&self) -> bool {
+        self.find(TypeId::of::<D>()).is_some()
+    }
+
+    pub fn get<D: Component>(&self) -> Option<Entity> {
+        self.find(TypeId::of::<D>())
+            .map(|index| self.0[index].window)
+    }
+
+    fn find(&self, kind: TypeId) -> Option<usize> {
+        self.0.iter().position(|entry| entry.kind == kind)
+    }
+
+    fn find_window(&self, window: Entity) -> Option<usize> {
+        self.0.iter().position(|entry| entry.window == window)
+    }
+}
+
+/// Marks the window entity of an open dialog.
+#[derive(Component, Debug)]
+pub struct Dialog {
+    pub modal: bool,
+}
+
+/// The full-screen node behind a modal dialog. It swallows pointer input so
+/// neither UI nor meshes underneath can be picked.
+#[derive(Component, Default)]
+pub struct DialogBackdrop;
+
+#[derive(Event, Debug, Clone, Copy)]
+pub struct DialogOpened(pub Entity);
+
+#[derive(Event, Debug, Clone, Copy)]
+pub struct DialogClosed(pub Entity);
+
+#[derive(Event, Debug, Clone, Copy)]
+pub struct DialogFocused(pub Entity);
+
+/// Opens, closes and focuses dialogs. Each dialog is identified by a marker
+/// component `D`, and only one dialog of each type can be open at a time.
+#[derive(SystemParam)]
+pub struct Dialogs<'w, 's> {
+    stack: ResMut<'w, DialogStack>,
+    commands: Commands<'w, 's>,
+    opened: EventWriter<'w, DialogOpened>,
+    closed: EventWriter<'w, DialogClosed>,
+    focused: EventWriter<'w, DialogFocused>,
+}
+
+impl Dialogs<'_, '_> {
+    /// Spawns `window` as a dialog of type `D`. If one is already open it is
+    /// focused instead and `window` is dropped.
+    pub fn open<D: Component + Default>(&mut self, window: Widget, modal: bool) -> Entity {
+        if let Some(existing) = self.stack.get::<D>() {
+            self.focus(existing);
+            return existing;
+        }
+
+        let window = window
+            .insert((D::default(), Dialog { modal }))
+            .observe(focus_on_press);
+        // Tab stays inside a modal dialog, which starts out focused.
+        let window = if modal {
+            window.insert((TabGroup::modal(), FocusFirst))
+        } else {
+            window.insert(TabGroup::new(1))
+        };
+        let (root, window) = if modal {
+            let backdrop = Node::builder()
+                .width(Val::Percent(100.0))
+                .height(Val::Percent(100.0))
+                .position_type(PositionType::Absolute)
+                .justify_content(JustifyContent::Center)
+                .align_items(AlignItems::Center)
+                .build();
+            let root = self.commands.spawn((backdrop, DialogBackdrop)).id();
+            (root, window.spawn_child_of(root, &mut self.commands).root)
+        } else {
+            let window = window.spawn(&mut self.commands).root;
+            (window, window)
+        };
+
+        self.stack.0.push(DialogEntry {
+            kind: TypeId::of::<D>(),
+            root,
+            window,
+            modal,
+        });
+        self.opened.write(DialogOpened(window));
+        self.focused.write(DialogFocused(window));
+        window
+    }
+
+    pub fn close<D: Component>(&mut self) {
+        if let Some(index) = self.stack.find(TypeId::of::<D>()) {
+            self.close_at(index);
+        }
+    }
+
+    /// Closes the dialog whose window is `window`.
+    pub fn close_window(&mut self, window: Entity) {
+        if let Some(index) = self.stack.find_window(window) {
+            self.close_at(index);
+        }
+    }
+
+    pub fn close_top(&mut self) {
+        if !self.stack.0.is_empty() {
+            self.close_at(self.stack.0.len() - 1);
+        }
+    }
+
+    /// Moves `window` to the top of the stack. Dialogs below a modal dialog
+    /// cannot be focused.
+    pub fn focus(&mut self, window: Entity) {
+        let Some(index) = self.stack.find_window(window) else {
+            return;
+        };
+        let blocked = self.stack.0[index + 1..].iter().any(|entry| entry.modal);
+        if blocked || index + 1 == self.stack.0.len() {
+            return;
+        }
+        let entry = self.stack.0.remove(index);
+        self.stack.0.push(entry);
+        self.focused.write(DialogFocused(window));
+    }
+
+    pub fn stack(&self) -> &DialogStack {
+        &self.stack
+    }
+
+    fn close_at(&mut self, index: usize) {
+        let entry = self.stack.0.remove(index);
+        self.commands.entity(entry.root).despawn();
+        self.closed.write(DialogClosed(entry.window));
+        if let Some(top) = self.stack.top() {
+            self.focused.write(DialogFocused(top));
+        }
+    }
+}
+
+fn focus_on_press(pressed: Trigger<Pointer<Pressed>>, mut dialogs: Dialogs) {
+    dialogs.focus(pressed.target());
+}
+
+fn escape_dialog_system(keys: Res<ButtonInput<KeyCode>>, mut dialogs: Dialogs) {
+    if keys.just_pressed(KeyCode::Escape) {
+        dialogs.close_top();
+    }
+}
+
+/// Drops dialogs that were despawned without going through [`Dialogs`].
+fn prune_dialogs_system(
+    mut stack: ResMut<DialogStack>,
+    mut removed: RemovedComponents<Dialog>,
+    mut closed: EventWriter<DialogClosed>,
+) {
+    for window in removed.read() {
+        if let Some(index) = stack.find_window(window) {
+            stack.0.remove(index);
+            closed.write(DialogClosed(window));
+        }
+    }
+}
+
+fn dialog_z_index_system(stack: Res<DialogStack>, mut commands: Commands) {
+    if !stack.is_changed() {
+        return;
+    }
+    for (index, entry) in stack.0.iter().enumerate() {
+        commands
+            .entity(entry.root)
+            .try_insert(GlobalZIndex(DIALOG_Z_INDEX + index as i32));
+    }
+}
@@ -0,0 +1,160 @@
+use crate::Disabled;
+use bevy::input_focus::tab_navigation::{
+    NavAction, TabNavigation, TabNavigationError, TabNavigationPlugin,
+};
+use bevy::input_focus::{InputDispatchPlugin, InputFocus, InputFocusVisible};
+use bevy::prelude::*;
+
+pub use bevy::input_focus::tab_navigation::{TabGroup, TabIndex};
+
+/// Keyboard and gamepad focus for widgets with a [`TabIndex`].
+///
+/// Tab and Shift+Tab cycle through them, the arrow keys and D-pad move up and
+/// down the same order, and Enter, Space or A trigger [`Activate`] on the
+/// focused button. Clicking a button triggers [`Activate`] as well, so one
+/// observer serves every input device.
+///
+/// Widgets are only reachable inside a [`TabGroup`]. Menu screens and dialogs
+/// add one for you.
+pub struct FocusPlugin;
+
+impl Plugin for FocusPlugin {
+    fn build(&self, app: &mut App) {
+        if !app.is_plugin_added::<InputDispatchPlugin>() {
+            app.add_plugins(InputDispatchPlugin);
+        }
+        if !app.is_plugin_added::<TabNavigationPlugin>() {
+            app.add_plugins(TabNavigationPlugin);
+        }
+
+        app.add_systems(
+            Update,
+            (
+                clear_lost_focus_system,
+                focus_first_system,
+                pointer_hides_focus_system,
+                navigate_focus_system,
+                activate_focused_system,
+                click_activate_system,
+            )
+                .chain(),
+        );
+    }
+}
+
+/// Triggered on a button when it is pressed, whether by pointer, keyboard or gamepad.
+#[derive(Event, Debug, Clone, Copy)]
+pub struct Activate;
+
+/// Focuses the first widget under this entity, in tab order, once it is spawned.
+#[derive(Component, Debug, Default)]
+pub struct FocusFirst;
+
+/// Drops focus from widgets that have been despawned.
+fn clear_lost_focus_system(mut focus: ResMut<InputFocus>, entities: Query<()>) {
+    if focus
+        .get()
+        .is_some_and(|focused| !entities.contains(focused))
+    {
+        focus.clear();
+    }
+}
+
+fn focus_first_system(
+    roots: Query<Entity, Added<FocusFirst>>,
+    children: Query<&Children>,
+    focusable: Query<&TabIndex, Without<Disabled>>,
+    mut focus: ResMut<InputFocus>,
+) {
+    for root in &roots {
+        let mut order: Vec<(TabIndex, Entity)> = children
+            .iter_descendants_depth_first(root)
+            .filter_map(|entity| focusable.get(entity).ok().map(|index| (*index, entity)))
+            .filter(|(index, _)| index.0 >= 0)
+            .collect();
+        order.sort_by_key(|(index, _)| *index);
+        if let Some((_, first)) = order.first() {
+            focus.set(*first);
+        }
+    }
+}
+
+/// The focus ring is only for keyboard and gamepad users.
+fn pointer_hides_focus_system(
+    mouse: Res<ButtonInput<MouseButton>>,
+    mut focus_visible: ResMut<InputFocusVisible>,
+) {
+    if mouse.get_just_pressed().next().is_some() && focus_visible.0 {
+        focus_visible.0 = false;
+    }
+}
+
+/// Moves focus with the arrow keys or D-pad. Nothing happens unless a widget
+/// already has focus, so games can keep using the arrow keys elsewhere.
+fn navigate_focus_system(
+    keys: Res<ButtonInput<KeyCode>>,
+    gamepads: Query<&Gamepad>,
+    focusable: Query<(), With<TabIndex>>,
+    navigation: TabNavigation,
+    mut focus: ResMut<InputFocus>,
+    mut focus_visible: ResMut<InputFocusVisible>,
+) {
+    if !focus
+        .get()
+        .is_some_and(|focused| focusable.contains(focused))
+    {
+        return;
+    }
+    let pressed = |key: KeyCode, button: GamepadButton| {
+        keys.just_pressed(key) || gamepads.iter().any(|gamepad| gamepad.just_pressed(button))
+    };
+    let action = if pressed(KeyCode::ArrowDown, GamepadButton::DPadDown) {
+        NavAction::Next
+    } else if pressed(KeyCode::ArrowUp, GamepadButton::DPadUp) {
+        NavAction::Previous
+    } else {
+        return;
+    };
+
+    match navigation.navigate(&focus, action) {
+        Ok(next)
+        | Err(TabNavigationError::NoTabGroupForCurrentFocus {
+            new_focus: next, ..
+        }) => {
+            focus.set(next);
+            focus_visible.0 = true;
+        }
+        Err(err) => debug!("Could not move focus: {err}"),
+    }
+}
+
+fn activate_focused_system(
+    keys: Res<ButtonInput<KeyCode>>,
+    gamepads: Query<&Gamepad>,
+    focus: Res<InputFocus>,
+    buttons: Query<(), (With<Button>, Without<Disabled>)>,
+    mut commands: Commands,
+) {
+    let Some(focused) = focus.get().filter(|focused| buttons.contains(*focused)) else {
+        return;
+    };
+    let activate = keys.any_just_pressed([KeyCode::Enter, KeyCode::NumpadEnter, KeyCode::Space])
+        || gamepads
+            .iter()
+            .any(|gamepad| gamepad.just_pressed(GamepadButton::South));
+    if activate {
+        commands.trigger_targets(Activate, focused);
+    }
+}
+
+#[allow(clippy::type_complexity)]
+fn click_activate_system(
+    buttons: Query<(Entity, &Interaction, Has<Disabled>), (Changed<Interaction>, With<Button>)>,
+    mut commands: Commands,
+) {
+    for (entity, interaction, disabled) in &buttons {
+        if *interaction == Interaction::Pressed && !disabled {
+            commands.trigger_targets(Activate, entity);
+        }
+    }
+}
@@ -1,4 +1,23 @@
+mod controls;
+mod dialog;
+mod focus;
+mod locale;
+mod menu;
+mod style;
+mod theme;
+mod widget;
+
+pub use controls::*;
+pub use dialog::*;
+pub use focus::*;
+pub use locale::*;
+pub use menu::*;
+pub use style::*;
+pub use theme::*;
+pub use widget::*;
+
 use bevy::prelude::*;
+use serde::Deserialize;
 
 impl BuilderExt for Node {}
 
@@ -8,40 +27,78 @@ pub trait BuilderExt {
     }
 }
 
-#[derive(Default)]
+/// Builds a [`Node`] one field at a time. Any field left unset keeps the value
+/// from [`Node::DEFAULT`].
+///
+/// Also used as the layout part of a [`StyleClass`], so every field can be
+/// written in a style sheet under the same name.
+#[derive(Default, Clone, Debug, Deserialize)]
+#[serde(default)]
 pub struct NodeBuilder {
-    width: Val,
-    height: Val,
-    right: Option<Val>,
+    display: Option<Display>,
+    box_sizing: Option<BoxSizing>,
+    position_type: Option<PositionType>,
+    overflow: Option<Overflow>,
+    overflow_clip_margin: Option<OverflowClipMargin>,
     left: Option<Val>,
+    right: Option<Val>,
     top: Option<Val>,
     bottom: Option<Val>,
-    margin: Option<UiRect>,
+    width: Option<Val>,
+    height: Option<Val>,
+    min_width: Option<Val>,
+    min_height: Option<Val>,
+    max_width: Option<Val>,
+    max_height: Option<Val>,
+    aspect_ratio: Option<f32>,
+    align_items: Option<AlignItems>,
+    justify_items: Option<JustifyItems>,
     align_self: Option<AlignSelf>,
-    flex_direction: Option<FlexDirection>,
+    justify_self: Option<JustifySelf>,
+    align_content: Option<AlignContent>,
     justify_content: Option<JustifyContent>,
-    align_items: Option<AlignItems>,
+    margin: Option<UiRect>,
+    padding: Option<UiRect>,
     border: Option<UiRect>,
+    flex_direction: Option<FlexDirection>,
+    flex_wrap: Option<FlexWrap>,
+    flex_grow: Option<f32>,
+    flex_shrink: Option<f32>,
+    flex_basis: Option<Val>,
+    row_gap: Option<Val>,
+    column_gap: Option<Val>,
+    grid_auto_flow: Option<GridAutoFlow>,
+    grid_template_rows: Option<Vec<RepeatedGridTrack>>,
+    grid_template_columns: Option<Vec<RepeatedGridTrack>>,
+    grid_auto_rows: Option<Vec<GridTrack>>,
+    grid_auto_columns: Option<Vec<GridTrack>>,
+    grid_row: Option<GridPlacement>,
+    grid_column: Option<GridPlacement>,
 }
 
 impl NodeBuilder {
-    pub fn border(mut self, border: UiRect) -> Self {
-        self.border = Some(border);
+    pub fn display(mut self, display: Display) -> Self {
+        self.display = Some(display);
         self
     }
 
-    pub fn width(mut self, width: Val) -> Self {
-        self.width = width;
+    pub fn box_sizing(mut self, box_sizing: BoxSizing) -> Self {
+        self.box_sizing = Some(box_sizing);
         self
     }
 
-    pub fn height(mut self, height: Val) -> Self {
-        self.height = height;
+    pub fn position_type(mut self, position_type: PositionType) -> Self {
+        self.position_type = Some(position_type);
         self
     }
 
-    pub fn flex_direction(mut self, flex_direction: FlexDirection) -> Self {
-        self.flex_direction = Some(flex_direction);
+    pub fn overflow(mut self, overflow: Overflow) -> Self {
+        self.overflow = Some(overflow);
+        self
+    }
+
+    pub fn overflow_clip_margin(mut self, overflow_clip_margin: OverflowClipMargin) -> Self {
+        self.overflow_clip_margin = Some(overflow_clip_margin);
         self
     }
 
@@ -55,13 +112,73 @@ impl NodeBuilder {
         self
     }
 
+    pub fn top(mut self, top: Val) -> Self {
+        self.top = Some(top);
+        self
+    }
+
+    pub fn bottom(mut self, bottom: Val) -> Self {
+        self.bottom = Some(bottom);
+        self
+    }
+
+    pub fn width(mut self, width: Val) -> Self {
+        self.width = Some(width);
+        self
+    }
+
+    pub fn height(mut self, height: Val) -> Self {
+        self.height = Some(height);
+        self
+    }
+
+    pub fn min_width(mut self, min_width: Val) -> Self {
+        self.min_width = Some(min_width);
+        self
+    }
+
+    pub fn min_height(mut self, min_height: Val) -> Self {
+        self.min_height = Some(min_height);
+        self
+    }
+
+    pub fn max_width(mut self, max_width: Val) -> Self {
+        self.max_width = Some(max_width);
+        self
+    }
+
+    pub fn max_height(mut self, max_height: Val) -> Self {
+        self.max_height = Some(max_height);
+        self
+    }
+
+    pub fn aspect_ratio(mut self, aspect_ratio: f32) -> Self {
+        self.aspect_ratio = Some(aspect_ratio);
+        self
+    }
+
+    pub fn align_items(mut self, align_items: AlignItems) -> Self {
+        self.align_items = Some(align_items);
+        self
+    }
+
+    pub fn justify_items(mut self, justify_items: JustifyItems) -> Self {
+        self.justify_items = Some(justify_items);
+        self
+    }
+
     pub fn align_self(mut self, align_self: AlignSelf) -> Self {
         self.align_self = Some(align_self);
         self
     }
 
-    pub fn margin(mut self, margin: UiRect) -> Self {
-        self.margin = Some(margin);
+    pub fn justify_self(mut self, justify_self: JustifySelf) -> Self {
+        self.justify_self = Some(justify_self);
+        self
+    }
+
+    pub fn align_content(mut self, align_content: AlignContent) -> Self {
+        self.align_content = Some(align_content);
         self
     }
 
@@ -70,35 +187,387 @@ impl NodeBuilder {
         self
     }
 
-    pub fn align_items(mut self, align_items: AlignItems) -> Self {
-        self.align_items = Some(align_items);
+    pub fn margin(mut self, margin: UiRect) -> Self {
+        self.margin = Some(margin);
+        self
+    }
+
+    pub fn padding(mut self, padding: UiRect) -> Self {
+        self.padding = Some(padding);
+        self
+    }
+
+    pub fn border(mut self, border: UiRect) -> Self {
+        self.border = Some(border);
+        self
+    }
+
+    pub fn flex_direction(mut self, flex_direction: FlexDirection) -> Self {
+        self.flex_direction = Some(flex_direction);
+        self
+    }
+
+    pub fn flex_wrap(mut self, flex_wrap: FlexWrap) -> Self {
+        self.flex_wrap = Some(flex_wrap);
+        self
+    }
+
+    pub fn flex_grow(mut self, flex_grow: f32) -> Self {
+        self.flex_grow = Some(flex_grow);
         self
     }
 
+    pub fn flex_shrink(mut self, flex_shrink: f32) -> Self {
+        self.flex_shrink = Some(flex_shrink);
+        self
+    }
+
+    pub fn flex_basis(mut self, flex_basis: Val) -> Self {
+        self.flex_basis = Some(flex_basis);
+        self
+    }
+
+    pub fn row_gap(mut self, row_gap: Val) -> Self {
+        self.row_gap = Some(row_gap);
+        self
+    }
+
+    pub fn column_gap(mut self, column_gap: Val) -> Self {
+        self.column_gap = Some(column_gap);
+        self
+    }
+
+    pub fn grid_auto_flow(mut self, grid_auto_flow: GridAutoFlow) -> Self {
+        self.grid_auto_flow = Some(grid_auto_flow);
+        self
+    }
+
+    pub fn grid_template_rows(
+        mut self,
+        grid_template_rows: impl Into<Vec<RepeatedGridTrack>>,
+    ) -> Self {
+        self.grid_template_rows = Some(grid_template_rows.into());
+        self
+    }
+
+    pub fn grid_template_columns(
+        mut self,
+        grid_template_columns: impl Into<Vec<RepeatedGridTrack>>,
+    ) -> Self {
+        self.grid_template_columns = Some(grid_template_columns.into());
+        self
+    }
+
+    pub fn grid_auto_rows(mut self, grid_auto_rows: impl Into<Vec<GridTrack>>) -> Self {
+        self.grid_auto_rows = Some(grid_auto_rows.into());
+        self
+    }
+
+    pub fn grid_auto_columns(mut self, grid_auto_columns: impl Into<Vec<GridTrack>>) -> Self {
+        self.grid_auto_columns = Some(grid_auto_columns.into());
+        self
+    }
+
+    pub fn grid_row(mut self, grid_row: GridPlacement) -> Self {
+        self.grid_row = Some(grid_row);
+        self
+    }
+
+    pub fn grid_column(mut self, grid_column: GridPlacement) -> Self {
+        self.grid_column = Some(grid_column);
+        self
+    }
+
+    /// Overwrites every field that is set in `other`.
+    pub(crate) fn merge(mut self, other: &NodeBuilder) -> Self {
+        if other.display.is_some() {
+            self.display = other.display;
+        }
+        if other.box_sizing.is_some() {
+            self.box_sizing = other.box_sizing;
+        }
+        if other.position_type.is_some() {
+            self.position_type = other.position_type;
+        }
+        if other.overflow.is_some() {
+            self.overflow = other.overflow;
+        }
+        if other.overflow_clip_margin.is_some() {
+            self.overflow_clip_margin = other.overflow_clip_margin;
+        }
+        if other.left.is_some() {
+            self.left = other.left;
+        }
+        if other.right.is_some() {
+            self.right = other.right;
+        }
+        if other.top.is_some() {
+            self.top = other.top;
+        }
+        if other.bottom.is_some() {
+            self.bottom = other.bottom;
+        }
+        if other.width.is_some() {
+            self.width = other.width;
+        }
+        if other.height.is_some() {
+            self.height = other.height;
+        }
+        if other.min_width.is_some() {
+            self.min_width = other.min_width;
+        }
+        if other.min_height.is_some() {
+            self.min_height = other.min_height;
+        }
+        if other.max_width.is_some() {
+            self.max_width = other.max_width;
+        }
+        if other.max_height.is_some() {
+            self.max_height = other.max_height;
+        }
+        if other.aspect_ratio.is_some() {
+            self.aspect_ratio = other.aspect_ratio;
+        }
+        if other.align_items.is_some() {
+            self.align_items = other.align_items;
+        }
+        if other.justify_items.is_some() {
+            self.justify_items = other.justify_items;
+        }
+        if other.align_self.is_some() {
+            self.align_self = other.align_self;
+        }
+        if other.justify_self.is_some() {
+            self.justify_self = other.justify_self;
+        }
+        if other.align_content.is_some() {
+            self.align_content = other.align_content;
+        }
+        if other.justify_content.is_some() {
+            self.justify_content = other.justify_content;
+        }
+        if other.margin.is_some() {
+            self.margin = other.margin;
+        }
+        if other.padding.is_some() {
+            self.padding = other.padding;
+        }
+        if other.border.is_some() {
+            self.border = other.border;
+        }
+        if other.flex_direction.is_some() {
+            self.flex_direction = other.flex_direction;
+        }
+        if other.flex_wrap.is_some() {
+            self.flex_wrap = other.flex_wrap;
+        }
+        if other.flex_grow.is_some() {
+            self.flex_grow = other.flex_grow;
+        }
+        if other.flex_shrink.is_some() {
+            self.flex_shrink = other.flex_shrink;
+        }
+        if other.flex_basis.is_some() {
+            self.flex_basis = other.flex_basis;
+        }
+        if other.row_gap.is_some() {
+            self.row_gap = other.row_gap;
+        }
+        if other.column_gap.is_some() {
+            self.column_gap = other.column_gap;
+        }
+        if other.grid_auto_flow.is_some() {
+            self.grid_auto_flow = other.grid_auto_flow;
+        }
+        if other.grid_template_rows.is_some() {
+            self.grid_template_rows = other.grid_template_rows.clone();
+        }
+        if other.grid_template_columns.is_some() {
+            self.grid_template_columns = other.grid_template_columns.clone();
+        }
+        if other.grid_auto_rows.is_some() {
+            self.grid_auto_rows = other.grid_auto_rows.clone();
+        }
+        if other.grid_auto_columns.is_some() {
+            self.grid_auto_columns = other.grid_auto_columns.clone();
+        }
+        if other.grid_row.is_some() {
+            self.grid_row = other.grid_row;
+        }
+        if other.grid_column.is_some() {
+            self.grid_column = other.grid_column;
+        }
+        self
+    }
+
+    /// Builds the node and tags it with a style sheet class. Fields set on this
+    /// builder take precedence over the ones from the class.
+    pub fn styled(self, class: impl Into<String>) -> (Node, Styled) {
+        let node = self.clone().build();
+        (
+            node,
+            Styled {
+                class: class.into(),
+                overrides: self,
+            },
+        )
+    }
+
     pub fn build(self) -> Node {
-        let left = self.left.unwrap_or_default();
-        let right = self.right.unwrap_or_default();
-        let top = self.top.unwrap_or_default();
-        let bottom = self.bottom.unwrap_or_default();
-        let margin = self.margin.unwrap_or_default();
-        let justify_content = self.justify_content.unwrap_or_default();
-        let align_items = self.align_items.unwrap_or_default();
-        let flex_direction = self.flex_direction.unwrap_or_default();
-        let border = self.border.unwrap_or_default();
+        let default = Node::DEFAULT;
 
+        // Every field is listed explicitly so a new `Node` field fails to compile
+        // here instead of being silently dropped.
         Node {
-            width: self.width,
-            height: self.height,
-            left,
-            right,
-            top,
-            bottom,
-            margin,
-            justify_content,
-            align_items,
-            flex_direction,
-            border,
-            ..default()
+            display: self.display.unwrap_or(default.display),
+            box_sizing: self.box_sizing.unwrap_or(default.box_sizing),
+            position_type: self.position_type.unwrap_or(default.position_type),
+            overflow: self.overflow.unwrap_or(default.overflow),
+            overflow_clip_margin: self
+                .overflow_clip_margin
+                .unwrap_or(default.overflow_clip_margin),
+            left: self.left.unwrap_or(default.left),
+            right: self.right.unwrap_or(default.right),
+            top: self.top.unwrap_or(default.top),
+            bottom: self.bottom.unwrap_or(default.bottom),
+            width: self.width.unwrap_or(default.width),
+            height: self.height.unwrap_or(default.height),
+            min_width: self.min_width.unwrap_or(default.min_width),
+            min_height: self.min_height.unwrap_or(default.min_height),
+            max_width: self.max_width.unwrap_or(default.max_width),
+            max_height: self.max_height.unwrap_or(default.max_height),
+            aspect_ratio: self.aspect_ratio.or(default.aspect_ratio),
+            align_items: self.align_items.unwrap_or(default.align_items),
+            justify_items: self.justify_items.unwrap_or(default.justify_items),
+            align_self: self.align_self.unwrap_or(default.align_self),
+            justify_self: self.justify_self.unwrap_or(default.justify_self),
+            align_content: self.align_content.unwrap_or(default.align_content),
+            justify_content: self.justify_content.unwrap_or(default.justify_content),
+            margin: self.margin.unwrap_or(default.margin),
+            padding: self.padding.unwrap_or(default.padding),
+            border: self.border.unwrap_or(default.border),
+            flex_direction: self.flex_direction.unwrap_or(default.flex_direction),
+            flex_wrap: self.flex_wrap.unwrap_or(default.flex_wrap),
+            flex_grow: self.flex_grow.unwrap_or(default.flex_grow),
+            flex_shrink: self.flex_shrink.unwrap_or(default.flex_shrink),
+            flex_basis: self.flex_basis.unwrap_or(default.flex_basis),
+            row_gap: self.row_gap.unwrap_or(default.row_gap),
+            column_gap: self.column_gap.unwrap_or(default.column_gap),
+            grid_auto_flow: self.grid_auto_flow.unwrap_or(default.grid_auto_flow),
+            grid_template_rows: self
+                .grid_template_rows
+                .unwrap_or(default.grid_template_rows),
+            grid_template_columns: self
+                .grid_template_columns
+                .unwrap_or(default.grid_template_columns),
+            grid_auto_rows: self.grid_auto_rows.unwrap_or(default.grid_auto_rows),
+            grid_auto_columns: self.grid_auto_columns.unwrap_or(default.grid_auto_columns),
+            grid_row: self.grid_row.unwrap_or(default.grid_row),
+            grid_column: self.grid_column.unwrap_or(default.grid_column),
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn build_sets_every_field() {
+        let node = Node::builder()
+            .display(Display::Grid)
+            .box_sizing(BoxSizing::ContentBox)
+            .position_type(PositionType::Absolute)
+            .overflow(Overflow::clip())
+            .overflow_clip_margin(OverflowClipMargin::padding_box().with_margin(2.0))
+            .left(Val::Px(1.0))
+            .right(Val::Px(2.0))
+            .top(Val::Px(3.0))
+            .bottom(Val::Px(4.0))
+            .width(Val::Percent(50.0))
+            .height(Val::Vh(20.0))
+            .min_width(Val::Px(5.0))
+            .min_height(Val::Px(6.0))
+            .max_width(Val::Px(7.0))
+            .max_height(Val::Px(8.0))
+            .aspect_ratio(1.5)
+            .align_items(AlignItems::Center)
+            .justify_items(JustifyItems::End)
+            .align_self(AlignSelf::Start)
+            .justify_self(JustifySelf::Stretch)
+            .align_content(AlignContent::SpaceAround)
+            .justify_content(JustifyContent::SpaceBetween)
+            .margin(UiRect::all(Val::Px(9.0)))
+            .padding(UiRect::horizontal(Val::Px(10.0)))
+            .border(UiRect::vertical(Val::Px(11.0)))
+            .flex_direction(FlexDirection::Column)
+            .flex_wrap(FlexWrap::Wrap)
+            .flex_grow(2.0)
+            .flex_shrink(0.5)
+            .flex_basis(Val::Px(12.0))
+            .row_gap(Val::Px(13.0))
+            .column_gap(Val::Px(14.0))
+            .grid_auto_flow(GridAutoFlow::Column)
+            .grid_template_rows(vec![RepeatedGridTrack::flex(2, 1.0)])
+            .grid_template_columns(vec![RepeatedGridTrack::px(3, 16.0)])
+            .grid_auto_rows(vec![GridTrack::auto()])
+            .grid_auto_columns(vec![GridTrack::min_content()])
+            .grid_row(GridPlacement::span(2))
+            .grid_column(GridPlacement::start(3))
+            .build();
+
+        assert_eq!(node.display, Display::Grid);
+        assert_eq!(node.box_sizing, BoxSizing::ContentBox);
+        assert_eq!(node.position_type, PositionType::Absolute);
+        assert_eq!(node.overflow, Overflow::clip());
+        assert_eq!(
+            node.overflow_clip_margin,
+            OverflowClipMargin::padding_box().with_margin(2.0)
+        );
+        assert_eq!(node.left, Val::Px(1.0));
+        assert_eq!(node.right, Val::Px(2.0));
+        assert_eq!(node.top, Val::Px(3.0));
+        assert_eq!(node.bottom, Val::Px(4.0));
+        assert_eq!(node.width, Val::Percent(50.0));
+        assert_eq!(node.height, Val::Vh(20.0));
+        assert_eq!(node.min_width, Val::Px(5.0));
+        assert_eq!(node.min_height, Val::Px(6.0));
+        assert_eq!(node.max_width, Val::Px(7.0));
+        assert_eq!(node.max_height, Val::Px(8.0));
+        assert_eq!(node.aspect_ratio, Some(1.5));
+        assert_eq!(node.align_items, AlignItems::Center);
+        assert_eq!(node.justify_items, JustifyItems::End);
+        assert_eq!(node.align_self, AlignSelf::Start);
+        assert_eq!(node.justify_self, JustifySelf::Stretch);
+        assert_eq!(node.align_content, AlignContent::SpaceAround);
+        assert_eq!(node.justify_content, JustifyContent::SpaceBetween);
+        assert_eq!(node.margin, UiRect::all(Val::Px(9.0)));
+        assert_eq!(node.padding, UiRect::horizontal(Val::Px(10.0)));
+        assert_eq!(node.border, UiRect::vertical(Val::Px(11.0)));
+        assert_eq!(node.flex_direction, FlexDirection::Column);
+        assert_eq!(node.flex_wrap, FlexWrap::Wrap);
+        assert_eq!(node.flex_grow, 2.0);
+        assert_eq!(node.flex_shrink, 0.5);
+        assert_eq!(node.flex_basis, Val::Px(12.0));
+        assert_eq!(node.row_gap, Val::Px(13.0));
+        assert_eq!(node.column_gap, Val::Px(14.0));
+        assert_eq!(node.grid_auto_flow, GridAutoFlow::Column);
+        assert_eq!(
+            node.grid_template_rows,
+            vec![RepeatedGridTrack::flex(2, 1.0)]
+        );
+        assert_eq!(
+            node.grid_template_columns,
+            vec![RepeatedGridTrack::px(3, 16.0)]
+        );
+        assert_eq!(node.grid_auto_rows, vec![GridTrack::auto()]);
+        assert_eq!(node.grid_auto_columns, vec![GridTrack::min_content()]);
+        assert_eq!(node.grid_row, GridPlacement::span(2));
+        assert_eq!(node.grid_column, GridPlacement::start(3));
+    }
+
+    #[test]
+    fn unset_fields_keep_the_default() {
+        assert_eq!(Node::builder().build(), Node::DEFAULT);
+    }
+}
@@ -0,0 +1,395 @@
+use bevy::asset::{AssetLoader, LoadContext, io::Reader};
+use bevy::ecs::system::SystemParam;
+use bevy::prelude::*;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::fmt::{self, Write};
+use thiserror::Error;
+
+/// Loads a [`LocaleTable`] for each language from `{directory}/{language}.lang.json5`
+/// and keeps every [`LocalizedText`] in the active [`Language`].
+///
+/// The first language is the default, and is used for keys that are missing
+/// from the active one.
+pub struct LocalizationPlugin {
+    pub directory: String,
+    pub languages: Vec<String>,
+}
+
+impl LocalizationPlugin {
+    pub fn new(
+        directory: impl Into<String>,
+        languages: impl IntoIterator<Item = impl Into<String>>,
+    ) -> Self {
+        LocalizationPlugin {
+            directory: directory.into(),
+            languages: languages.into_iter().map(Into::into).collect(),
+        }
+    }
+}
+
+impl Plugin for LocalizationPlugin {
+    fn build(&self, app: &mut App) {
+        assert!(
+            !self.languages.is_empty(),
+            "LocalizationPlugin needs at least one language"
+        );
+        let directory = self.directory.clone();
+        let languages = self.languages.clone();
+        app.init_asset::<LocaleTable>()
+            .init_asset_loader::<LocaleTableLoader>()
+            .insert_resource(Language(self.languages[0].clone()))
+            .add_systems(
+                Startup,
+                move |mut commands: Commands, asset_server: Res<AssetServer>| {
+                    let tables = languages
+                        .iter()
+                        .map(|language| {
+                            let path = format!("{directory}/{language}.lang.json5");
+                            (language.clone(), asset_server.load(path))
+                        })
+                        .collect();
+                    commands.insert_resource(Locales { tables });
+                },
+            )
+            .add_systems(
+                Update,
+                localize_text_system.run_if(resource_exists::<Locales>),
+            );
+    }
+}
+
+/// The language [`LocalizedText`] is shown in. Changing it updates all of them.
+#[derive(Resource, Debug, Clone, PartialEq, Eq)]
+pub struct Language(pub String);
+
+/// The tables of every language, in the order given to [`LocalizationPlugin`].
+#[derive(Resource, Debug)]
+pub struct Locales {
+    tables: Vec<(String, Handle<LocaleTable>)>,
+}
+
+impl Locales {
+    pub fn languages(&self) -> impl Iterator<Item = &str> {
+        self.tables.iter().map(|(language, _)| language.as_str())
+    }
+
+    fn table(&self, language: &str) -> Option<&Handle<LocaleTable>> {
+        self.tables
+            .iter()
+            .find(|(name, _)| name == language)
+            .map(|(_, table)| table)
+    }
+}
+
+/// Translations for one language.
+///
+/// ```json5
+/// {
+///     "menu.start": "Start",
+///     "hud.gold": { one: "{count} gold coin", other: "{count} gold coins" },
+/// }
+/// ```
+#[derive(Asset, TypePath, Debug, Default, Deserialize)]
+#[serde(transparent)]
+pub struct LocaleTable {
+    entries: HashMap<String, LocaleEntry>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(untagged)]
+enum LocaleEntry {
+    Text(String),
+    Plural(PluralForms),
+}
+
+/// Picks a form by the number in the argument named `count`: `zero` for 0
+/// when given, `one` for 1 and `other` for everything else.
+#[derive(Debug, Clone, Deserialize)]
+struct PluralForms {
+    #[serde(default = "default_count")]
+    count: String,
+    zero: Option<String>,
+    one: Option<String>,
+    other: String,
+}
+
+fn default_count() -> String {
+    "count".to_string()
+}
+
+impl PluralForms {
+    fn select(&self, count: Option<i64>) -> &str {
+        let form = match count {
+            Some(0) => self.zero.as_ref(),
+            Some(1) => self.one.as_ref(),
+            _ => None,
+        };
+        form.unwrap_or(&self.other)
+    }
+}
+
+/// Sets the [`Text`] or [`TextSpan`] on this entity to the translation of `key`.
+/// `{name}` in the translation is replaced by the argument called `name`.
+#[derive(Component, Debug, Clone, PartialEq)]
+pub struct LocalizedText {
+    pub key: String,
+    pub args: Vec<(String, LocaleArg)>,
+}
+
+impl LocalizedText {
+    pub fn new(key: impl Into<String>) -> Self {
+        LocalizedText {
+            key: key.into(),
+            args: Vec::new(),
+        }
+    }
+
+    pub fn with_arg(mut self, name: impl Into<String>, value: impl Into<LocaleArg>) -> Self {
+        self.args.push((name.into(), value.into()));
+        self
+    }
+
+    fn arg(&self, name: &str) -> Option<&LocaleArg> {
+        self.args
+            .iter()
+            .find(|(arg, _)| arg == name)
+            .map(|(_, value)| value)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum LocaleArg {
+    Number(i64),
+    Text(String),
+}
+
+impl fmt::Display for LocaleArg {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LocaleArg::Number(number) => write!(f, "{number}"),
+            LocaleArg::Text(text) => f.write_str(text),
+        }
+    }
+}
+
+impl From<i64> for LocaleArg {
+    fn from(number: i64) -> Self {
+        LocaleArg::Number(number)
+    }
+}
+
+impl From<i32> for LocaleArg {
+    fn from(number: i32) -> Self {
+        LocaleArg::Number(number.into())
+    }
+}
+
+impl From<u32> for LocaleArg {
+    fn from(number: u32) -> Self {
+        LocaleArg::Number(number.into())
+    }
+}
+
+impl From<&str> for LocaleArg {
+    fn from(text: &str) -> Self {
+        LocaleArg::Text(text.to_string())
+    }
+}
+
+impl From<String> for LocaleArg {
+    fn from(text: String) -> Self {
+        LocaleArg::Text(text)
+    }
+}
+
+/// Translates [`LocalizedText`] in systems that need the string itself.
+#[derive(SystemParam)]
+pub struct Localizer<'w> {
+    language: Res<'w, Language>,
+    locales: Res<'w, Locales>,
+    tables: Res<'w, Assets<LocaleTable>>,
+}
+
+impl Localizer<'_> {
+    pub fn language(&self) -> &str {
+        &self.language.0
+    }
+
+    /// The translation in the active language, falling back to the default
+    /// language and then to the key itself.
+    pub fn get(&self, text: &LocalizedText) -> String {
+        let default = self.locales.languages().next().unwrap_or_default();
+        let entry = [self.language.0.as_str(), default]
+            .into_iter()
+            .filter_map(|language| self.locales.table(language))
+            .filter_map(|handle| self.tables.get(handle))
+            .find_map(|table| table.entries.get(&text.key));
+
+        let template = match entry {
+            Some(LocaleEntry::Text(template)) => template.as_str(),
+            Some(LocaleEntry::Plural(forms)) => {
+                let count = match text.arg(&forms.count) {
+                    Some(LocaleArg::Number(number)) => Some(*number),
+                    _ => None,
+                };
+                forms.select(count)
+            }
+            None => {
+                let loaded = self
+                    .locales
+                    .table(default)
+                    .is_some_and(|handle| self.tables.contains(handle));
+                if loaded {
+                    warn!("Missing translation for `{}`", text.key);
+                }
+                return text.key.clone();
+            }
+        };
+        fill_args(template, text)
+    }
+}
+
+/// Replaces each `{name}` in `template` with the matching argument. Unknown
+/// names are left as they are.
+fn fill_args(template: &str, text: &LocalizedText) -> String {
+    let mut out = String::with_capacity(template.len());
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        let Some(len) = rest[start..].find('}') else {
+            break;
+        };
+        out.push_str(&rest[..start]);
+        let placeholder = &rest[start..=start + len];
+        match text.arg(&placeholder[1..len]) {
+            Some(value) => {
+                let _ = write!(out, "{value}");
+            }
+            None => out.push_str(placeholder),
+        }
+        rest = &rest[start + len + 1..];
+    }
+    out.push_str(rest);
+    out
+}
+
+#[derive(Default)]
+struct LocaleTableLoader;
+
+#[derive(Debug, Error)]
+enum LocaleTableLoaderError {
+    #[error("could not read locale table: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("could not parse locale table: {0}")]
+    Json5(#[from] serde_json5::Error),
+}
+
+impl AssetLoader for LocaleTableLoader {
+    type Asset = LocaleTable;
+    type Settings = ();
+    type Error = LocaleTableLoaderError;
+
+    async fn load(
+        &self,
+        reader: &mut dyn Reader,
+        _settings: &(),
+        _load_context: &mut LoadContext<'_>,
+    ) -> Result<LocaleTable, Self::Error> {
+        let mut bytes = Vec::new();
+        reader.read_to_end(&mut bytes).await?;
+        Ok(serde_json5::from_slice(&bytes)?)
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["lang.json5"]
+    }
+}
+
+fn localize_text_system(
+    localizer: Localizer,
+    mut events: EventReader<AssetEvent<LocaleTable>>,
+    mut texts: Query<(Ref<LocalizedText>, Option<&mut Text>, Option<&mut TextSpan>)>,
+) {
+    let reloaded = events.read().count() > 0 || localizer.language.is_changed();
+    for (localized, text, span) in &mut texts {
+        if !reloaded && !localized.is_changed() {
+            continue;
+        }
+        let value = localizer.get(&localized);
+        if let Some(mut text) = text {
+            text.set_if_neq(Text(value));
+        } else if let Some(mut span) = span.filter(|span| span.0 != value) {
+            span.0 = value;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bevy::ecs::system::SystemState;
+
+    fn table(source: &str) -> LocaleTable {
+        serde_json5::from_str(source).unwrap()
+    }
+
+    /// Translates `text` with the tables in `tests/locales`, English first.
+    fn localize(language: &str, text: &LocalizedText) -> String {
+        let mut world = World::new();
+        let mut tables = Assets::<LocaleTable>::default();
+        let en = tables.add(table(include_str!("../tests/locales/en.lang.json5")));
+        let de = tables.add(table(include_str!("../tests/locales/de.lang.json5")));
+        world.insert_resource(tables);
+        world.insert_resource(Locales {
+            tables: vec![("en".to_string(), en), ("de".to_string(), de)],
+        });
+        world.insert_resource(Language(language.to_string()));
+        let mut localizer = SystemState::<Localizer>::new(&mut world);
+        localizer.get(&world).get(text)
+    }
+
+    #[test]
+    fn picks_plural_forms() {
+        let gold = |count: i64| LocalizedText::new("hud.gold").with_arg("count", count);
+        assert_eq!(localize("de", &gold(0)), "Kein Gold");
+        assert_eq!(localize("de", &gold(1)), "1 Goldmünze");
+        assert_eq!(localize("de", &gold(5)), "5 Goldmünzen");
+        // Without a number to go by, the `other` form is used as it is.
+        let no_count = LocalizedText::new("hud.gold").with_arg("count", "many");
+        assert_eq!(localize("de", &no_count), "many Goldmünzen");
+        assert_eq!(
+            localize("de", &LocalizedText::new("hud.gold")),
+            "{count} Goldmünzen"
+        );
+    }
+
+    #[test]
+    fn fills_arguments() {
+        let cost = LocalizedText::new("build.cost")
+            .with_arg("gold", 3)
+            .with_arg("food", 4u32);
+        assert_eq!(localize("de", &cost), "3 Gold, 4 Nahrung");
+        assert_eq!(localize("en", &cost), "3 gold, 4 food");
+
+        let text = LocalizedText::new("").with_arg("name", "Ada");
+        assert_eq!(
+            fill_args("Hi {name}, {other} {name", &text),
+            "Hi Ada, {other} {name"
+        );
+    }
+
+    #[test]
+    fn falls_back_to_english() {
+        let back = LocalizedText::new("menu.back");
+        assert_eq!(localize("de", &back), "Back");
+        assert_eq!(localize("fr", &LocalizedText::new("menu.start")), "Start");
+    }
+
+    #[test]
+    fn shows_missing_keys() {
+        assert_eq!(
+            localize("de", &LocalizedText::new("menu.quit")),
+            "menu.quit"
+        );
+    }
+}
@@ -0,0 +1,167 @@
+use crate::{Activate, FocusFirst, FocusPlugin, TabGroup, Widget};
+use bevy::ecs::system::SystemId;
+use bevy::prelude::*;
+use std::collections::HashMap;
+
+pub type ScreenId = &'static str;
+
+/// Spawns menu screens registered with [`MenuAppExt::add_menu_screen`] as
+/// [`MenuAction`]s come in, and despawns the previous one.
+pub struct MenuNavigationPlugin;
+
+impl Plugin for MenuNavigationPlugin {
+    fn build(&self, app: &mut App) {
+        if !app.is_plugin_added::<FocusPlugin>() {
+            app.add_plugins(FocusPlugin);
+        }
+
+        app.init_resource::<MenuScreens>()
+            .init_resource::<MenuHistory>()
+            .add_event::<MenuAction>()
+            .add_observer(menu_button_observer)
+            .add_systems(Update, (back_input_system, menu_action_system).chain());
+    }
+}
+
+pub trait MenuAppExt {
+    /// Registers `screen` under `id`. The system runs each time the screen is
+    /// shown and returns the UI to spawn.
+    fn add_menu_screen<M>(
+        &mut self,
+        id: ScreenId,
+        screen: impl IntoSystem<(), Widget, M> + 'static,
+    ) -> &mut Self;
+}
+
+impl MenuAppExt for App {
+    fn add_menu_screen<M>(
+        &mut self,
+        id: ScreenId,
+        screen: impl IntoSystem<(), Widget, M> + 'static,
+    ) -> &mut Self {
+        let system = self.world_mut().register_system(screen);
+        self.world_mut()
+            .get_resource_or_init::<MenuScreens>()
+            .0
+            .insert(id, system);
+        self
+    }
+}
+
+#[derive(Resource, Default)]
+struct MenuScreens(HashMap<ScreenId, SystemId<(), Widget>>);
+
+/// The screens that have been visited, oldest first. The last one is shown.
+#[derive(Resource, Debug, Default)]
+pub struct MenuHistory(Vec<ScreenId>);
+
+impl MenuHistory {
+    pub fn current(&self) -> Option<ScreenId> {
+        self.0.last().copied()
+    }
+}
+
+#[derive(Event, Debug, Clone, Copy)]
+pub enum MenuAction {
+    /// Shows a screen and remembers the current one for [`MenuAction::Back`].
+    Push(ScreenId),
+    /// Shows a screen in place of the current one.
+    Replace(ScreenId),
+    /// Returns to the previous screen. Does nothing on the first screen.
+    Back,
+    /// Hides the menu and forgets the history.
+    Close,
+}
+
+/// The root entity of the screen that is currently shown.
+#[derive(Component, Debug)]
+pub struct MenuScreen(pub ScreenId);
+
+/// Pushes a screen when the button is activated.
+#[derive(Component, Debug)]
+pub struct NavigateTo(pub ScreenId);
+
+/// Goes back to the previous screen when the button is activated.
+#[derive(Component, Debug, Default)]
+pub struct BackButton;
+
+fn menu_button_observer(
+    activate: Trigger<Activate>,
+    buttons: Query<(Option<&NavigateTo>, Has<BackButton>)>,
+    mut actions: EventWriter<MenuAction>,
+) {
+    let Ok((navigate, back)) = buttons.get(activate.target()) else {
+        return;
+    };
+    if let Some(NavigateTo(id)) = navigate {
+        actions.write(MenuAction::Push(id));
+    } else if back {
+        actions.write(MenuAction::Back);
+    }
+}
+
+fn back_input_system(
+    keys: Res<ButtonInput<KeyCode>>,
+    gamepads: Query<&Gamepad>,
+    history: Res<MenuHistory>,
+    mut actions: EventWriter<MenuAction>,
+) {
+    let back = keys.just_pressed(KeyCode::Escape)
+        || gamepads
+            .iter()
+            .any(|gamepad| gamepad.just_pressed(GamepadButton::East));
+    if back && history.0.len() > 1 {
+        actions.write(MenuAction::Back);
+    }
+}
+
+fn menu_action_system(
+    mut actions: EventReader<MenuAction>,
+    mut history: ResMut<MenuHistory>,
+    screens: Query<Entity, With<MenuScreen>>,
+    mut commands: Commands,
+) {
+    let mut changed = false;
+    for action in actions.read() {
+        match *action {
+            MenuAction::Push(id) => history.0.push(id),
+            MenuAction::Replace(id) => {
+                history.0.pop();
+                history.0.push(id);
+            }
+            MenuAction::Back if history.0.len() > 1 => {
+                history.0.pop();
+            }
+            MenuAction::Back => continue,
+            MenuAction::Close => history.0.clear(),
+        }
+        changed = true;
+    }
+    if !changed {
+        return;
+    }
+
+    for entity in &screens {
+        commands.entity(entity).despawn();
+    }
+    if let Some(id) = history.current() {
+        commands.queue(move |world: &mut World| spawn_screen(world, id));
+    }
+}
+
+fn spawn_screen(world: &mut World, id: ScreenId) {
+    let Some(system) = world.resource::<MenuScreens>().0.get(id).copied() else {
+        error!("No menu screen registered as `{id}`");
+        return;
+    };
+    match world.run_system(system) {
+        Ok(widget) => {
+            let mut commands = world.commands();
+            widget
+                .insert((MenuScreen(id), TabGroup::default(), FocusFirst))
+                .spawn(&mut commands);
+            world.flush();
+        }
+        Err(err) => error!("Could not build menu screen `{id}`: {err}"),
+    }
+}
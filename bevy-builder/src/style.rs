@@ -0,0 +1,169 @@
+use crate::{NodeBuilder, UiTheme};
+use bevy::asset::{AssetLoader, LoadContext, io::Reader};
+use bevy::prelude::*;
+use serde::{Deserialize, Deserializer};
+use std::collections::HashMap;
+use thiserror::Error;
+
+/// Loads a [`UiStyle`] sheet from `path` and keeps every [`Styled`] node in sync
+/// with it. Run with `bevy/file_watcher` enabled to pick up edits live.
+pub struct UiStylePlugin {
+    pub path: String,
+}
+
+impl UiStylePlugin {
+    pub fn new(path: impl Into<String>) -> Self {
+        UiStylePlugin { path: path.into() }
+    }
+}
+
+impl Plugin for UiStylePlugin {
+    fn build(&self, app: &mut App) {
+        let path = self.path.clone();
+        app.init_asset::<UiStyle>()
+            .init_asset_loader::<UiStyleLoader>()
+            .add_systems(
+                Startup,
+                move |mut commands: Commands, asset_server: Res<AssetServer>| {
+                    commands.insert_resource(UiStyleSheet(asset_server.load(path.clone())));
+                },
+            )
+            .add_systems(Update, apply_style_system);
+    }
+}
+
+/// The style sheet that [`Styled`] nodes read their class from.
+#[derive(Resource)]
+pub struct UiStyleSheet(pub Handle<UiStyle>);
+
+/// A JSON5 style sheet.
+///
+/// ```json5
+/// {
+///     theme: { font_size: 32, panel: "#2f4f4f" },
+///     classes: {
+///         "menu-button": {
+///             width: { Percent: 30 },
+///             margin: { left: { Px: 10 }, right: { Px: 10 }, top: { Px: 10 }, bottom: { Px: 10 } },
+///         },
+///     },
+/// }
+/// ```
+#[derive(Asset, TypePath, Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct UiStyle {
+    /// Replaces the [`UiTheme`] resource when present.
+    pub theme: Option<UiTheme>,
+    pub classes: HashMap<String, StyleClass>,
+}
+
+/// Layout and colours for every node tagged with the class.
+#[derive(Debug, Default, Clone, Deserialize)]
+#[serde(default)]
+pub struct StyleClass {
+    #[serde(flatten)]
+    pub node: NodeBuilder,
+    #[serde(deserialize_with = "hex_color_opt")]
+    pub background: Option<Color>,
+    #[serde(deserialize_with = "hex_color_opt")]
+    pub border_color: Option<Color>,
+}
+
+/// Tags a node with a class from the active [`UiStyleSheet`]. Created by
+/// [`NodeBuilder::styled`].
+#[derive(Component, Debug, Clone)]
+pub struct Styled {
+    pub class: String,
+    pub(crate) overrides: NodeBuilder,
+}
+
+#[derive(Default)]
+struct UiStyleLoader;
+
+#[derive(Debug, Error)]
+enum UiStyleLoaderError {
+    #[error("could not read style sheet: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("could not parse style sheet: {0}")]
+    Json5(#[from] serde_json5::Error),
+}
+
+impl AssetLoader for UiStyleLoader {
+    type Asset = UiStyle;
+    type Settings = ();
+    type Error = UiStyleLoaderError;
+
+    async fn load(
+        &self,
+        reader: &mut dyn Reader,
+        _settings: &(),
+        _load_context: &mut LoadContext<'_>,
+    ) -> Result<UiStyle, Self::Error> {
+        let mut bytes = Vec::new();
+        reader.read_to_end(&mut bytes).await?;
+        Ok(serde_json5::from_slice(&bytes)?)
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["style.json5"]
+    }
+}
+
+fn apply_style_system(
+    sheet: Option<Res<UiStyleSheet>>,
+    styles: Res<Assets<UiStyle>>,
+    mut events: EventReader<AssetEvent<UiStyle>>,
+    mut theme: ResMut<UiTheme>,
+    mut nodes: Query<(
+        Ref<Styled>,
+        &mut Node,
+        &mut BackgroundColor,
+        &mut BorderColor,
+    )>,
+) {
+    let Some(sheet) = sheet else {
+        return;
+    };
+    let reloaded = events
+        .read()
+        .any(|event| event.is_loaded_with_dependencies(&sheet.0) || event.is_modified(&sheet.0));
+    let Some(style) = styles.get(&sheet.0) else {
+        return;
+    };
+
+    if reloaded {
+        info!("Applying UI style sheet");
+        if let Some(sheet_theme) = &style.theme {
+            *theme = sheet_theme.clone();
+        }
+    }
+
+    for (styled, mut node, mut background, mut border) in &mut nodes {
+        if !reloaded && !styled.is_added() {
+            continue;
+        }
+        let Some(class) = style.classes.get(&styled.class) else {
+            warn!("Unknown style class `{}`", styled.class);
+            continue;
+        };
+        *node = class.node.clone().merge(&styled.overrides).build();
+        if let Some(color) = class.background {
+            background.0 = color;
+        }
+        if let Some(color) = class.border_color {
+            border.0 = color;
+        }
+    }
+}
+
+/// Reads a colour written as `"#rrggbb"` or `"#rrggbbaa"`.
+pub(crate) fn hex_color<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Color, D::Error> {
+    let hex = String::deserialize(deserializer)?;
+    Srgba::hex(&hex)
+        .map(Color::from)
+        .map_err(|err| serde::de::Error::custom(format!("invalid colour `{hex}`: {err}")))
+}
+
+fn hex_color_opt<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<Color>, D::Error> {
+    hex_color(deserializer).map(Some)
+}
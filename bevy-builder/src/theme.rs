@@ -0,0 +1,306 @@
+use crate::controls::{NumberField, TextField, number_field_display, text_field_display};
+use crate::dialog::DialogBackdrop;
+use crate::style::hex_color;
+use bevy::color::palettes::css::*;
+use bevy::input_focus::{InputDispatchPlugin, InputFocus, InputFocusVisible};
+use bevy::prelude::*;
+use serde::Deserialize;
+
+/// Registers [`UiTheme`] and the systems that keep themed widgets in sync with it.
+pub struct UiThemePlugin;
+
+impl Plugin for UiThemePlugin {
+    fn build(&self, app: &mut App) {
+        if !app.is_plugin_added::<InputDispatchPlugin>() {
+            app.add_plugins(InputDispatchPlugin);
+        }
+
+        app.init_resource::<UiTheme>().add_systems(
+            Update,
+            (
+                button_theme_system,
+                label_theme_system,
+                panel_theme_system,
+                backdrop_theme_system,
+                title_bar_theme_system,
+                field_theme_system,
+                number_field_display,
+                text_field_display,
+                focus_ring_system,
+            ),
+        );
+    }
+}
+
+/// Colours for a widget that reacts to the pointer.
+#[derive(Clone, Copy, Debug, Deserialize)]
+pub struct StateColors {
+    #[serde(deserialize_with = "hex_color")]
+    pub normal: Color,
+    #[serde(deserialize_with = "hex_color")]
+    pub hovered: Color,
+    #[serde(deserialize_with = "hex_color")]
+    pub pressed: Color,
+    #[serde(deserialize_with = "hex_color")]
+    pub disabled: Color,
+}
+
+impl StateColors {
+    pub fn get(&self, interaction: Interaction, disabled: bool) -> Color {
+        if disabled {
+            return self.disabled;
+        }
+        match interaction {
+            Interaction::Pressed => self.pressed,
+            Interaction::Hovered => self.hovered,
+            Interaction::None => self.normal,
+        }
+    }
+}
+
+/// Colours and sizes shared by every widget built with the themed constructors
+/// on [`Widget`](crate::Widget). Changing this resource restyles them all.
+///
+/// Can also be set from the `theme` section of a [`UiStyle`](crate::UiStyle).
+#[derive(Resource, Clone, Debug, Deserialize)]
+#[serde(default)]
+pub struct UiTheme {
+    pub font_size: f32,
+    #[serde(deserialize_with = "hex_color")]
+    pub text: Color,
+    #[serde(deserialize_with = "hex_color")]
+    pub text_disabled: Color,
+    #[serde(deserialize_with = "hex_color")]
+    pub panel: Color,
+    #[serde(deserialize_with = "hex_color")]
+    pub panel_border: Color,
+    #[serde(deserialize_with = "hex_color")]
+    pub title_bar: Color,
+    /// Drawn behind modal dialogs.
+    #[serde(deserialize_with = "hex_color")]
+    pub backdrop: Color,
+    #[serde(deserialize_with = "hex_color")]
+    pub title_text: Color,
+    pub button: StateColors,
+    pub button_border: StateColors,
+    /// `pressed` is used while the field has input focus.
+    pub field: StateColors,
+    /// Outline around the widget that has keyboard or gamepad focus.
+    #[serde(deserialize_with = "hex_color")]
+    pub focus_ring: Color,
+    pub border_radius: Val,
+}
+
+impl Default for UiTheme {
+    fn default() -> Self {
+        UiTheme {
+            font_size: 24.0,
+            text: Color::WHITE,
+            text_disabled: Color::srgb(0.5, 0.5, 0.5),
+            panel: DARK_SLATE_GRAY.into(),
+            panel_border: DARK_GRAY.into(),
+            title_bar: Color::BLACK,
+            backdrop: Color::srgba(0.0, 0.0, 0.0, 0.5),
+            title_text: GOLD.into(),
+            button: StateColors {
+                normal: Color::srgb(0.15, 0.15, 0.15),
+                hovered: Color::srgb(0.25, 0.25, 0.25),
+                pressed: Color::srgb(0.35, 0.75, 0.35),
+                disabled: Color::srgb(0.1, 0.1, 0.1),
+            },
+            button_border: StateColors {
+                normal: Color::BLACK,
+                hovered: Color::WHITE,
+                pressed: DARK_CYAN.into(),
+                disabled: Color::BLACK,
+            },
+            field: StateColors {
+                normal: DARK_CYAN.into(),
+                hovered: Color::srgb(0.0, 0.65, 0.65),
+                pressed: Color::srgb(0.0, 0.8, 0.8),
+                disabled: DARK_GRAY.into(),
+            },
+            focus_ring: ORANGE.into(),
+            border_radius: Val::Px(2.0),
+        }
+    }
+}
+
+/// Stops a themed widget from reacting to input and greys it out.
+#[derive(Component, Default)]
+pub struct Disabled;
+
+#[derive(Component, Default)]
+pub struct ThemedButton;
+
+/// Draws a themed button as pressed, such as the active tool in a toolbar.
+#[derive(Component, Default)]
+pub struct Selected;
+
+#[derive(Component, Default)]
+pub struct ThemedLabel;
+
+#[derive(Component, Default)]
+pub struct ThemedPanel;
+
+#[derive(Component, Default)]
+pub struct ThemedTitleBar;
+
+#[derive(Component, Default)]
+pub struct ThemedTitle;
+
+/// Marks the node whose [`Outline`] was added by [`focus_ring_system`].
+#[derive(Component)]
+struct FocusRing;
+
+type ButtonThemeQuery<'a> = (
+    Entity,
+    &'a Interaction,
+    Has<Disabled>,
+    Has<Selected>,
+    &'a mut BackgroundColor,
+    &'a mut BorderColor,
+    &'a mut BorderRadius,
+    Option<&'a mut TextColor>,
+);
+
+fn button_theme_system(
+    theme: Res<UiTheme>,
+    focus: Res<InputFocus>,
+    focus_visible: Res<InputFocusVisible>,
+    mut buttons: Query<ButtonThemeQuery, With<ThemedButton>>,
+) {
+    for (entity, interaction, disabled, selected, mut background, mut border, mut radius, text) in
+        &mut buttons
+    {
+        // A keyboard-focused button looks hovered.
+        let interaction = match *interaction {
+            _ if selected => Interaction::Pressed,
+            Interaction::None if focus_visible.0 && focus.get() == Some(entity) => {
+                Interaction::Hovered
+            }
+            interaction => interaction,
+        };
+        background.set_if_neq(BackgroundColor(theme.button.get(interaction, disabled)));
+        border.set_if_neq(BorderColor(theme.button_border.get(interaction, disabled)));
+        radius.set_if_neq(BorderRadius::all(theme.border_radius));
+        if let Some(mut text) = text {
+            let color = if disabled {
+                theme.text_disabled
+            } else {
+                theme.text
+            };
+            text.set_if_neq(TextColor(color));
+        }
+    }
+}
+
+fn label_theme_system(
+    theme: Res<UiTheme>,
+    mut labels: Query<(&mut TextColor, &mut TextFont, Has<Disabled>), With<ThemedLabel>>,
+) {
+    for (mut color, mut font, disabled) in &mut labels {
+        let text = if disabled {
+            theme.text_disabled
+        } else {
+            theme.text
+        };
+        color.set_if_neq(TextColor(text));
+        if font.font_size != theme.font_size {
+            font.font_size = theme.font_size;
+        }
+    }
+}
+
+fn panel_theme_system(
+    theme: Res<UiTheme>,
+    mut panels: Query<(&mut BackgroundColor, &mut BorderColor), With<ThemedPanel>>,
+) {
+    for (mut background, mut border) in &mut panels {
+        background.set_if_neq(BackgroundColor(theme.panel));
+        border.set_if_neq(BorderColor(theme.panel_border));
+    }
+}
+
+fn backdrop_theme_system(
+    theme: Res<UiTheme>,
+    mut backdrops: Query<&mut BackgroundColor, With<DialogBackdrop>>,
+) {
+    for mut background in &mut backdrops {
+        background.set_if_neq(BackgroundColor(theme.backdrop));
+    }
+}
+
+fn title_bar_theme_system(
+    theme: Res<UiTheme>,
+    mut title_bars: Query<&mut BackgroundColor, With<ThemedTitleBar>>,
+    mut titles: Query<(&mut TextColor, &mut TextFont), With<ThemedTitle>>,
+) {
+    for mut background in &mut title_bars {
+        background.set_if_neq(BackgroundColor(theme.title_bar));
+    }
+    for (mut color, mut font) in &mut titles {
+        color.set_if_neq(TextColor(theme.title_text));
+        if font.font_size != theme.font_size {
+            font.font_size = theme.font_size;
+        }
+    }
+}
+
+type FieldThemeQuery<'a> = (
+    Entity,
+    &'a Interaction,
+    Has<Disabled>,
+    &'a mut BackgroundColor,
+    &'a mut TextColor,
+);
+
+type FieldFilter = Or<(With<NumberField>, With<TextField>)>;
+
+fn field_theme_system(
+    theme: Res<UiTheme>,
+    focus: Res<InputFocus>,
+    mut fields: Query<FieldThemeQuery, FieldFilter>,
+) {
+    for (entity, interaction, disabled, mut background, mut text) in &mut fields {
+        let interaction = if focus.get() == Some(entity) {
+            Interaction::Pressed
+        } else {
+            *interaction
+        };
+        background.set_if_neq(BackgroundColor(theme.field.get(interaction, disabled)));
+        let color = if disabled {
+            theme.text_disabled
+        } else {
+            theme.text
+        };
+        text.set_if_neq(TextColor(color));
+    }
+}
+
+fn focus_ring_system(
+    theme: Res<UiTheme>,
+    focus: Res<InputFocus>,
+    focus_visible: Res<InputFocusVisible>,
+    rings: Query<Entity, With<FocusRing>>,
+    nodes: Query<(), With<Node>>,
+    mut commands: Commands,
+) {
+    if !theme.is_changed() && !focus.is_changed() && !focus_visible.is_changed() {
+        return;
+    }
+    let target = focus
+        .get()
+        .filter(|focused| focus_visible.0 && nodes.contains(*focused));
+    for entity in &rings {
+        if Some(entity) != target {
+            commands.entity(entity).try_remove::<(Outline, FocusRing)>();
+        }
+    }
+    if let Some(entity) = target {
+        commands.entity(entity).try_insert((
+            Outline::new(Val::Px(2.0), Val::Px(2.0), theme.focus_ring),
+            FocusRing,
+        ));
+    }
+}
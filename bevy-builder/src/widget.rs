@@ -0,0 +1,124 @@
+use bevy::ecs::system::IntoObserverSystem;
+use bevy::prelude::*;
+use std::collections::HashMap;
+use std::ops::Index;
+
+type Insert = Box<dyn FnOnce(&mut EntityCommands) + Send>;
+
+/// A declarative description of a UI entity and everything below it.
+///
+/// Nothing is spawned until [`Widget::spawn`] is called, at which point the
+/// whole hierarchy is created in one go and the entities registered with
+/// [`Widget::named`] are handed back in a [`WidgetTree`].
+pub struct Widget {
+    name: Option<String>,
+    inserts: Vec<Insert>,
+    children: Vec<Widget>,
+}
+
+impl Widget {
+    pub fn new(bundle: impl Bundle) -> Self {
+        Widget {
+            name: None,
+            inserts: vec![Box::new(move |entity| {
+                entity.insert(bundle);
+            })],
+            children: Vec::new(),
+        }
+    }
+
+    /// Registers the spawned entity under `name` in the returned [`WidgetTree`].
+    pub fn named(mut self, name: impl Into<String>) -> Self {
+        self.name = Some(name.into());
+        self
+    }
+
+    pub fn insert(mut self, bundle: impl Bundle) -> Self {
+        self.inserts.push(Box::new(move |entity| {
+            entity.insert(bundle);
+        }));
+        self
+    }
+
+    pub fn observe<E: Event, B: Bundle, M>(
+        mut self,
+        observer: impl IntoObserverSystem<E, B, M>,
+    ) -> Self {
+        self.inserts.push(Box::new(move |entity| {
+            entity.observe(observer);
+        }));
+        self
+    }
+
+    pub fn child(mut self, child: Widget) -> Self {
+        self.children.push(child);
+        self
+    }
+
+    pub fn children(mut self, children: impl IntoIterator<Item = Widget>) -> Self {
+        self.children.extend(children);
+        self
+    }
+
+    pub fn spawn(self, commands: &mut Commands) -> WidgetTree {
+        let mut named = HashMap::new();
+        let root = self.spawn_inner(commands, None, &mut named);
+        WidgetTree { root, named }
+    }
+
+    pub fn spawn_child_of(self, parent: Entity, commands: &mut Commands) -> WidgetTree {
+        let mut named = HashMap::new();
+        let root = self.spawn_inner(commands, Some(parent), &mut named);
+        WidgetTree { root, named }
+    }
+
+    fn spawn_inner(
+        self,
+        commands: &mut Commands,
+        parent: Option<Entity>,
+        named: &mut HashMap<String, Entity>,
+    ) -> Entity {
+        let mut entity = commands.spawn_empty();
+        if let Some(parent) = parent {
+            entity.insert(ChildOf(parent));
+        }
+        for insert in self.inserts {
+            insert(&mut entity);
+        }
+        let id = entity.id();
+
+        if let Some(name) = self.name {
+            commands.entity(id).insert(Name::new(name.clone()));
+            named.insert(name, id);
+        }
+
+        for child in self.children {
+            child.spawn_inner(commands, Some(id), named);
+        }
+
+        id
+    }
+}
+
+/// The entities spawned from a [`Widget`].
+#[derive(Debug, Clone)]
+pub struct WidgetTree {
+    pub root: Entity,
+    named: HashMap<String, Entity>,
+}
+
+impl WidgetTree {
+    pub fn get(&self, name: &str) -> Option<Entity> {
+        self.named.get(name).copied()
+    }
+}
+
+impl Index<&str> for WidgetTree {
+    type Output = Entity;
+
+    fn index(&self, name: &str) -> &Entity {
+        self.named
+            .get(name)
+            .unwrap_or_else(|| panic!("no widget named `{name}`"))
+    }
+}
@@ -0,0 +1,20 @@
+<?xml version="1.0" encoding="UTF-8"?>
+<tileset version="1.10" tiledversion="1.10.2" name="terrain" tilewidth="32" tileheight="32" tilecount="3" columns="3">
+ <image source="terrain.png" width="96" height="32"/>
+ <tile id="0" type="grass">
+  <properties>
+   <property name="color" type="color" value="#ff7c90ff"/>
+  </properties>
+ </tile>
+ <tile id="1" type="path">
+  <properties>
+   <property name="color" type="color" value="#ffc2b280"/>
+  </properties>
+ </tile>
+ <tile id="2" type="water">
+  <properties>
+   <property name="color" type="color" value="#ff4060c0"/>
+   <property name="buildable" type="bool" value="false"/>
+  </properties>
+ </tile>
+</tileset>
@@ -0,0 +1,14 @@
+-- The buildings the game comes with. Mods declare more in the same way, in
+-- their own files in this folder. See `LuaPlugin` for the fields.
+
+buildings = {
+    {
+        name = "farm",
+        mesh = "house.obj",
+        color = "#7c90ff",
+        cost = { gold = 0, food = 0 },
+        rate = 1,
+        capacity = 100,
+        resource = "food",
+    },
+}
@@ -0,0 +1,14 @@
+-- Every script in this folder runs when the game starts. See `LuaPlugin` for
+-- the hooks the game calls.
+
+function on_start()
+    print("Hello, bevy! from Lua")
+end
+
+function on_tile_clicked(x, y, z)
+    print(("Tile clicked at %g, %g"):format(x, z))
+end
+
+function on_storage_full(x, y, z)
+    print(("Storage full at %g, %g"):format(x, z))
+end
@@ -0,0 +1,249 @@
+use crate::lua::{LuaHook, LuaScript};
+use crate::map::{Generator, Storage, on_construct_release};
+use crate::tiles::{Palette, Tile};
+use crate::ui::PlayerResources;
+use bevy::prelude::*;
+
+/// Buildings are declared by mods as [`BuildingType`]s and built on a tile
+/// from the build popup.
+pub struct BuildingPlugin;
+
+impl Plugin for BuildingPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<BuildingTypes>()
+            .add_event::<BuildRequest>()
+            .add_systems(Update, build_system);
+    }
+}
+
+/// A building, of the [`BuildingType`] named `kind`.
+#[derive(Component, Debug, Clone)]
+pub struct Building {
+    pub kind: String,
+}
+
+/// What it takes to build something.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Cost {
+    pub gold: u32,
+    pub food: u32,
+}
+
+/// A kind of building, as declared in the `buildings` table of a mod.
+#[derive(Debug, Clone)]
+pub struct BuildingType {
+    pub name: String,
+    /// Shown in the build popup instead of the translation of
+    /// `building.<name>`.
+    pub title: Option<String>,
+    pub mesh: String,
+    pub color: Color,
+    pub cost: Cost,
+    /// How much the building produces each millisecond.
+    pub rate: u32,
+    pub capacity: u32,
+    /// What the building produces, such as `food`.
+    pub resource: String,
+}
+
+/// What a declaration leaves out, which is as for the farm in `mods/base.lua`.
+/// Also what level farms are built as while no farm is declared.
+impl Default for BuildingType {
+    fn default() -> Self {
+        BuildingType {
+            name: "farm".to_string(),
+            title: None,
+            mesh: "house.obj".to_string(),
+            color: Color::srgb_u8(124, 144, 255),
+            cost: Cost::default(),
+            rate: 1,
+            capacity: 100,
+            resource: "food".to_string(),
+        }
+    }
+}
+
+/// Every building type the loaded mods declare, in the order they were first
+/// declared. Each name keeps a stack of declarations, one per script, so that
+/// a type a mod overrides comes back when the mod is unloaded.
+#[derive(Resource, Debug, Default)]
+pub struct BuildingTypes(Vec<Vec<(AssetId<LuaScript>, BuildingType)>>);
+
+impl BuildingTypes {
+    pub fn get(&self, name: &str) -> Option<&BuildingType> {
+        self.iter().find(|building| building.name == name)
+    }
+
+    /// The script that declared the type named `name`.
+    pub fn declared_by(&self, name: &str) -> Option<AssetId<LuaScript>> {
+        self.0
+            .iter()
+            .filter_map(|stack| stack.last())
+            .find(|(_, building)| building.name == name)
+            .map(|(script, _)| *script)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &BuildingType> {
+        self.0
+            .iter()
+            .filter_map(|stack| stack.last())
+            .map(|(_, building)| building)
+    }
+
+    /// Replaces the types declared by `script`. A type declared again by a
+    /// script loaded later shadows the one from before, and a script that
+    /// is reloaded keeps its place.
+    pub fn replace(&mut self, script: AssetId<LuaScript>, buildings: Vec<BuildingType>) {
+        let mut buildings: Vec<_> = buildings.into_iter().map(Some).collect();
+        for stack in &mut self.0 {
+            let name = &stack[0].1.name;
+            let new = buildings
+                .iter_mut()
+                .find(|new| new.as_ref().is_some_and(|new| new.name == *name))
+                .and_then(Option::take);
+            match (
+                stack
+                    .iter()
+                    .position(|(declared_by, _)| *declared_by == script),
+                new,
+            ) {
+                (Some(index), Some(new)) => stack[index].1 = new,
+                (Some(index), None) => {
+                    stack.remove(index);
+                }
+                (None, Some(new)) => stack.push((script, new)),
+                (None, None) => {}
+            }
+        }
+        self.0.retain(|stack| !stack.is_empty());
+        self.0.extend(
+            buildings
+                .into_iter()
+                .flatten()
+                .map(|building| vec![(script, building)]),
+        );
+    }
+}
+
+/// Asks to build a building of type `kind` on a tile, paid for by the player.
+#[derive(Event, Debug, Clone)]
+pub struct BuildRequest {
+    pub tile: Entity,
+    pub kind: String,
+}
+
+#[allow(clippy::too_many_arguments)]
+fn build_system(
+    mut commands: Commands,
+    mut requests: EventReader<BuildRequest>,
+    types: Res<BuildingTypes>,
+    mut tiles: Query<(&mut Tile, &Transform)>,
+    mut player: Single<&mut PlayerResources>,
+    asset_server: Res<AssetServer>,
+    mut palette: Palette,
+    mut hooks: EventWriter<LuaHook>,
+) {
+    for request in requests.read() {
+        let Some(building) = types.get(&request.kind) else {
+            warn!("Cannot build unknown building `{}`", request.kind);
+            continue;
+        };
+        let Ok((mut tile, transform)) = tiles.get_mut(request.tile) else {
+            continue;
+        };
+        if !player.pay(building.cost) {
+            info!("Not enough resources to build {}", building.name);
+            continue;
+        }
+
+        tile.color = Color::BLACK;
+        let translation = transform.translation + Vec3::Y;
+        let entity = spawn_building(
+            &mut commands,
+            &asset_server,
+            &mut palette,
+            building,
+            translation,
+        );
+        hooks.write(LuaHook::BuildingCallback {
+            kind: building.name.clone(),
+            callback: "on_built",
+            entity,
+            position: translation,
+        });
+    }
+}
+
+pub fn spawn_building(
+    commands: &mut Commands,
+    asset_server: &AssetServer,
+    palette: &mut Palette,
+    building: &BuildingType,
+    translation: Vec3,
+) -> Entity {
+    commands
+        .spawn((
+            Building {
+                kind: building.name.clone(),
+            },
+            Generator {
+                rate: building.rate,
+            },
+            Storage {
+                capacity: building.capacity,
+                amount: 0,
+                resource: building.resource.clone(),
+            },
+            Transform::from_translation(translation).with_scale(Vec3::new(0.25, 0.25, 0.25)),
+            MeshMaterial3d(palette.material(building.color)),
+            Mesh3d(asset_server.load(building.mesh.clone())),
+        ))
+        .observe(on_construct_release)
+        .id()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bevy::asset::uuid::Uuid;
+
+    fn script(index: u128) -> AssetId<LuaScript> {
+        AssetId::from(Uuid::from_u128(index))
+    }
+
+    fn building(name: &str, rate: u32) -> BuildingType {
+        BuildingType {
+            name: name.to_string(),
+            rate,
+            ..default()
+        }
+    }
+
+    fn rates(types: &BuildingTypes) -> Vec<(&str, u32)> {
+        types
+            .iter()
+            .map(|building| (building.name.as_str(), building.rate))
+            .collect()
+    }
+
+    #[test]
+    fn overridden_types_come_back_on_unload() {
+        let (base, first, second) = (script(1), script(2), script(3));
+        let mut types = BuildingTypes::default();
+        types.replace(base, vec![building("farm", 1), building("mill", 2)]);
+        types.replace(first, vec![building("farm", 10)]);
+        types.replace(second, vec![building("farm", 20), building("tower", 3)]);
+        assert_eq!(rates(&types), [("farm", 20), ("mill", 2), ("tower", 3)]);
+        assert_eq!(types.declared_by("farm"), Some(second));
+
+        // Reloading a script that is shadowed leaves it shadowed.
+        types.replace(first, vec![building("farm", 11)]);
+        assert_eq!(types.get("farm").unwrap().rate, 20);
+
+        types.replace(second, Vec::new());
+        assert_eq!(rates(&types), [("farm", 11), ("mill", 2)]);
+        types.replace(first, Vec::new());
+        assert_eq!(rates(&types), [("farm", 1), ("mill", 2)]);
+        assert_eq!(types.declared_by("farm"), Some(base));
+    }
+}
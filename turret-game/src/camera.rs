@@ -0,0 +1,182 @@
+use crate::GameState;
+use bevy::input::mouse::{AccumulatedMouseScroll, MouseScrollUnit};
+use bevy::prelude::*;
+use bevy::window::PrimaryWindow;
+use std::f32::consts::{FRAC_PI_2, TAU};
+
+/// Tiles per second at the closest zoom. Panning speeds up as the camera
+/// zooms out, so the board seems to move at the same speed on screen.
+const PAN_SPEED: f32 = 1.5;
+/// How close to the edge of the window the pointer scrolls the board, in pixels.
+const EDGE_MARGIN: f32 = 12.0;
+const MIN_DISTANCE: f32 = 4.0;
+const MAX_DISTANCE: f32 = 40.0;
+/// Each line scrolled moves this fraction of the way to the board.
+const ZOOM_STEP: f32 = 0.1;
+/// Pixel scrolling, as from a touchpad, counts this many pixels as one line.
+const PIXELS_PER_LINE: f32 = 16.0;
+/// How far the camera looks down, in radians.
+const PITCH: f32 = -0.95;
+/// How quickly a quarter turn eases in, per second.
+const TURN_SPEED: f32 = 10.0;
+
+/// A strategy camera over the board. WASD or the pointer at the edge of the
+/// window pans, the wheel zooms and Q and E turn the board in quarter turns.
+/// The camera never looks past the [`BoardBounds`].
+pub struct CameraPlugin;
+
+impl Plugin for CameraPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<BoardBounds>()
+            .add_event::<FocusCamera>()
+            .add_systems(
+                Update,
+                (
+                    (pan_camera_system, zoom_camera_system, turn_camera_system)
+                        .run_if(in_state(GameState::Game)),
+                    focus_camera_system,
+                    camera_transform_system,
+                )
+                    .chain(),
+            );
+    }
+}
+
+/// The area of the ground the camera can look at.
+#[derive(Resource, Debug, Clone, Copy)]
+pub struct BoardBounds(pub Rect);
+
+impl Default for BoardBounds {
+    fn default() -> Self {
+        BoardBounds(Rect::from_center_half_size(Vec2::ZERO, Vec2::splat(5.0)))
+    }
+}
+
+/// Centres the camera on a point of the ground, such as one picked on the minimap.
+#[derive(Event, Debug, Clone, Copy)]
+pub struct FocusCamera(pub Vec2);
+
+#[derive(Component, Debug, Clone)]
+#[require(Camera3d)]
+pub struct RtsCamera {
+    /// The point on the ground in the middle of the screen, as X and Z.
+    pub focus: Vec2,
+    pub distance: f32,
+    /// Turned clockwise from looking along -Z, in quarter turns.
+    pub quarter_turns: u8,
+    /// Eases towards `quarter_turns`, in radians.
+    yaw: f32,
+}
+
+impl Default for RtsCamera {
+    fn default() -> Self {
+        RtsCamera {
+            focus: Vec2::ZERO,
+            distance: 12.0,
+            quarter_turns: 0,
+            yaw: 0.0,
+        }
+    }
+}
+
+impl RtsCamera {
+    fn target_yaw(&self) -> f32 {
+        -(self.quarter_turns as f32) * FRAC_PI_2
+    }
+}
+
+fn pan_camera_system(
+    keys: Res<ButtonInput<KeyCode>>,
+    window: Single<&Window, With<PrimaryWindow>>,
+    time: Res<Time>,
+    mut camera: Single<&mut RtsCamera>,
+) {
+    let mut direction = Vec2::ZERO;
+    let keys = [
+        (KeyCode::KeyW, Vec2::NEG_Y),
+        (KeyCode::KeyS, Vec2::Y),
+        (KeyCode::KeyA, Vec2::NEG_X),
+        (KeyCode::KeyD, Vec2::X),
+    ]
+    .map(|(key, step)| (keys.pressed(key), step));
+    for (pressed, step) in keys {
+        if pressed {
+            direction += step;
+        }
+    }
+    // The cursor is only known while it is over the window.
+    if let Some(cursor) = window.cursor_position() {
+        let size = window.size();
+        if cursor.x < EDGE_MARGIN {
+            direction.x -= 1.0;
+        } else if cursor.x > size.x - EDGE_MARGIN {
+            direction.x += 1.0;
+        }
+        if cursor.y < EDGE_MARGIN {
+            direction.y -= 1.0;
+        } else if cursor.y > size.y - EDGE_MARGIN {
+            direction.y += 1.0;
+        }
+    }
+    if direction == Vec2::ZERO {
+        return;
+    }
+
+    // Up on the screen is away from the camera across the ground.
+    let direction = Vec2::from_angle(-camera.yaw).rotate(direction.normalize());
+    let speed = PAN_SPEED * camera.distance / MIN_DISTANCE;
+    camera.focus += direction * speed * time.delta_secs();
+}
+
+fn zoom_camera_system(scroll: Res<AccumulatedMouseScroll>, mut camera: Single<&mut RtsCamera>) {
+    let lines = match scroll.unit {
+        MouseScrollUnit::Line => scroll.delta.y,
+        MouseScrollUnit::Pixel => scroll.delta.y / PIXELS_PER_LINE,
+    };
+    if lines != 0.0 {
+        camera.distance =
+            (camera.distance * (1.0 - ZOOM_STEP).powf(lines)).clamp(MIN_DISTANCE, MAX_DISTANCE);
+    }
+}
+
+fn turn_camera_system(keys: Res<ButtonInput<KeyCode>>, mut camera: Single<&mut RtsCamera>) {
+    if keys.just_pressed(KeyCode::KeyQ) {
+        camera.quarter_turns = (camera.quarter_turns + 3) % 4;
+    }
+    if keys.just_pressed(KeyCode::KeyE) {
+        camera.quarter_turns = (camera.quarter_turns + 1) % 4;
+    }
+}
+
+fn focus_camera_system(mut events: EventReader<FocusCamera>, mut camera: Single<&mut RtsCamera>) {
+    if let Some(FocusCamera(point)) = events.read().last() {
+        camera.focus = *point;
+    }
+}
+
+fn camera_transform_system(
+    bounds: Res<BoardBounds>,
+    time: Res<Time>,
+    camera: Single<(&mut RtsCamera, &mut Transform)>,
+) {
+    let (mut camera, mut transform) = camera.into_inner();
+    let target = camera.target_yaw();
+    // Turn the short way round, as from the last quarter back to the first.
+    let turn = (target - camera.yaw + TAU / 2.0).rem_euclid(TAU) - TAU / 2.0;
+    let focus = camera.focus.clamp(bounds.0.min, bounds.0.max);
+    if turn.abs() < 0.001 && focus == camera.focus && !camera.is_changed() {
+        return;
+    }
+
+    let camera = camera.as_mut();
+    camera.focus = focus;
+    camera.yaw = if turn.abs() < 0.001 {
+        target
+    } else {
+        camera.yaw + turn * (TURN_SPEED * time.delta_secs()).min(1.0)
+    };
+    let rotation = Quat::from_euler(EulerRot::YXZ, camera.yaw, PITCH, 0.0);
+    let focus = Vec3::new(focus.x, 0.0, focus.y);
+    *transform = Transform::from_translation(focus + rotation * Vec3::Z * camera.distance)
+        .with_rotation(rotation);
+}
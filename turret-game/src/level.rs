@@ -0,0 +1,439 @@
+use bevy::asset::{AssetLoader, LoadContext, ReadAssetBytesError, io::Reader};
+use bevy::prelude::*;
+use std::collections::HashMap;
+use std::io::{self, Cursor};
+use std::path::{Path, PathBuf};
+use thiserror::Error;
+use tiled::{DefaultResourceCache, LayerType, Properties, PropertyValue};
+use voxel_map::{MapError, TileType, VoxelMap};
+use xml::reader::{EventReader, XmlEvent};
+
+/// Registers the [`Level`] asset, loaded from Tiled `.tmx` maps or `.vmap`
+/// maps saved by the voxel map editor.
+pub struct LevelPlugin;
+
+impl Plugin for LevelPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_asset::<Level>()
+            .init_asset_loader::<LevelLoader>()
+            .init_asset_loader::<VoxelLevelLoader>();
+    }
+}
+
+/// A map, flattened into the tiles and objects the game spawns.
+///
+/// Tile positions are in tiles, with `(0, 0)` in the top left corner of the map.
+#[derive(Asset, TypePath, Debug, Default)]
+pub struct Level {
+    pub width: u32,
+    pub height: u32,
+    pub tiles: Vec<LevelTile>,
+    pub objects: Vec<LevelObject>,
+}
+
+#[derive(Debug, Clone)]
+pub struct LevelTile {
+    pub position: UVec2,
+    /// From the `elevation` property of the tile layer.
+    pub elevation: f32,
+    /// The type set on the tile in the tileset.
+    pub kind: Option<String>,
+    pub properties: LevelProperties,
+}
+
+/// An object from an object layer, such as a spawn point or a building.
+#[derive(Debug, Clone)]
+pub struct LevelObject {
+    pub name: String,
+    /// The type of the object in Tiled.
+    pub kind: String,
+    pub position: Vec2,
+    pub properties: LevelProperties,
+}
+
+/// Custom properties set in Tiled. Kept on spawned entities so systems can
+/// read values that have no component of their own.
+#[derive(Component, Debug, Clone, Default)]
+pub struct LevelProperties(pub Properties);
+
+impl LevelProperties {
+    // Properties from `.vmap` annotations are always strings, so numbers and
+    // colours are parsed from those as well.
+
+    pub fn get_bool(&self, name: &str) -> Option<bool> {
+        match self.0.get(name)? {
+            PropertyValue::BoolValue(value) => Some(*value),
+            PropertyValue::StringValue(value) => value.parse().ok(),
+            _ => None,
+        }
+    }
+
+    pub fn get_u32(&self, name: &str) -> Option<u32> {
+        match self.0.get(name)? {
+            PropertyValue::IntValue(value) => u32::try_from(*value).ok(),
+            PropertyValue::StringValue(value) => value.parse().ok(),
+            _ => None,
+        }
+    }
+
+    pub fn get_f32(&self, name: &str) -> Option<f32> {
+        match self.0.get(name)? {
+            PropertyValue::FloatValue(value) => Some(*value),
+            PropertyValue::IntValue(value) => Some(*value as f32),
+            PropertyValue::StringValue(value) => value.parse().ok(),
+            _ => None,
+        }
+    }
+
+    pub fn get_str(&self, name: &str) -> Option<&str> {
+        match self.0.get(name)? {
+            PropertyValue::StringValue(value) | PropertyValue::FileValue(value) => Some(value),
+            _ => None,
+        }
+    }
+
+    pub fn get_color(&self, name: &str) -> Option<Color> {
+        match self.0.get(name)? {
+            PropertyValue::ColorValue(color) => Some(Color::srgba_u8(
+                color.red,
+                color.green,
+                color.blue,
+                color.alpha,
+            )),
+            PropertyValue::StringValue(value) => Srgba::hex(value).ok().map(Color::from),
+            _ => None,
+        }
+    }
+}
+
+impl Level {
+    fn from_map(map: &tiled::Map) -> Self {
+        let mut level = Level {
+            width: map.width,
+            height: map.height,
+            ..default()
+        };
+        let tile_size = Vec2::new(map.tile_width as f32, map.tile_height as f32);
+
+        for layer in map.layers() {
+            let layer_properties = LevelProperties(layer.properties.clone());
+            match layer.layer_type() {
+                LayerType::Tiles(tiles) => {
+                    let elevation = layer_properties.get_f32("elevation").unwrap_or(0.0);
+                    for y in 0..map.height {
+                        for x in 0..map.width {
+                            let Some(tile) = tiles.get_tile(x as i32, y as i32) else {
+                                continue;
+                            };
+                            let data = tile.get_tile();
+                            level.tiles.push(LevelTile {
+                                position: UVec2::new(x, y),
+                                elevation,
+                                kind: data.as_ref().and_then(|data| data.user_type.clone()),
+                                properties: LevelProperties(
+                                    data.map(|data| data.properties.clone()).unwrap_or_default(),
+                                ),
+                            });
+                        }
+                    }
+                }
+                LayerType::Objects(objects) => {
+                    for object in objects.objects() {
+                        level.objects.push(LevelObject {
+                            name: object.name.clone(),
+                            kind: object.user_type.clone(),
+                            position: Vec2::new(object.x, object.y) / tile_size,
+                            properties: LevelProperties(object.properties.clone()),
+                        });
+                    }
+                }
+                _ => {}
+            }
+        }
+        level
+    }
+
+    /// Each column of the voxel map becomes a tile at the height of its top
+    /// voxel, and each annotation an object.
+    fn from_voxel_map(map: &VoxelMap) -> Self {
+        let dimensions = map.dimensions();
+        let mut level = Level {
+            width: dimensions.width,
+            height: dimensions.length,
+            ..default()
+        };
+
+        for z in 0..dimensions.length {
+            for x in 0..dimensions.width {
+                let Some((y, voxel)) = map.top(x, z) else {
+                    continue;
+                };
+                let mut properties = Properties::new();
+                properties.insert(
+                    "material".to_string(),
+                    PropertyValue::IntValue(voxel.material.into()),
+                );
+                if voxel.tile == TileType::Water {
+                    properties.insert("buildable".to_string(), PropertyValue::BoolValue(false));
+                }
+                let color = tile_color(voxel.tile).or_else(|| {
+                    let [red, green, blue, alpha] =
+                        map.palette.get(voxel.material)?.color.to_u8_array();
+                    Some(tiled::Color {
+                        red,
+                        green,
+                        blue,
+                        alpha,
+                    })
+                });
+                if let Some(color) = color {
+                    properties.insert("color".to_string(), PropertyValue::ColorValue(color));
+                }
+                level.tiles.push(LevelTile {
+                    position: UVec2::new(x, z),
+                    elevation: y as f32,
+                    kind: Some(voxel.tile.name().to_string()),
+                    properties: LevelProperties(properties),
+                });
+            }
+        }
+
+        for annotation in &map.annotations {
+            let [x, _, z] = annotation.position;
+            level.objects.push(LevelObject {
+                name: annotation
+                    .properties
+                    .get("name")
+                    .cloned()
+                    .unwrap_or_default(),
+                kind: annotation.kind.clone(),
+                position: Vec2::new(x as f32, z as f32),
+                properties: LevelProperties(
+                    annotation
+                        .properties
+                        .iter()
+                        .map(|(name, value)| {
+                            (name.clone(), PropertyValue::StringValue(value.clone()))
+                        })
+                        .collect(),
+                ),
+            });
+        }
+        level
+    }
+}
+
+/// Matches the colours in `levels/terrain.tsx`. Ground takes the colour of its
+/// material from the map's palette instead.
+fn tile_color(tile: TileType) -> Option<tiled::Color> {
+    let [red, green, blue] = match tile {
+        TileType::Empty | TileType::Ground => return None,
+        TileType::Path => [194, 178, 128],
+        TileType::Water => [64, 96, 192],
+    };
+    Some(tiled::Color {
+        red,
+        green,
+        blue,
+        alpha: 255,
+    })
+}
+
+#[derive(Default)]
+struct LevelLoader;
+
+#[derive(Debug, Error)]
+enum LevelLoaderError {
+    #[error("could not read level: {0}")]
+    Io(#[from] io::Error),
+    #[error("could not read tileset: {0}")]
+    Tileset(#[from] ReadAssetBytesError),
+    #[error("could not parse level: {0}")]
+    Xml(#[from] xml::reader::Error),
+    #[error("could not load level: {0}")]
+    Tiled(#[from] tiled::Error),
+}
+
+impl AssetLoader for LevelLoader {
+    type Asset = Level;
+    type Settings = ();
+    type Error = LevelLoaderError;
+
+    async fn load(
+        &self,
+        reader: &mut dyn Reader,
+        _settings: &(),
+        load_context: &mut LoadContext<'_>,
+    ) -> Result<Level, Self::Error> {
+        let mut bytes = Vec::new();
+        reader.read_to_end(&mut bytes).await?;
+
+        // `tiled` reads external tilesets itself, but only through a blocking
+        // reader, so fetch them up front.
+        let map_path = load_context.path().to_path_buf();
+        let directory = map_path.parent().unwrap_or(Path::new("")).to_path_buf();
+        let mut files = HashMap::new();
+        for source in tileset_sources(&bytes)? {
+            let path = directory.join(source);
+            let tileset = load_context.read_asset_bytes(path.clone()).await?;
+            files.insert(path, tileset);
+        }
+        files.insert(map_path.clone(), bytes);
+
+        let mut loader = tiled::Loader::with_cache_and_reader(
+            DefaultResourceCache::new(),
+            PrefetchedReader(files),
+        );
+        let map = loader.load_tmx_map(&map_path)?;
+        Ok(Level::from_map(&map))
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["tmx"]
+    }
+}
+
+#[derive(Default)]
+struct VoxelLevelLoader;
+
+impl AssetLoader for VoxelLevelLoader {
+    type Asset = Level;
+    type Settings = ();
+    type Error = MapError;
+
+    async fn load(
+        &self,
+        reader: &mut dyn Reader,
+        _settings: &(),
+        _load_context: &mut LoadContext<'_>,
+    ) -> Result<Level, Self::Error> {
+        let mut bytes = Vec::new();
+        reader.read_to_end(&mut bytes).await?;
+        Ok(Level::from_voxel_map(&VoxelMap::from_bytes(&bytes)?))
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["vmap"]
+    }
+}
+
+/// The `source` of every external tileset referenced by a map.
+fn tileset_sources(tmx: &[u8]) -> Result<Vec<String>, xml::reader::Error> {
+    let mut sources = Vec::new();
+    for event in EventReader::new(tmx) {
+        match event? {
+            XmlEvent::StartElement {
+                name, attributes, ..
+            } if name.local_name == "tileset" => {
+                sources.extend(
+                    attributes
+                        .into_iter()
+                        .filter(|attribute| attribute.name.local_name == "source")
+                        .map(|attribute| attribute.value),
+                );
+            }
+            _ => {}
+        }
+    }
+    Ok(sources)
+}
+
+/// Hands `tiled` the files fetched by [`LevelLoader`].
+struct PrefetchedReader(HashMap<PathBuf, Vec<u8>>);
+
+impl tiled::ResourceReader for PrefetchedReader {
+    type Resource = Cursor<Vec<u8>>;
+    type Error = io::Error;
+
+    fn read_from(&mut self, path: &Path) -> Result<Self::Resource, Self::Error> {
+        self.0.get(path).cloned().map(Cursor::new).ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("{} is not referenced by the map", path.display()),
+            )
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bevy::asset::{AssetPlugin, LoadState};
+
+    /// Loads `path` from the assets folder of the game.
+    fn load(path: &str) -> Level {
+        let mut app = App::new();
+        app.add_plugins((MinimalPlugins, AssetPlugin::default(), LevelPlugin));
+        let handle: Handle<Level> = app.world().resource::<AssetServer>().load(path);
+        for _ in 0..10_000 {
+            app.update();
+            if let Some(level) = app
+                .world_mut()
+                .resource_mut::<Assets<Level>>()
+                .remove(&handle)
+            {
+                return level;
+            }
+            let asset_server = app.world().resource::<AssetServer>();
+            if let LoadState::Failed(err) = asset_server.load_state(&handle) {
+                panic!("could not load {path}: {err}");
+            }
+            std::thread::sleep(std::time::Duration::from_millis(1));
+        }
+        panic!("{path} did not load");
+    }
+
+    #[test]
+    fn loads_tiled_maps() {
+        let level = load("levels/first.tmx");
+        assert_eq!((level.width, level.height), (32, 32));
+        assert_eq!(level.tiles.len(), 32 * 32);
+
+        let tile = |x: u32, y: u32| {
+            level
+                .tiles
+                .iter()
+                .find(|tile| tile.position == UVec2::new(x, y))
+                .unwrap()
+        };
+        // Tile types and properties come from the external tileset.
+        assert_eq!(tile(0, 0).kind.as_deref(), Some("grass"));
+        assert_eq!(tile(0, 0).elevation, 0.0);
+        assert_eq!(
+            tile(0, 0).properties.get_color("color"),
+            Some(Color::srgb_u8(0x7c, 0x90, 0xff))
+        );
+        assert_eq!(tile(16, 0).kind.as_deref(), Some("path"));
+        assert_eq!(tile(6, 21).kind.as_deref(), Some("water"));
+        assert_eq!(tile(6, 21).properties.get_bool("buildable"), Some(false));
+        assert_eq!(tile(0, 0).properties.get_bool("buildable"), None);
+
+        let kinds: Vec<_> = level
+            .objects
+            .iter()
+            .map(|object| (object.name.as_str(), object.kind.as_str()))
+            .collect();
+        assert_eq!(
+            kinds,
+            [
+                ("player", "spawn"),
+                ("starter farm", "farm"),
+                ("gold vein", "resource"),
+                ("berry bush", "resource"),
+            ]
+        );
+        let [spawn, farm, gold, _] = &level.objects[..] else {
+            unreachable!();
+        };
+        // Positions are in tiles.
+        assert_eq!(spawn.position, Vec2::new(16.5, 16.5));
+        assert_eq!(farm.properties.get_u32("rate"), Some(2));
+        assert_eq!(farm.properties.get_u32("capacity"), Some(200));
+        assert_eq!(gold.properties.get_str("resource"), Some("gold"));
+        assert_eq!(gold.properties.get_u32("amount"), Some(500));
+        assert_eq!(
+            gold.properties.get_color("color"),
+            Some(Color::srgb_u8(0xff, 0xd7, 0x00))
+        );
+    }
+}
@@ -0,0 +1,518 @@
+use crate::GameState;
+use crate::buildings::{Building, BuildingType, Cost};
+use crate::lua_api::{LuaContext, ScriptEvent};
+use crate::lua_sandbox::{LuaSandbox, ScriptUsage, out_of_memory};
+use crate::map::StorageFullEvent;
+use crate::tiles::TileClicked;
+use bevy::asset::{AssetLoadFailedEvent, AssetLoader, LoadContext, LoadedFolder, io::Reader};
+use bevy::prelude::*;
+use mlua::ChunkMode;
+use mlua::prelude::*;
+use std::cell::Cell;
+use std::io;
+use thiserror::Error;
+
+/// Where the game looks for scripts, inside the assets folder.
+const SCRIPTS_FOLDERS: [&str; 2] = ["scripts", "mods"];
+
+/// Runs the `.lua` scripts in `assets/scripts` and `assets/mods` in one
+/// long-lived Lua state.
+///
+/// Each script gets copies of the standard libraries and of `game` to keep
+/// its globals in, so that nothing it changes is seen by other scripts, and a
+/// `print` that logs. It may define hooks, which the game calls as things
+/// happen:
+///
+/// - `on_start()` once the script has loaded, or reloaded,
+/// - `on_tick(dt)` every frame of the game, with the seconds since the last,
+/// - `on_tile_clicked(x, y, z, id)` with the position and id of the tile,
+/// - `on_storage_full(x, y, z, id)` with the position and id of the building,
+/// - `on_event(name, value)` for each [`ScriptEvent`] sent the frame before.
+///
+/// Hooks can look at and change the game through the `game` table, see
+/// [`LuaContext::scope`]. A script that fails is logged and the game carries on.
+///
+/// A script declares building types in a global `buildings` list of tables
+/// with a `name`, and optionally a `title`, `mesh`, `color` as hex, `cost` as
+/// `{ gold = 10, food = 5 }`, generator `rate`, storage `capacity` and
+/// `resource`. Anything left out is as for a farm. A building type can also
+/// have `on_built(id, x, y, z)` and `on_full(id, x, y, z)` callbacks.
+///
+/// Scripts run in the [`LuaSandbox`] unless it is turned off, for scripts that
+/// are trusted. Its limits are for each script over all the hooks of a frame,
+/// and a script that goes over them is disabled.
+pub struct LuaPlugin {
+    pub sandbox: Option<LuaSandbox>,
+}
+
+impl Default for LuaPlugin {
+    fn default() -> Self {
+        LuaPlugin {
+            sandbox: Some(LuaSandbox::default()),
+        }
+    }
+}
+
+impl Plugin for LuaPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_non_send_resource(LuaRuntime::new(self.sandbox.as_ref()))
+            .init_asset::<LuaScript>()
+            .init_asset_loader::<LuaScriptLoader>()
+            .add_event::<LuaHook>()
+            .add_event::<ScriptEvent>()
+            .add_observer(tile_clicked_hook)
+            .add_systems(Startup, load_scripts)
+            .add_systems(
+                Update,
+                (
+                    run_scripts_system,
+                    tick_hook_system.run_if(in_state(GameState::Game)),
+                    storage_full_hook_system,
+                    script_event_hook_system,
+                    call_hooks_system,
+                )
+                    .chain(),
+            );
+    }
+}
+
+/// The source of a Lua script.
+#[derive(Asset, TypePath, Debug)]
+pub struct LuaScript {
+    pub source: String,
+}
+
+#[derive(Default)]
+struct LuaScriptLoader;
+
+#[derive(Debug, Error)]
+enum LuaScriptLoaderError {
+    #[error("could not read script: {0}")]
+    Io(#[from] io::Error),
+    #[error("script is not UTF-8: {0}")]
+    Utf8(#[from] std::string::FromUtf8Error),
+}
+
+impl AssetLoader for LuaScriptLoader {
+    type Asset = LuaScript;
+    type Settings = ();
+    type Error = LuaScriptLoaderError;
+
+    async fn load(
+        &self,
+        reader: &mut dyn Reader,
+        _settings: &(),
+        _load_context: &mut LoadContext<'_>,
+    ) -> Result<LuaScript, Self::Error> {
+        let mut bytes = Vec::new();
+        reader.read_to_end(&mut bytes).await?;
+        Ok(LuaScript {
+            source: String::from_utf8(bytes)?,
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["lua"]
+    }
+}
+
+/// Keeps the script folders loaded.
+#[derive(Resource)]
+struct LuaScripts(Vec<Handle<LoadedFolder>>);
+
+/// Something that happened in the game, passed on to the hook of the same
+/// name in every script.
+#[derive(Event, Debug, Clone)]
+pub enum LuaHook {
+    Start,
+    Tick(f32),
+    TileClicked(Entity, Vec3),
+    StorageFull(Entity, Vec3),
+    Event(ScriptEvent),
+    /// A callback in the declaration of a building type, called with the
+    /// building.
+    BuildingCallback {
+        kind: String,
+        callback: &'static str,
+        entity: Entity,
+        position: Vec3,
+    },
+}
+
+impl LuaHook {
+    pub fn name(&self) -> &'static str {
+        match self {
+            LuaHook::Start => "on_start",
+            LuaHook::Tick(_) => "on_tick",
+            LuaHook::TileClicked(..) => "on_tile_clicked",
+            LuaHook::StorageFull(..) => "on_storage_full",
+            LuaHook::Event(_) => "on_event",
+            LuaHook::BuildingCallback { callback, .. } => callback,
+        }
+    }
+}
+
+/// The Lua state the scripts run in. It is not `Send`, so it lives on the
+/// main thread as a non-send resource.
+pub struct LuaRuntime {
+    lua: Lua,
+    scripts: Vec<LoadedScript>,
+    sandbox: Option<LuaSandbox>,
+}
+
+struct LoadedScript {
+    id: AssetId<LuaScript>,
+    path: String,
+    /// The table the script's globals live in, see [`LuaRuntime::environment`].
+    environment: LuaRegistryKey,
+    /// What the script has used of the sandbox's limits this frame.
+    usage: Cell<ScriptUsage>,
+}
+
+impl LuaRuntime {
+    pub fn new(sandbox: Option<&LuaSandbox>) -> Self {
+        let lua = match sandbox {
+            Some(sandbox) => sandbox
+                .create_lua()
+                .expect("the sandbox only uses what Lua always has"),
+            None => Lua::new(),
+        };
+        LuaRuntime {
+            lua,
+            scripts: Vec::new(),
+            sandbox: sandbox.cloned(),
+        }
+    }
+
+    /// Gives every script its full budget again, before the hooks of a frame
+    /// are called.
+    pub fn start_frame(&mut self) {
+        for script in &self.scripts {
+            script.usage.take();
+        }
+    }
+
+    /// Runs a script in a fresh environment, replacing the one it ran in
+    /// before, and calls its `on_start`.
+    pub fn load(
+        &mut self,
+        id: AssetId<LuaScript>,
+        path: String,
+        source: &str,
+        context: &mut LuaContext,
+    ) {
+        self.unload(id, context);
+        let script = context.scope(&self.lua, || {
+            // Running the script counts towards its budget, as its `on_start` does.
+            self.lua.set_app_data(ScriptUsage::default());
+            let environment = self.environment(&path)?;
+            self.lua
+                .load(source)
+                .set_name(format!("@{path}"))
+                .set_mode(ChunkMode::Text)
+                .set_environment(environment.clone())
+                .exec()?;
+            let buildings = building_types(&environment)?;
+            let script = LoadedScript {
+                id,
+                path: path.clone(),
+                environment: self.lua.create_registry_value(environment)?,
+                usage: Cell::default(),
+            };
+            Ok((script, buildings))
+        });
+        let usage: ScriptUsage = self.lua.remove_app_data().unwrap_or_default();
+        match script {
+            Ok((script, buildings)) => {
+                script.usage.set(usage);
+                context.replace_building_types(id, buildings);
+                let started =
+                    context.scope(&self.lua, || Ok(self.call_script(&script, &LuaHook::Start)));
+                match started {
+                    Ok(false) => context.replace_building_types(id, Vec::new()),
+                    Ok(true) => self.scripts.push(script),
+                    Err(err) => {
+                        error!("Could not start {path}: {err}");
+                        self.scripts.push(script);
+                    }
+                }
+            }
+            Err(err) => error!("Could not run {path}: {err}"),
+        }
+    }
+
+    /// A table for the globals of a script, with copies of the shared globals
+    /// and the libraries in them. `game` is copied in again before each call,
+    /// as it changes from one call to the next.
+    fn environment(&self, path: &str) -> LuaResult<LuaTable> {
+        let environment = self.lua.create_table()?;
+        for pair in self.lua.globals().pairs::<LuaValue, LuaValue>() {
+            let (name, value) = pair?;
+            let value = match value {
+                LuaValue::Table(table) => LuaValue::Table(copy_table(&self.lua, &table)?),
+                value => value,
+            };
+            environment.raw_set(name, value)?;
+        }
+        environment.raw_set("_G", environment.clone())?;
+        let path = path.to_string();
+        let print = self.lua.create_function(move |_, values: LuaMultiValue| {
+            let values = values
+                .iter()
+                .map(LuaValue::to_string)
+                .collect::<LuaResult<Vec<_>>>()?;
+            info!("{path}: {}", values.join("\t"));
+            Ok(())
+        })?;
+        environment.raw_set("print", print)?;
+        Ok(environment)
+    }
+
+    /// Forgets a script and the building types it declared.
+    pub fn unload(&mut self, id: AssetId<LuaScript>, context: &mut LuaContext) {
+        self.scripts.retain(|script| script.id != id);
+        context.replace_building_types(id, Vec::new());
+    }
+
+    /// Calls the hook in every script that defines it. Building callbacks
+    /// only go to the script that declared the building type.
+    pub fn call(&mut self, hook: &LuaHook, context: &mut LuaContext) {
+        let declared_by = match hook {
+            LuaHook::BuildingCallback { kind, .. } => match context.declared_by(kind) {
+                Some(script) => Some(script),
+                None => return,
+            },
+            _ => None,
+        };
+        let mut disabled = Vec::new();
+        let result = context.scope(&self.lua, || {
+            let scripts = self
+                .scripts
+                .iter()
+                .filter(|script| declared_by.is_none_or(|id| script.id == id));
+            for script in scripts {
+                if !self.call_script(script, hook) {
+                    disabled.push(script.id);
+                }
+            }
+            Ok(())
+        });
+        if let Err(err) = result {
+            error!("Could not call {}: {err}", hook.name());
+        }
+        for id in disabled {
+            self.unload(id, context);
+        }
+    }
+
+    /// Calls the hook in one script, charging it to what the script has used
+    /// this frame. Returns `false` if the script went over the limits of the
+    /// sandbox and has to be disabled.
+    fn call_script(&self, script: &LoadedScript, hook: &LuaHook) -> bool {
+        self.lua.set_app_data(script.usage.get());
+        let result = self.try_call_script(script, hook);
+        let usage = self.lua.remove_app_data().unwrap_or_default();
+        script.usage.set(usage);
+        let exceeded = self
+            .sandbox
+            .as_ref()
+            .is_some_and(|sandbox| sandbox.exceeded(&usage))
+            || result.as_ref().is_err_and(out_of_memory);
+        if let Err(err) = result {
+            error!("{} failed in {}: {err}", hook.name(), script.path);
+        }
+        if exceeded {
+            error!("Disabling {} for going over its limits", script.path);
+        }
+        !exceeded
+    }
+
+    fn try_call_script(&self, script: &LoadedScript, hook: &LuaHook) -> LuaResult<()> {
+        let environment: LuaTable = self.lua.registry_value(&script.environment)?;
+        let mut function: Option<LuaFunction> = None;
+        if let LuaHook::BuildingCallback { kind, callback, .. } = hook {
+            let buildings: Option<LuaTable> = environment.raw_get("buildings")?;
+            if let Some(buildings) = buildings {
+                for definition in buildings.sequence_values() {
+                    let definition: LuaTable = definition?;
+                    let name: String = definition.get("name")?;
+                    if name == *kind {
+                        function = definition.get(*callback)?;
+                    }
+                }
+            }
+        } else {
+            function = environment.raw_get(hook.name())?;
+        }
+        let Some(function) = function else {
+            return Ok(());
+        };
+        let game: Option<LuaTable> = self.lua.globals().raw_get("game")?;
+        if let Some(game) = game {
+            environment.raw_set("game", copy_table(&self.lua, &game)?)?;
+        }
+        match hook {
+            LuaHook::Start => function.call(()),
+            LuaHook::Tick(dt) => function.call(*dt),
+            LuaHook::TileClicked(entity, position) | LuaHook::StorageFull(entity, position) => {
+                function.call((position.x, position.y, position.z, entity.to_bits()))
+            }
+            LuaHook::Event(event) => function.call((event.name.clone(), event.value.clone())),
+            LuaHook::BuildingCallback {
+                entity, position, ..
+            } => function.call((entity.to_bits(), position.x, position.y, position.z)),
+        }
+    }
+}
+
+/// A table with the same fields as `table`, but not its metatable.
+fn copy_table(lua: &Lua, table: &LuaTable) -> LuaResult<LuaTable> {
+    let copy = lua.create_table()?;
+    for pair in table.pairs::<LuaValue, LuaValue>() {
+        let (key, value) = pair?;
+        copy.raw_set(key, value)?;
+    }
+    Ok(copy)
+}
+
+/// Reads the building types a script declares in its `buildings` list.
+fn building_types(environment: &LuaTable) -> LuaResult<Vec<BuildingType>> {
+    let buildings: Option<LuaTable> = environment.raw_get("buildings")?;
+    let Some(buildings) = buildings else {
+        return Ok(Vec::new());
+    };
+    let mut types = Vec::new();
+    for definition in buildings.sequence_values() {
+        let definition: LuaTable = definition?;
+        let farm = BuildingType::default();
+        let name: String = definition.get("name")?;
+        let title: Option<String> = definition.get("title")?;
+        let mesh: Option<String> = definition.get("mesh")?;
+        let color: Option<String> = definition.get("color")?;
+        let color = match color {
+            Some(color) => Srgba::hex(&color)
+                .map_err(|err| LuaError::RuntimeError(format!("color of {name}: {err}")))?
+                .into(),
+            None => farm.color,
+        };
+        let cost: Option<LuaTable> = definition.get("cost")?;
+        let cost = match cost {
+            Some(cost) => {
+                let gold: Option<u32> = cost.get("gold")?;
+                let food: Option<u32> = cost.get("food")?;
+                Cost {
+                    gold: gold.unwrap_or(0),
+                    food: food.unwrap_or(0),
+                }
+            }
+            None => farm.cost,
+        };
+        let rate: Option<u32> = definition.get("rate")?;
+        let capacity: Option<u32> = definition.get("capacity")?;
+        let resource: Option<String> = definition.get("resource")?;
+        types.push(BuildingType {
+            name,
+            title,
+            mesh: mesh.unwrap_or(farm.mesh),
+            color,
+            cost,
+            rate: rate.unwrap_or(farm.rate),
+            capacity: capacity.unwrap_or(farm.capacity),
+            resource: resource.unwrap_or(farm.resource),
+        });
+    }
+    Ok(types)
+}
+
+fn load_scripts(mut commands: Commands, asset_server: Res<AssetServer>) {
+    let folders = SCRIPTS_FOLDERS.map(|folder| asset_server.load_folder(folder));
+    commands.insert_resource(LuaScripts(folders.into()));
+}
+
+/// Runs scripts as they load, and again whenever they change on disk.
+fn run_scripts_system(
+    folders: Res<LuaScripts>,
+    mut failed: EventReader<AssetLoadFailedEvent<LoadedFolder>>,
+    mut events: EventReader<AssetEvent<LuaScript>>,
+    scripts: Res<Assets<LuaScript>>,
+    asset_server: Res<AssetServer>,
+    mut runtime: NonSendMut<LuaRuntime>,
+    mut context: LuaContext,
+) {
+    for failure in failed.read() {
+        if folders.0.iter().any(|folder| folder.id() == failure.id) {
+            warn!("Could not load scripts: {}", failure.error);
+        }
+    }
+    for event in events.read() {
+        match *event {
+            AssetEvent::Added { id } | AssetEvent::Modified { id } => {
+                let Some(script) = scripts.get(id) else {
+                    continue;
+                };
+                let path = asset_server
+                    .get_path(id)
+                    .map(|path| path.to_string())
+                    .unwrap_or_else(|| id.to_string());
+                runtime.load(id, path, &script.source, &mut context);
+            }
+            AssetEvent::Removed { id } => runtime.unload(id, &mut context),
+            _ => {}
+        }
+    }
+}
+
+fn tick_hook_system(time: Res<Time>, mut hooks: EventWriter<LuaHook>) {
+    hooks.write(LuaHook::Tick(time.delta_secs()));
+}
+
+fn tile_clicked_hook(
+    clicked: Trigger<TileClicked>,
+    tiles: Query<&Transform>,
+    mut hooks: EventWriter<LuaHook>,
+) {
+    if let Ok(transform) = tiles.get(clicked.target()) {
+        hooks.write(LuaHook::TileClicked(
+            clicked.target(),
+            transform.translation,
+        ));
+    }
+}
+
+fn storage_full_hook_system(
+    mut events: EventReader<StorageFullEvent>,
+    buildings: Query<(&Transform, Option<&Building>)>,
+    mut hooks: EventWriter<LuaHook>,
+) {
+    for StorageFullEvent(entity) in events.read() {
+        let Ok((transform, building)) = buildings.get(*entity) else {
+            continue;
+        };
+        hooks.write(LuaHook::StorageFull(*entity, transform.translation));
+        if let Some(building) = building {
+            hooks.write(LuaHook::BuildingCallback {
+                kind: building.kind.clone(),
+                callback: "on_full",
+                entity: *entity,
+                position: transform.translation,
+            });
+        }
+    }
+}
+
+fn script_event_hook_system(mut events: EventReader<ScriptEvent>, mut hooks: EventWriter<LuaHook>) {
+    for event in events.read() {
+        hooks.write(LuaHook::Event(event.clone()));
+    }
+}
+
+fn call_hooks_system(
+    mut hooks: EventReader<LuaHook>,
+    mut runtime: NonSendMut<LuaRuntime>,
+    mut context: LuaContext,
+) {
+    runtime.start_frame();
+    for hook in hooks.read() {
+        runtime.call(hook, &mut context);
+    }
+}
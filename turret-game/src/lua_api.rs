@@ -0,0 +1,575 @@
+use crate::buildings::{Building, BuildingType, BuildingTypes, spawn_building};
+use crate::lua::LuaScript;
+use crate::lua_sandbox::count_event;
+use crate::map::{Generator, ResourceNode, Storage};
+use crate::tiles::{Palette, Tile};
+use crate::ui::PlayerResources;
+use bevy::ecs::system::SystemParam;
+use bevy::prelude::*;
+use mlua::prelude::*;
+use std::cell::RefCell;
+
+/// Sent by scripts with `game.emit(name, value)`, and passed on to the
+/// `on_event` hook of every script the frame after.
+#[derive(Event, Debug, Clone, PartialEq)]
+pub struct ScriptEvent {
+    pub name: String,
+    pub value: Option<String>,
+}
+
+type BuildingItem = (
+    Entity,
+    &'static Building,
+    &'static Transform,
+    Option<&'static Storage>,
+    Option<&'static Generator>,
+);
+
+/// What scripts can see of the game and change, through the `game` table.
+///
+/// Entities are passed to Lua as integer ids. Changes go through
+/// [`Commands`], so they take effect after the hook returns rather than
+/// while the script is reading.
+#[derive(SystemParam)]
+pub struct LuaContext<'w, 's> {
+    commands: Commands<'w, 's>,
+    players: Query<'w, 's, (Entity, &'static PlayerResources)>,
+    buildings: Query<'w, 's, BuildingItem>,
+    tiles: Query<'w, 's, (Entity, &'static Transform), With<Tile>>,
+    resource_nodes: Query<'w, 's, (Entity, &'static ResourceNode, &'static Transform)>,
+    building_types: ResMut<'w, BuildingTypes>,
+    asset_server: Res<'w, AssetServer>,
+    palette: Palette<'w>,
+    events: EventWriter<'w, ScriptEvent>,
+}
+
+/// A building as scripts see it.
+struct BuildingInfo {
+    entity: Entity,
+    kind: String,
+    position: Vec3,
+    storage: Option<(u32, u32)>,
+    rate: Option<u32>,
+}
+
+impl BuildingInfo {
+    fn new(
+        (entity, building, transform, storage, generator): (
+            Entity,
+            &Building,
+            &Transform,
+            Option<&Storage>,
+            Option<&Generator>,
+        ),
+    ) -> Self {
+        BuildingInfo {
+            entity,
+            kind: building.kind.clone(),
+            position: transform.translation,
+            storage: storage.map(|storage| (storage.amount, storage.capacity)),
+            rate: generator.map(|generator| generator.rate),
+        }
+    }
+
+    fn into_table(self, lua: &Lua) -> LuaResult<LuaTable> {
+        let table = lua.create_table()?;
+        table.set("id", self.entity.to_bits())?;
+        table.set("kind", self.kind)?;
+        table.set("x", self.position.x)?;
+        table.set("y", self.position.y)?;
+        table.set("z", self.position.z)?;
+        if let Some((amount, capacity)) = self.storage {
+            table.set("amount", amount)?;
+            table.set("capacity", capacity)?;
+        }
+        table.set("rate", self.rate)?;
+        Ok(table)
+    }
+}
+
+fn entity(id: u64) -> LuaResult<Entity> {
+    Entity::try_from_bits(id).map_err(|_| LuaError::RuntimeError(format!("{id} is not an entity")))
+}
+
+impl LuaContext<'_, '_> {
+    /// Replaces the building types declared by `script`.
+    pub fn replace_building_types(
+        &mut self,
+        script: AssetId<LuaScript>,
+        buildings: Vec<BuildingType>,
+    ) {
+        self.building_types.replace(script, buildings);
+    }
+
+    /// The script that declared the building type named `kind`.
+    pub fn declared_by(&self, kind: &str) -> Option<AssetId<LuaScript>> {
+        self.building_types.declared_by(kind)
+    }
+
+    /// Runs `f` with the `game` table set up for this context, and returns
+    /// what it returns.
+    ///
+    /// `game.resources()` returns the player's `gold` and `food`, and
+    /// `game.add_resources(gold, food)` adds to them, or takes away when
+    /// negative. `game.buildings()` and `game.building(id)` return buildings
+    /// with their `id`, `kind`, position and their `amount`, `capacity` and
+    /// `rate` where they store or produce. `game.tiles()` returns the `id`
+    /// and position of every tile, and `game.resource_nodes()` those of every
+    /// deposit with the `kind` and `amount` it can be harvested for.
+    ///
+    /// `game.spawn_building(kind, x, y, z)` spawns a building of any declared
+    /// type for free and returns its id, `game.despawn(id)` removes one, and
+    /// `game.set_storage(id, amount, capacity)` and
+    /// `game.set_generator(id, rate)` change them.
+    /// `game.emit(name, value)` sends a [`ScriptEvent`], as many each frame as
+    /// the [`LuaSandbox`](crate::lua_sandbox::LuaSandbox) allows.
+    pub fn scope<R>(&mut self, lua: &Lua, f: impl FnOnce() -> LuaResult<R>) -> LuaResult<R> {
+        let context = RefCell::new(self);
+        lua.scope(|scope| {
+            let game = lua.create_table()?;
+
+            let resources = scope.create_function(|lua, ()| {
+                let context = context.borrow();
+                let table = lua.create_table()?;
+                if let Some((_, resources)) = context.players.iter().next() {
+                    table.set("gold", resources.gold)?;
+                    table.set("food", resources.food)?;
+                }
+                Ok(table)
+            })?;
+            game.set("resources", resources)?;
+
+            let add_resources = scope.create_function(|_, (gold, food): (i64, Option<i64>)| {
+                let mut context = context.borrow_mut();
+                let Some((player, _)) = context.players.iter().next() else {
+                    return Ok(());
+                };
+                let food = food.unwrap_or(0);
+                context
+                    .commands
+                    .entity(player)
+                    .entry::<PlayerResources>()
+                    .and_modify(move |mut resources| {
+                        resources.gold =
+                            (resources.gold as i64 + gold).clamp(0, u32::MAX as i64) as u32;
+                        resources.food =
+                            (resources.food as i64 + food).clamp(0, u32::MAX as i64) as u32;
+                    });
+                Ok(())
+            })?;
+            game.set("add_resources", add_resources)?;
+
+            let buildings = scope.create_function(|lua, ()| {
+                let context = context.borrow();
+                let table = lua.create_table()?;
+                for building in &context.buildings {
+                    table.push(BuildingInfo::new(building).into_table(lua)?)?;
+                }
+                Ok(table)
+            })?;
+            game.set("buildings", buildings)?;
+
+            let building = scope.create_function(|lua, id: u64| {
+                let context = context.borrow();
+                match context.buildings.get(entity(id)?) {
+                    Ok(building) => BuildingInfo::new(building).into_table(lua).map(Some),
+                    Err(_) => Ok(None),
+                }
+            })?;
+            game.set("building", building)?;
+
+            let tiles = scope.create_function(|lua, ()| {
+                let context = context.borrow();
+                let table = lua.create_table()?;
+                for (tile, transform) in &context.tiles {
+                    let position = transform.translation;
+                    let entry = lua.create_table()?;
+                    entry.set("id", tile.to_bits())?;
+                    entry.set("x", position.x)?;
+                    entry.set("y", position.y)?;
+                    entry.set("z", position.z)?;
+                    table.push(entry)?;
+                }
+                Ok(table)
+            })?;
+            game.set("tiles", tiles)?;
+
+            let resource_nodes = scope.create_function(|lua, ()| {
+                let context = context.borrow();
+                let table = lua.create_table()?;
+                for (node, resource, transform) in &context.resource_nodes {
+                    let position = transform.translation;
+                    let entry = lua.create_table()?;
+                    entry.set("id", node.to_bits())?;
+                    entry.set("kind", resource.kind.as_str())?;
+                    entry.set("amount", resource.amount)?;
+                    entry.set("x", position.x)?;
+                    entry.set("y", position.y)?;
+                    entry.set("z", position.z)?;
+                    table.push(entry)?;
+                }
+                Ok(table)
+            })?;
+            game.set("resource_nodes", resource_nodes)?;
+
+            let spawn_building =
+                scope.create_function(|_, (kind, x, y, z): (String, f32, f32, f32)| {
+                    let context = &mut **context.borrow_mut();
+                    let Some(building) = context.building_types.get(&kind) else {
+                        return Err(LuaError::RuntimeError(format!("unknown building `{kind}`")));
+                    };
+                    let building = spawn_building(
+                        &mut context.commands,
+                        &context.asset_server,
+                        &mut context.palette,
+                        building,
+                        Vec3::new(x, y, z),
+                    );
+                    Ok(building.to_bits())
+                })?;
+            game.set("spawn_building", spawn_building)?;
+
+            let despawn = scope.create_function(|_, id: u64| {
+                let mut context = context.borrow_mut();
+                let building = entity(id)?;
+                if !context.buildings.contains(building) {
+                    return Err(LuaError::RuntimeError(format!("{id} is not a building")));
+                }
+                context.commands.entity(building).despawn();
+                Ok(())
+            })?;
+            game.set("despawn", despawn)?;
+
+            let set_storage =
+                scope.create_function(|_, (id, amount, capacity): (u64, u32, Option<u32>)| {
+                    let mut context = context.borrow_mut();
+                    let building = entity(id)?;
+                    if !matches!(context.buildings.get(building), Ok((_, _, _, Some(_), _))) {
+                        return Err(LuaError::RuntimeError(format!("{id} has no storage")));
+                    }
+                    context
+                        .commands
+                        .entity(building)
+                        .entry::<Storage>()
+                        .and_modify(move |mut storage| {
+                            storage.capacity = capacity.unwrap_or(storage.capacity);
+                            storage.amount = amount.min(storage.capacity);
+                        });
+                    Ok(())
+                })?;
+            game.set("set_storage", set_storage)?;
+
+            let set_generator = scope.create_function(|_, (id, rate): (u64, u32)| {
+                let mut context = context.borrow_mut();
+                let building = entity(id)?;
+                if !matches!(context.buildings.get(building), Ok((_, _, _, _, Some(_)))) {
+                    return Err(LuaError::RuntimeError(format!("{id} has no generator")));
+                }
+                context
+                    .commands
+                    .entity(building)
+                    .entry::<Generator>()
+                    .and_modify(move |mut generator| generator.rate = rate);
+                Ok(())
+            })?;
+            game.set("set_generator", set_generator)?;
+
+            let emit = scope.create_function(|lua, (name, value): (String, Option<String>)| {
+                count_event(lua)?;
+                context
+                    .borrow_mut()
+                    .events
+                    .write(ScriptEvent { name, value });
+                Ok(())
+            })?;
+            game.set("emit", emit)?;
+
+            lua.globals().set("game", game)?;
+            f()
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::buildings::BuildingPlugin;
+    use crate::lua::{LuaHook, LuaPlugin};
+    use crate::lua_sandbox::LuaSandbox;
+    use crate::map::{StorageFullEvent, generator_system};
+    use crate::tiles::MaterialPalette;
+    use bevy::asset::AssetPlugin;
+    use bevy::asset::uuid::Uuid;
+
+    /// A game without a level or the scripts in the assets folder, where the
+    /// player has 100 gold and food and `sources` are the only scripts.
+    fn app_with_scripts(sources: &[&str]) -> App {
+        let mut app = App::new();
+        app.add_plugins((
+            MinimalPlugins,
+            AssetPlugin {
+                // Missing, so that only `sources` are loaded.
+                file_path: "no_assets".to_string(),
+                ..default()
+            },
+            LuaPlugin::default(),
+            BuildingPlugin,
+        ))
+        .init_asset::<Mesh>()
+        .init_asset::<StandardMaterial>()
+        .init_resource::<MaterialPalette>()
+        .add_event::<StorageFullEvent>();
+        app.world_mut().spawn(PlayerResources {
+            gold: 100,
+            food: 100,
+        });
+        // Added under ids of their own rather than with handles, which would
+        // unload them once dropped.
+        let mut scripts = app.world_mut().resource_mut::<Assets<LuaScript>>();
+        for (index, source) in sources.iter().enumerate() {
+            scripts.insert(
+                Uuid::from_u128(index as u128 + 1),
+                LuaScript {
+                    source: source.to_string(),
+                },
+            );
+        }
+        // Loaded the frame after they are added, and the events they send
+        // are handled the frame after that.
+        for _ in 0..3 {
+            app.update();
+        }
+        app
+    }
+
+    fn player(app: &mut App) -> (u32, u32) {
+        let mut players = app.world_mut().query::<&PlayerResources>();
+        let resources = players.single(app.world()).unwrap();
+        (resources.gold, resources.food)
+    }
+
+    #[test]
+    fn scripts_change_the_game() {
+        let mut app = app_with_scripts(&[r#"
+            buildings = { { name = "mill", rate = 3, capacity = 50, resource = "gold" } }
+
+            function on_start()
+                game.add_resources(10, -5)
+                mill = game.spawn_building("mill", 1, 0, 2)
+                shed = game.spawn_building("mill", 4, 0, 2)
+                game.emit("spawned", tostring(mill))
+            end
+
+            function on_event(name, value)
+                if name == "spawned" and value == tostring(mill) then
+                    game.set_storage(mill, 20, 40)
+                    game.set_generator(mill, 7)
+                    game.despawn(shed)
+                end
+            end
+            "#]);
+
+        assert_eq!(player(&mut app), (110, 95));
+        let mut buildings = app
+            .world_mut()
+            .query::<(&Building, &Transform, &Storage, &Generator)>();
+        let buildings: Vec<_> = buildings.iter(app.world()).collect();
+        let [(building, transform, storage, generator)] = buildings[..] else {
+            panic!(
+                "expected only the mill, found {} buildings",
+                buildings.len()
+            );
+        };
+        assert_eq!(building.kind, "mill");
+        assert_eq!(transform.translation, Vec3::new(1.0, 0.0, 2.0));
+        assert_eq!(
+            (storage.amount, storage.capacity, storage.resource.as_str()),
+            (20, 40, "gold")
+        );
+        assert_eq!(generator.rate, 7);
+    }
+
+    /// Runs the generators for a few frames, long enough apart to produce.
+    fn generate(app: &mut App) {
+        app.add_systems(Update, generator_system);
+        for _ in 0..3 {
+            std::thread::sleep(std::time::Duration::from_millis(2));
+            app.update();
+        }
+    }
+
+    fn storage(app: &mut App) -> (u32, u32) {
+        let mut storages = app.world_mut().query::<&Storage>();
+        let storage = storages.single(app.world()).unwrap();
+        (storage.amount, storage.capacity)
+    }
+
+    #[test]
+    fn huge_rates_fill_storage_without_overflowing() {
+        let mut app = app_with_scripts(&[r#"
+            buildings = { { name = "mill", resource = "gold" } }
+
+            function on_start()
+                mill = game.spawn_building("mill", 0, 0, 0)
+                game.emit("spawned")
+            end
+
+            function on_event()
+                game.set_storage(mill, 4294967290, 4294967295)
+                game.set_generator(mill, 4294967295)
+            end
+            "#]);
+        generate(&mut app);
+
+        assert_eq!(storage(&mut app), (u32::MAX, u32::MAX));
+    }
+
+    #[test]
+    fn declared_rates_fill_storage_without_overflowing() {
+        let mut app = app_with_scripts(&[r#"
+            buildings = {
+                { name = "mill", rate = 4294967295, capacity = 4294967295 },
+            }
+
+            function on_start()
+                game.spawn_building("mill", 0, 0, 0)
+            end
+            "#]);
+        generate(&mut app);
+
+        assert_eq!(storage(&mut app), (u32::MAX, u32::MAX));
+    }
+
+    #[test]
+    fn scripts_see_resource_nodes() {
+        let mut app = app_with_scripts(&[r#"
+            function on_event(name)
+                for _, node in ipairs(game.resource_nodes()) do
+                    if node.kind == "gold" and node.x == 3 then
+                        game.add_resources(node.amount)
+                    end
+                end
+            end
+            "#]);
+        for (kind, x) in [("gold", 3.0), ("food", 3.0), ("gold", 4.0)] {
+            app.world_mut().spawn((
+                ResourceNode {
+                    kind: kind.to_string(),
+                    amount: 25,
+                },
+                Transform::from_xyz(x, 0.0, 1.0),
+            ));
+        }
+        app.world_mut().send_event(ScriptEvent {
+            name: "count".to_string(),
+            value: None,
+        });
+        app.update();
+
+        assert_eq!(player(&mut app), (125, 100));
+    }
+
+    #[test]
+    fn failing_calls_leave_the_game_as_it_was() {
+        let mut app = app_with_scripts(&[r#"
+            function on_start()
+                game.add_resources(1)
+                game.spawn_building("castle", 0, 0, 0)
+                game.add_resources(1)
+            end
+            "#]);
+
+        // Stops at the unknown building, and what came before still counts.
+        assert_eq!(player(&mut app), (101, 100));
+        let mut buildings = app.world_mut().query::<&Building>();
+        assert_eq!(buildings.iter(app.world()).count(), 0);
+    }
+
+    #[test]
+    fn building_callbacks_go_to_the_declaring_script() {
+        let mill = r#"
+            buildings = { { name = "mill", on_built = function() game.add_resources(1) end } }
+        "#;
+        let other_mill = r#"
+            buildings = { { name = "mill", on_built = function() game.add_resources(0, 10) end } }
+        "#;
+        let mut app = app_with_scripts(&[mill, other_mill]);
+        app.world_mut().send_event(LuaHook::BuildingCallback {
+            kind: "mill".to_string(),
+            callback: "on_built",
+            entity: Entity::PLACEHOLDER,
+            position: Vec3::ZERO,
+        });
+        app.update();
+
+        // The second script declared the mill last, replacing the first one's.
+        assert_eq!(player(&mut app), (100, 110));
+    }
+
+    #[test]
+    fn scripts_cannot_change_what_others_see() {
+        let meddler = r#"
+            local function meddle()
+                string.upper = nil
+                table.insert = nil
+                math.pi = 3
+                print = nil
+                shared = true
+                game.add_resources = nil
+                rawset(game, "resources", nil)
+                setmetatable(_G, { __index = function() return 0 end })
+                local strings = getmetatable("")
+                if strings then
+                    strings.__index = {}
+                end
+            end
+
+            function on_start()
+                meddle()
+                game.emit("meddled")
+            end
+
+            on_event = meddle
+        "#;
+        let onlooker = r#"
+            function on_event()
+                local untouched = string.upper("a") == "A"
+                    and ("a"):upper() == "A"
+                    and table.insert ~= nil
+                    and math.pi > 3.14
+                    and print ~= nil
+                    and shared == nil
+                    and game.resources ~= nil
+                if untouched then
+                    game.add_resources(1, 0)
+                end
+            end
+        "#;
+        let mut app = app_with_scripts(&[meddler, onlooker]);
+
+        assert_eq!(player(&mut app), (101, 100));
+    }
+
+    #[test]
+    fn scripts_cannot_flood_the_event_queue() {
+        let mut app = app_with_scripts(&[r#"
+            function on_start() game.emit("ping") end
+            function on_event(name) game.emit(name) game.emit(name) end
+            "#]);
+        // Without a limit, the events would double every frame.
+        for _ in 0..10 {
+            app.update();
+        }
+
+        let mut sent = app
+            .world()
+            .resource::<Events<ScriptEvent>>()
+            .get_cursor_current();
+        app.update();
+        let events = app.world().resource::<Events<ScriptEvent>>();
+        assert_eq!(
+            sent.read(events).count(),
+            LuaSandbox::default().event_limit as usize
+        );
+    }
+}
@@ -0,0 +1,230 @@
+use mlua::prelude::*;
+use mlua::{HookTriggers, VmState};
+
+/// How often the instruction count is checked.
+const INSTRUCTIONS_PER_CHECK: u32 = 1000;
+
+/// Limits scripts so that a broken or hostile mod cannot reach outside the
+/// game or hang it.
+///
+/// Sandboxed scripts only get the `string`, `table`, `math`, `utf8` and
+/// `coroutine` libraries, and the clock and date functions of `os`. There is
+/// no `io`, `debug`, `require` or loading of code or files at run time, and
+/// the metatable strings share is out of reach.
+///
+/// Instructions are only counted in Lua code. Library functions run in C as
+/// one instruction however long they take, so a pattern that backtracks a
+/// lot in `string.find`, `string.match` or `string.gsub` can still hold up
+/// the game. `string.rep` and the like are held back by the memory limit.
+#[derive(Debug, Clone)]
+pub struct LuaSandbox {
+    /// The most memory all scripts together may use, in bytes. They share one
+    /// Lua state, so a script that uses a lot leaves less for the others.
+    pub memory_limit: usize,
+    /// The most instructions a script may run each frame, over all the hooks
+    /// the game calls in it.
+    pub instruction_limit: u64,
+    /// The most events a script may send with `game.emit` each frame.
+    pub event_limit: u32,
+}
+
+impl Default for LuaSandbox {
+    fn default() -> Self {
+        LuaSandbox {
+            memory_limit: 64 * 1024 * 1024,
+            instruction_limit: 1_000_000,
+            event_limit: 100,
+        }
+    }
+}
+
+/// What a script has used of its limits this frame. The one for the script
+/// running now is kept in the app data of the Lua state.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ScriptUsage {
+    pub instructions: u64,
+    pub events: u32,
+}
+
+impl LuaSandbox {
+    /// A Lua state with the safe parts of the standard library and these
+    /// limits in place.
+    pub fn create_lua(&self) -> LuaResult<Lua> {
+        let libraries = LuaStdLib::STRING
+            | LuaStdLib::TABLE
+            | LuaStdLib::MATH
+            | LuaStdLib::UTF8
+            | LuaStdLib::COROUTINE
+            | LuaStdLib::OS;
+        let lua = Lua::new_with(libraries, LuaOptions::new())?;
+        restrict(&lua)?;
+        count_instructions(&lua, INSTRUCTIONS_PER_CHECK, self.instruction_limit)?;
+        lua.set_memory_limit(self.memory_limit)?;
+        lua.set_app_data(self.clone());
+        Ok(lua)
+    }
+
+    /// Whether a script that used `usage` has to be disabled.
+    pub fn exceeded(&self, usage: &ScriptUsage) -> bool {
+        usage.instructions > self.instruction_limit
+    }
+}
+
+/// Takes away what sandboxed scripts may not use.
+fn restrict(lua: &Lua) -> LuaResult<()> {
+    let globals = lua.globals();
+    for name in ["dofile", "loadfile", "load", "collectgarbage"] {
+        globals.raw_set(name, LuaNil)?;
+    }
+    let os: LuaTable = globals.get("os")?;
+    let safe_os = lua.create_table()?;
+    for name in ["clock", "date", "difftime", "time"] {
+        let function: LuaFunction = os.get(name)?;
+        safe_os.set(name, function)?;
+    }
+    globals.set("os", safe_os)?;
+    // `getmetatable("")` would hand out the `__index` every string shares.
+    lua.load(r#"getmetatable("").__metatable = false"#).exec()
+}
+
+/// Counts instructions against the script running now, every `step` of them.
+/// Once it is over `limit` the hook fails on every instruction, so that the
+/// script cannot keep going by catching the error with `pcall`. Coroutines
+/// the script creates take on the hook of the one creating them.
+fn count_instructions(lua: &Lua, step: u32, limit: u64) -> LuaResult<()> {
+    let triggers = HookTriggers::new().every_nth_instruction(step);
+    lua.set_global_hook(triggers, move |lua, _| {
+        let used = match lua.app_data_mut::<ScriptUsage>() {
+            Some(mut usage) => {
+                usage.instructions += u64::from(step);
+                usage.instructions
+            }
+            None => 0,
+        };
+        let exceeded = used > limit;
+        if exceeded != (step == 1) {
+            let step = if exceeded { 1 } else { INSTRUCTIONS_PER_CHECK };
+            count_instructions(lua, step, limit)?;
+        }
+        if exceeded {
+            return Err(LuaError::RuntimeError(format!(
+                "ran more than {limit} instructions this frame"
+            )));
+        }
+        Ok(VmState::Continue)
+    })
+}
+
+/// Counts an event sent by the script running now, and fails once it has
+/// sent more this frame than the sandbox allows.
+pub fn count_event(lua: &Lua) -> LuaResult<()> {
+    let Some(event_limit) = lua
+        .app_data_ref::<LuaSandbox>()
+        .map(|sandbox| sandbox.event_limit)
+    else {
+        return Ok(());
+    };
+    let Some(mut usage) = lua.app_data_mut::<ScriptUsage>() else {
+        return Ok(());
+    };
+    if usage.events >= event_limit {
+        return Err(LuaError::RuntimeError(format!(
+            "sent more than {event_limit} events this frame"
+        )));
+    }
+    usage.events += 1;
+    Ok(())
+}
+
+/// Whether the script ran out of the memory the sandbox allows.
+pub fn out_of_memory(err: &LuaError) -> bool {
+    match err {
+        LuaError::MemoryError(_) => true,
+        LuaError::CallbackError { cause, .. } => out_of_memory(cause),
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Runs `source` as a script that has used nothing yet, and returns what
+    /// it used.
+    fn run(lua: &Lua, source: &str) -> (LuaResult<()>, ScriptUsage) {
+        lua.set_app_data(ScriptUsage::default());
+        let result = lua.load(source).exec();
+        (result, lua.remove_app_data().unwrap())
+    }
+
+    #[test]
+    fn stops_endless_loops() {
+        let sandbox = LuaSandbox::default();
+        let lua = sandbox.create_lua().unwrap();
+        for source in [
+            "while true do end",
+            "while true do pcall(function() while true do end end) end",
+            "local loop = coroutine.wrap(function() while true do end end) loop()",
+            "while true do pcall(coroutine.wrap(function() while true do end end)) end",
+        ] {
+            let (result, usage) = run(&lua, source);
+            assert!(result.is_err(), "`{source}` ran to the end");
+            assert!(
+                sandbox.exceeded(&usage),
+                "`{source}` stayed under the limit"
+            );
+        }
+
+        // The next script starts over with the usual check.
+        let (result, usage) = run(&lua, "for i = 1, 1000 do end");
+        assert!(result.is_ok());
+        assert!(!sandbox.exceeded(&usage));
+    }
+
+    #[test]
+    fn budget_spans_calls() {
+        let sandbox = LuaSandbox {
+            instruction_limit: 100_000,
+            ..LuaSandbox::default()
+        };
+        let lua = sandbox.create_lua().unwrap();
+        let spin = lua
+            .load("return function() for i = 1, 20000 do end end")
+            .eval::<LuaFunction>()
+            .unwrap();
+        lua.set_app_data(ScriptUsage::default());
+        let calls = (0..100).take_while(|_| spin.call::<()>(()).is_ok()).count();
+        let usage: ScriptUsage = lua.remove_app_data().unwrap();
+        assert!(calls < 100, "every call got a budget of its own");
+        assert!(sandbox.exceeded(&usage));
+    }
+
+    #[test]
+    fn caps_events() {
+        let sandbox = LuaSandbox {
+            event_limit: 3,
+            ..LuaSandbox::default()
+        };
+        let lua = sandbox.create_lua().unwrap();
+        let emit = lua.create_function(|lua, ()| count_event(lua)).unwrap();
+        lua.globals().set("emit", emit).unwrap();
+        let (result, usage) = run(&lua, "for i = 1, 3 do emit() end");
+        assert!(result.is_ok());
+        assert_eq!(usage.events, 3);
+        let (result, _) = run(&lua, "for i = 1, 4 do emit() end");
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn takes_away_unsafe_functions() {
+        let lua = LuaSandbox::default().create_lua().unwrap();
+        for name in ["io", "debug", "require", "load", "dofile", "collectgarbage"] {
+            let value: LuaValue = lua.globals().get(name).unwrap();
+            assert!(value.is_nil(), "`{name}` is there");
+        }
+        let exit: LuaValue = lua.load("return os.exit").eval().unwrap();
+        assert!(exit.is_nil());
+        let strings: LuaValue = lua.load(r#"return getmetatable("")"#).eval().unwrap();
+        assert_eq!(strings, LuaValue::Boolean(false));
+    }
+}
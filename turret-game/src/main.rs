@@ -1,15 +1,29 @@
+mod buildings;
+mod camera;
 mod game;
+mod level;
+mod lua;
+mod lua_api;
+mod lua_sandbox;
 mod map;
 mod menu;
+mod minimap;
+mod tiles;
 mod ui;
 
+use crate::buildings::BuildingPlugin;
+use crate::camera::{CameraPlugin, RtsCamera};
 use crate::game::*;
+use crate::level::LevelPlugin;
+use crate::lua::LuaPlugin;
 use crate::map::*;
 use crate::menu::*;
+use crate::minimap::MinimapPlugin;
+use crate::tiles::TilePlugin;
 use crate::ui::*;
 use bevy::asset::load_internal_binary_asset;
 use bevy::prelude::*;
-use bevy_builder::BuilderExt;
+use bevy_builder::{BuilderExt, DialogPlugin, LocalizationPlugin, UiStylePlugin, UiThemePlugin};
 use bevy_obj::ObjPlugin;
 
 fn main() {
@@ -19,13 +33,25 @@ fn main() {
         DefaultPlugins.set(ImagePlugin::default_nearest()),
         MenuPlugin,
         GamePlugin,
+        CameraPlugin,
+        MinimapPlugin,
+        LuaPlugin::default(),
+        BuildingPlugin,
         ObjPlugin,
         MeshPickingPlugin,
+        LevelPlugin,
+        TilePlugin,
+        UiThemePlugin,
+        UiStylePlugin::new("ui.style.json5"),
+        DialogPlugin,
+        LocalizationPlugin::new("locales", ["en", "de"]),
     ))
     .init_state::<GameState>()
+    .add_event::<StorageFullEvent>()
     .add_systems(Startup, setup)
     .add_systems(Startup, (setup_canvas, init_ui).chain())
-    .add_systems(Startup, generate_map)
+    .add_systems(Startup, load_level)
+    .add_systems(Update, spawn_level_system)
     .add_systems(Update, generator_system)
     .add_systems(Update, player_resources_ui)
     .add_systems(Update, spawn_storage_full_bubble)
@@ -52,10 +78,7 @@ pub enum GameState {
 
 fn setup(mut commands: Commands, mut game_state: ResMut<NextState<GameState>>) {
     info!("Setting up camera.");
-    commands.spawn((
-        Camera3d::default(),
-        Transform::from_xyz(-2.5, 4.5, 9.0).looking_at(Vec3::ZERO, Vec3::Y),
-    ));
+    commands.spawn(RtsCamera::default());
     commands.spawn((
         PointLight {
             shadows_enabled: true,
@@ -1,98 +1,212 @@
-use crate::Canvas;
+use crate::buildings::{BuildingType, BuildingTypes, spawn_building};
+use crate::camera::BoardBounds;
+use crate::level::Level;
+use crate::tiles::{InRegion, Palette, REGION_SIZE, Tile, TileMesh, TileRegion};
+use bevy::color::palettes::css::GOLD;
 use bevy::prelude::*;
-use bevy_builder::BuilderExt;
+use std::collections::{HashMap, HashSet};
 
+/// Marks the player's starting point in the level.
 #[derive(Component)]
-struct Map {
-    width: u32,
-    height: u32,
-}
+pub struct SpawnPoint;
 
-impl Map {
-    fn new(width: u32, height: u32) -> Self {
-        Map { width, height }
-    }
+/// A deposit that can be harvested for `kind`.
+#[derive(Component)]
+pub struct ResourceNode {
+    pub kind: String,
+    pub amount: u32,
 }
 
+/// Everything spawned from the current level. Despawned when the level is reloaded.
 #[derive(Component)]
-struct Tile;
+pub struct LevelEntity;
+
+#[derive(Resource)]
+pub struct CurrentLevel(pub Handle<Level>);
+
+/// Loads the level given on the command line, such as a `.vmap` saved by the
+/// editor, or the first level.
+pub fn load_level(mut commands: Commands, asset_server: Res<AssetServer>) {
+    let path = std::env::args()
+        .nth(1)
+        .unwrap_or_else(|| "levels/first.tmx".to_string());
+    commands.insert_resource(CurrentLevel(asset_server.load(path)));
+}
 
-pub fn generate_map(
+/// Spawns the current level once it has loaded, and again whenever it is
+/// edited in Tiled.
+#[allow(clippy::too_many_arguments)]
+pub fn spawn_level_system(
     mut commands: Commands,
+    mut events: EventReader<AssetEvent<Level>>,
+    current: Option<Res<CurrentLevel>>,
+    levels: Res<Assets<Level>>,
+    spawned: Query<Entity, With<LevelEntity>>,
     asset_server: Res<AssetServer>,
-    mut materials: ResMut<Assets<StandardMaterial>>,
+    building_types: Res<BuildingTypes>,
+    mut palette: Palette,
 ) {
-    let map = Map::new(100, 100);
+    let Some(current) = current else {
+        return;
+    };
+    let reloaded = events.read().any(|event| {
+        event.is_loaded_with_dependencies(&current.0) || event.is_modified(&current.0)
+    });
+    let Some(level) = levels.get(&current.0).filter(|_| reloaded) else {
+        return;
+    };
+
+    info!("Spawning level");
+    for entity in &spawned {
+        commands.entity(entity).despawn();
+    }
 
-    for x in 0..map.width {
-        for y in 0..map.height {
-            let x = x as f32 - map.height as f32 / 2.0;
-            let y = y as f32 - map.width as f32 / 2.0;
-            commands
-                .spawn((
-                    Tile,
-                    Mesh3d(asset_server.load("untitled.obj")),
-                    MeshMaterial3d(materials.add(Color::srgb_u8(124, 144, 255))),
-                    Transform::from_xyz(x, 0.0, y).with_scale(Vec3::new(0.25, 0.25, 0.25)),
-                ))
-                .observe(spawn_builder_ui)
-                .observe(on_block_down);
+    let origin = Vec2::new(level.width as f32, level.height as f32) / 2.0;
+    commands.insert_resource(BoardBounds(Rect::from_corners(-origin, origin - Vec2::ONE)));
+    let mut regions = HashMap::new();
+    for tile in &level.tiles {
+        let region = *regions
+            .entry(tile.position / REGION_SIZE)
+            .or_insert_with(|| commands.spawn((TileRegion::default(), LevelEntity)).id());
+        let position = tile.position.as_vec2() - origin;
+        let mesh = tile.properties.get_str("mesh").unwrap_or("untitled.obj");
+        let color = tile
+            .properties
+            .get_color("color")
+            .unwrap_or(Color::srgb_u8(124, 144, 255));
+        let name = tile.kind.as_deref().unwrap_or("tile");
+        let mut entity = commands.spawn((
+            Tile { color },
+            InRegion(region),
+            LevelEntity,
+            Name::new(name.to_string()),
+            tile.properties.clone(),
+            TileMesh(asset_server.load(mesh.to_string())),
+            Transform::from_xyz(position.x, tile.elevation, position.y)
+                .with_scale(Vec3::new(0.25, 0.25, 0.25)),
+        ));
+        if tile.properties.get_bool("buildable").unwrap_or(true) {
+            entity.observe(spawn_builder_ui);
         }
     }
-}
 
-fn _on_block_hover(
-    hover: Trigger<Pointer<Over>>,
-    mut materials: ResMut<Assets<StandardMaterial>>,
-    mut tiles: Query<&mut MeshMaterial3d<StandardMaterial>, With<Tile>>,
-) {
-    info!("Hovered over tile");
-    let white_material = materials.add(Color::WHITE);
-    if let Ok(mut tile) = tiles.get_mut(hover.target()) {
-        info!("Hovered over tile");
-        tile.0 = white_material.clone();
+    for object in &level.objects {
+        let position = object.position.floor() - origin;
+        let translation = Vec3::new(position.x, 1.0, position.y);
+        let properties = &object.properties;
+        let entity = match object.kind.as_str() {
+            "spawn" => commands
+                .spawn((SpawnPoint, Transform::from_translation(translation)))
+                .id(),
+            "farm" => {
+                let farm = building_types.get("farm").cloned().unwrap_or_default();
+                let farm = BuildingType {
+                    rate: properties.get_u32("rate").unwrap_or(farm.rate),
+                    capacity: properties.get_u32("capacity").unwrap_or(farm.capacity),
+                    ..farm
+                };
+                spawn_building(
+                    &mut commands,
+                    &asset_server,
+                    &mut palette,
+                    &farm,
+                    translation,
+                )
+            }
+            "resource" => commands
+                .spawn((
+                    ResourceNode {
+                        kind: properties.get_str("resource").unwrap_or("gold").to_string(),
+                        amount: properties.get_u32("amount").unwrap_or(100),
+                    },
+                    Mesh3d(
+                        asset_server.load(
+                            properties
+                                .get_str("mesh")
+                                .unwrap_or("untitled.obj")
+                                .to_string(),
+                        ),
+                    ),
+                    MeshMaterial3d(
+                        palette.material(properties.get_color("color").unwrap_or(GOLD.into())),
+                    ),
+                    Transform::from_translation(translation)
+                        .with_scale(Vec3::new(0.25, 0.25, 0.25)),
+                ))
+                .id(),
+            kind => {
+                warn!(
+                    "Skipping level object `{}` of unknown type `{kind}`",
+                    object.name
+                );
+                continue;
+            }
+        };
+        commands.entity(entity).insert((
+            LevelEntity,
+            Name::new(object.name.clone()),
+            properties.clone(),
+        ));
     }
 }
 
-#[derive(Component)]
-pub struct Farm;
-
 #[derive(Component)]
 pub struct Generator {
-    rate: u32,
+    pub rate: u32,
 }
 
 #[derive(Component)]
 pub struct Storage {
-    capacity: u32,
-    amount: u32,
+    pub capacity: u32,
+    pub amount: u32,
+    /// What is stored, such as `food`.
+    pub resource: String,
 }
 
-#[derive(Component)]
-pub struct StorageFullEvent(Entity);
+/// Sent when a storage fills up.
+#[derive(Event, Debug, Clone, Copy)]
+pub struct StorageFullEvent(pub Entity);
 
 impl Storage {
     fn increase(&mut self, amount: u32) {
-        self.amount += amount;
-        self.amount = self.amount.min(self.capacity);
+        self.amount = self.amount.saturating_add(amount).min(self.capacity);
+    }
+
+    pub fn is_full(&self) -> bool {
+        self.amount == self.capacity
     }
 }
 
 #[derive(Component)]
-struct ContextBubble(pub Entity);
+pub struct ContextBubble(pub Entity);
 
+/// Shows a bubble over each full storage, and removes it once the storage is
+/// emptied.
 pub fn spawn_storage_full_bubble(
     mut commands: Commands,
     asset_server: Res<AssetServer>,
     storage: Query<(Entity, &Storage, &Transform)>,
-    mut materials: ResMut<Assets<StandardMaterial>>,
+    bubbles: Query<(Entity, &ContextBubble)>,
+    mut palette: Palette,
 ) {
+    let mut shown = HashSet::new();
+    for (bubble, ContextBubble(owner)) in &bubbles {
+        if storage
+            .get(*owner)
+            .is_ok_and(|(_, storage, _)| storage.is_full())
+        {
+            shown.insert(*owner);
+        } else {
+            commands.entity(bubble).despawn();
+        }
+    }
+
     for (entity, storage, transform) in &storage {
-        if storage.amount == storage.capacity {
+        if storage.is_full() && !shown.contains(&entity) {
             commands.spawn((
                 ContextBubble(entity),
                 Mesh3d(asset_server.load("context_bubble.obj")),
-                MeshMaterial3d(materials.add(Color::srgb_u8(124, 144, 255))),
+                MeshMaterial3d(palette.material(Color::srgb_u8(124, 144, 255))),
                 Transform::from_translation(Vec3::new(
                     transform.translation.x,
                     transform.translation.y + 1.0,
@@ -104,50 +218,37 @@ pub fn spawn_storage_full_bubble(
     }
 }
 
-pub fn generator_system(mut generators: Query<(&mut Storage, &Generator)>, time: Res<Time>) {
-    for (mut storage, generator) in &mut generators {
-        storage.increase(generator.rate * time.delta().as_millis() as u32);
-    }
-}
-
-fn on_block_down(
-    down: Trigger<Pointer<Released>>,
-    mut materials: ResMut<Assets<StandardMaterial>>,
-    mut tiles: Query<(&mut MeshMaterial3d<StandardMaterial>, &Transform), With<Tile>>,
-    mut commands: Commands,
-    asset_server: Res<AssetServer>,
+pub fn generator_system(
+    mut generators: Query<(Entity, &mut Storage, &Generator)>,
+    time: Res<Time>,
+    mut full: EventWriter<StorageFullEvent>,
 ) {
-    let white_material = materials.add(Color::BLACK);
-    if let Ok((mut tile, transform)) = tiles.get_mut(down.target()) {
-        tile.0 = white_material.clone();
-        let translation = transform.translation + Vec3::new(0.0, 1.0, 0.0);
-        commands
-            .spawn((
-                Generator { rate: 1 },
-                Storage {
-                    capacity: 100,
-                    amount: 0,
-                },
-                Farm,
-                Transform::from_translation(translation).with_scale(Vec3::new(0.25, 0.25, 0.25)),
-                MeshMaterial3d(materials.add(Color::srgb_u8(124, 144, 255))),
-                Mesh3d(asset_server.load("house.obj")),
-            ))
-            .observe(on_construct_release);
+    for (entity, mut storage, generator) in &mut generators {
+        let was_full = storage.is_full();
+        storage.increase(
+            generator
+                .rate
+                .saturating_mul(time.delta().as_millis() as u32),
+        );
+        if !was_full && storage.is_full() {
+            full.write(StorageFullEvent(entity));
+        }
     }
 }
 
 use crate::ui::{PlayerResources, spawn_builder_ui};
-fn on_construct_release(
+pub fn on_construct_release(
     released: Trigger<Pointer<Released>>,
     mut constructs: Query<(Entity, &mut Storage)>,
     mut resources: Single<&mut PlayerResources>,
 ) {
     if let Ok((entity, mut storage)) = constructs.get_mut(released.target()) {
         info!("Construct released: {}", entity);
-        info!("Food currently: {}", resources.food);
-        info!("Food in storage: {}", storage.amount);
-        resources.food += storage.amount;
-        storage.amount = 0;
+        info!("{} in storage: {}", storage.resource, storage.amount);
+        if resources.add(&storage.resource, storage.amount) {
+            storage.amount = 0;
+        } else {
+            warn!("Cannot collect unknown resource `{}`", storage.resource);
+        }
     }
 }
@@ -0,0 +1,279 @@
+use crate::buildings::Building;
+use crate::camera::{BoardBounds, FocusCamera, RtsCamera};
+use crate::map::{ResourceNode, Storage};
+use crate::tiles::Tile;
+use bevy::asset::RenderAssetUsages;
+use bevy::ecs::system::SystemParam;
+use bevy::prelude::*;
+use bevy::render::render_resource::{Extent3d, TextureDimension, TextureFormat};
+use bevy::ui::RelativeCursorPosition;
+use bevy_builder::{BuilderExt, Widget};
+
+/// The minimap is drawn at a whole number of pixels per tile, at least this
+/// many pixels across.
+const MINIMAP_PIXELS: u32 = 200;
+const BACKGROUND: [u8; 4] = [0, 0, 0, 255];
+const BUILDING: [u8; 4] = [255, 255, 255, 255];
+const FULL_STORAGE: [u8; 4] = [230, 40, 40, 255];
+const RESOURCE: [u8; 4] = [255, 215, 0, 255];
+const FRUSTUM: [u8; 4] = [255, 255, 255, 255];
+
+/// An overview of the board in the corner of the screen, drawn from the tiles
+/// and buildings rather than by a second camera. It marks buildings, full
+/// storages and what the camera sees. Clicking or dragging on it moves the
+/// camera there.
+pub struct MinimapPlugin;
+
+impl Plugin for MinimapPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(Startup, spawn_minimap).add_systems(
+            Update,
+            (
+                resize_minimap_system,
+                minimap_terrain_system,
+                draw_minimap_system,
+            )
+                .chain(),
+        );
+    }
+}
+
+#[derive(Component, Debug, Default)]
+pub struct Minimap {
+    /// The board in tiles.
+    size: UVec2,
+    /// Pixels per tile.
+    scale: u32,
+    /// The colour of the top tile of each cell, a row along X at a time.
+    terrain: Vec<[u8; 4]>,
+}
+
+impl Minimap {
+    /// Where a point on the ground is on the minimap image, in pixels.
+    fn pixel(&self, bounds: &BoardBounds, point: Vec2) -> Vec2 {
+        (point - bounds.0.min + 0.5) * self.scale as f32
+    }
+}
+
+fn spawn_minimap(mut commands: Commands) {
+    Widget::new((
+        Node::builder().styled("minimap"),
+        ImageNode::default(),
+        RelativeCursorPosition::default(),
+        Minimap::default(),
+    ))
+    .observe(
+        |pressed: Trigger<Pointer<Pressed>>, mut pointer: MinimapPointer| {
+            pointer.focus_camera(pressed.target());
+        },
+    )
+    .observe(
+        |dragged: Trigger<Pointer<Drag>>, mut pointer: MinimapPointer| {
+            pointer.focus_camera(dragged.target());
+        },
+    )
+    .spawn(&mut commands);
+}
+
+/// Moves the camera to the point of the board under the pointer.
+#[derive(SystemParam)]
+struct MinimapPointer<'w, 's> {
+    minimaps: Query<'w, 's, (&'static Minimap, &'static RelativeCursorPosition)>,
+    bounds: Res<'w, BoardBounds>,
+    focus: EventWriter<'w, FocusCamera>,
+}
+
+impl MinimapPointer<'_, '_> {
+    fn focus_camera(&mut self, minimap: Entity) {
+        let Ok((minimap, cursor)) = self.minimaps.get(minimap) else {
+            return;
+        };
+        if let Some(position) = cursor.normalized {
+            let point = self.bounds.0.min - 0.5 + position * minimap.size.as_vec2();
+            self.focus.write(FocusCamera(point));
+        }
+    }
+}
+
+/// Starts a new image whenever the board changes size.
+fn resize_minimap_system(
+    bounds: Res<BoardBounds>,
+    minimap: Single<(&mut Minimap, &mut ImageNode)>,
+    mut images: ResMut<Assets<Image>>,
+) {
+    let (mut minimap, mut node) = minimap.into_inner();
+    let size = (bounds.0.size().round().as_uvec2() + UVec2::ONE).max(UVec2::ONE);
+    if minimap.size == size && minimap.scale > 0 {
+        return;
+    }
+
+    let scale = (MINIMAP_PIXELS / size.max_element()).max(1);
+    let pixels = size * scale;
+    node.image = images.add(Image::new_fill(
+        Extent3d {
+            width: pixels.x,
+            height: pixels.y,
+            depth_or_array_layers: 1,
+        },
+        TextureDimension::D2,
+        &BACKGROUND,
+        TextureFormat::Rgba8UnormSrgb,
+        RenderAssetUsages::default(),
+    ));
+    *minimap = Minimap {
+        size,
+        scale,
+        terrain: vec![BACKGROUND; (size.x * size.y) as usize],
+    };
+}
+
+/// Takes the colour of the highest tile in each cell whenever a tile changes.
+fn minimap_terrain_system(
+    tiles: Query<(&Tile, &Transform)>,
+    changed: Query<(), Changed<Tile>>,
+    mut removed: RemovedComponents<Tile>,
+    bounds: Res<BoardBounds>,
+    mut minimap: Single<&mut Minimap>,
+) {
+    if changed.is_empty() && removed.read().count() == 0 && !minimap.is_changed() {
+        return;
+    }
+
+    let size = minimap.size;
+    let mut terrain = vec![BACKGROUND; (size.x * size.y) as usize];
+    let mut heights = vec![f32::NEG_INFINITY; terrain.len()];
+    for (tile, transform) in &tiles {
+        let cell = (transform.translation.xz() - bounds.0.min).round();
+        if cell.x < 0.0 || cell.y < 0.0 || cell.x >= size.x as f32 || cell.y >= size.y as f32 {
+            continue;
+        }
+        let index = (cell.x as u32 + cell.y as u32 * size.x) as usize;
+        if transform.translation.y >= heights[index] {
+            heights[index] = transform.translation.y;
+            terrain[index] = tile.color.to_srgba().to_u8_array();
+        }
+    }
+    minimap.terrain = terrain;
+}
+
+type BuildingChanged = (
+    Or<(Changed<Storage>, Changed<Transform>)>,
+    Or<(With<Building>, With<ResourceNode>)>,
+);
+
+#[allow(clippy::too_many_arguments)]
+fn draw_minimap_system(
+    minimap: Single<(Ref<Minimap>, &ImageNode)>,
+    bounds: Res<BoardBounds>,
+    buildings: Query<(&Transform, &Storage), With<Building>>,
+    resources: Query<&Transform, With<ResourceNode>>,
+    changed: Query<(), BuildingChanged>,
+    mut removed: RemovedComponents<Building>,
+    camera: Single<(&Camera, Ref<GlobalTransform>), With<RtsCamera>>,
+    mut images: ResMut<Assets<Image>>,
+) {
+    let (minimap, node) = minimap.into_inner();
+    let (camera, camera_transform) = camera.into_inner();
+    let removed = removed.read().count() > 0;
+    if !minimap.is_changed() && !camera_transform.is_changed() && changed.is_empty() && !removed {
+        return;
+    }
+    let Some(image) = images.get_mut(&node.image) else {
+        return;
+    };
+    let mut canvas = Canvas {
+        width: image.width(),
+        height: image.height(),
+        data: image.data.get_or_insert_default(),
+    };
+
+    let scale = minimap.scale;
+    for (index, color) in minimap.terrain.iter().enumerate() {
+        let cell = UVec2::new(index as u32 % minimap.size.x, index as u32 / minimap.size.x);
+        canvas.fill(
+            (cell * scale).as_vec2(),
+            ((cell + UVec2::ONE) * scale).as_vec2(),
+            *color,
+        );
+    }
+
+    // Markers stay visible however small the tiles are drawn.
+    let marker = (scale as f32).max(3.0) / 2.0;
+    let mark = |canvas: &mut Canvas, point: Vec3, size: f32, color: [u8; 4]| {
+        let center = minimap.pixel(&bounds, point.xz());
+        canvas.fill(center - size, center + size, color);
+    };
+    for transform in &resources {
+        mark(&mut canvas, transform.translation, marker, RESOURCE);
+    }
+    for (transform, storage) in &buildings {
+        if storage.is_full() {
+            mark(
+                &mut canvas,
+                transform.translation,
+                marker + 1.0,
+                FULL_STORAGE,
+            );
+        } else {
+            mark(&mut canvas, transform.translation, marker, BUILDING);
+        }
+    }
+
+    // Where the corners of the screen meet the ground.
+    let Some(viewport) = camera.logical_viewport_size() else {
+        return;
+    };
+    let corners = [
+        Vec2::ZERO,
+        viewport.with_y(0.0),
+        viewport,
+        viewport.with_x(0.0),
+    ]
+    .map(|corner| {
+        let ray = camera.viewport_to_world(&camera_transform, corner).ok()?;
+        let distance = ray.intersect_plane(Vec3::ZERO, InfinitePlane3d::new(Vec3::Y))?;
+        Some(minimap.pixel(&bounds, ray.get_point(distance).xz()))
+    });
+    for index in 0..corners.len() {
+        if let (Some(from), Some(to)) = (corners[index], corners[(index + 1) % corners.len()]) {
+            canvas.line(from, to, FRUSTUM);
+        }
+    }
+}
+
+/// The pixels of the minimap image.
+struct Canvas<'a> {
+    width: u32,
+    height: u32,
+    data: &'a mut Vec<u8>,
+}
+
+impl Canvas<'_> {
+    fn set(&mut self, x: i32, y: i32, color: [u8; 4]) {
+        if x < 0 || y < 0 || x >= self.width as i32 || y >= self.height as i32 {
+            return;
+        }
+        let index = (x as usize + y as usize * self.width as usize) * 4;
+        if let Some(pixel) = self.data.get_mut(index..index + 4) {
+            pixel.copy_from_slice(&color);
+        }
+    }
+
+    /// Fills the pixels from `min` up to `max`.
+    fn fill(&mut self, min: Vec2, max: Vec2, color: [u8; 4]) {
+        let (min, max) = (min.round().as_ivec2(), max.round().as_ivec2());
+        for y in min.y..max.y {
+            for x in min.x..max.x {
+                self.set(x, y, color);
+            }
+        }
+    }
+
+    fn line(&mut self, from: Vec2, to: Vec2, color: [u8; 4]) {
+        let steps = (to - from).abs().max_element().ceil().max(1.0) as u32;
+        for step in 0..=steps {
+            let point = from.lerp(to, step as f32 / steps as f32).floor().as_ivec2();
+            self.set(point.x, point.y, color);
+        }
+    }
+}
@@ -0,0 +1,515 @@
+use bevy::asset::RenderAssetUsages;
+use bevy::ecs::system::SystemParam;
+use bevy::picking::backend::HitData;
+use bevy::prelude::*;
+use bevy::render::mesh::{Indices, PrimitiveTopology, VertexAttributeValues};
+use std::collections::HashMap;
+use std::ops::Range;
+
+/// Tiles are drawn in square regions of this many tiles along each side.
+pub const REGION_SIZE: u32 = 16;
+
+/// Draws the board as one merged mesh per [`TileRegion`] instead of one
+/// entity per tile, and shares materials through the [`MaterialPalette`].
+///
+/// Tile colours are vertex colours, so highlighting a tile or changing its
+/// colour rewrites a few vertices of its region rather than swapping materials.
+pub struct TilePlugin;
+
+impl Plugin for TilePlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<MaterialPalette>()
+            .init_resource::<HoveredTile>()
+            .add_observer(hover_tile)
+            .add_observer(leave_tile)
+            .add_observer(click_tile)
+            .add_systems(Update, (build_region_system, tile_color_system).chain());
+    }
+}
+
+/// One material per colour, shared by everything drawn in that colour.
+#[derive(Resource, Default)]
+pub struct MaterialPalette(HashMap<[u8; 4], Handle<StandardMaterial>>);
+
+/// Hands out materials from the [`MaterialPalette`], adding a colour the
+/// first time it is asked for.
+#[derive(SystemParam)]
+pub struct Palette<'w> {
+    palette: ResMut<'w, MaterialPalette>,
+    materials: ResMut<'w, Assets<StandardMaterial>>,
+}
+
+impl Palette<'_> {
+    pub fn material(&mut self, color: impl Into<Color>) -> Handle<StandardMaterial> {
+        let color = color.into();
+        let materials = &mut self.materials;
+        self.palette
+            .0
+            .entry(color.to_srgba().to_u8_array())
+            .or_insert_with(|| materials.add(color))
+            .clone()
+    }
+}
+
+/// A tile of the board. It has no mesh of its own and is drawn by the
+/// [`TileRegion`] it is [`InRegion`] of.
+#[derive(Component, Debug, Clone, Copy)]
+pub struct Tile {
+    pub color: Color,
+}
+
+/// The mesh a tile is drawn with, placed by the tile's [`Transform`].
+#[derive(Component, Debug, Clone)]
+pub struct TileMesh(pub Handle<Mesh>);
+
+/// Draws the tile lighter than its colour, such as while it is under the pointer.
+#[derive(Component, Debug, Default)]
+pub struct TileHighlight;
+
+/// Triggered on a tile when it is clicked with the primary button.
+#[derive(Event, Debug, Clone, Copy)]
+pub struct TileClicked;
+
+#[derive(Component, Debug)]
+#[relationship(relationship_target = RegionTiles)]
+pub struct InRegion(pub Entity);
+
+#[derive(Component, Debug)]
+#[relationship_target(relationship = InRegion)]
+pub struct RegionTiles(Vec<Entity>);
+
+/// One mesh for all the tiles [`InRegion`] of it. The mesh is built once all of
+/// their meshes have loaded.
+#[derive(Component, Debug, Default)]
+#[require(Transform, Visibility)]
+pub struct TileRegion {
+    built: bool,
+    /// The vertices of each tile in the region mesh.
+    vertices: HashMap<Entity, Range<usize>>,
+}
+
+/// The tile under the pointer, which has a [`TileHighlight`].
+#[derive(Resource, Debug, Default)]
+struct HoveredTile(Option<Entity>);
+
+fn build_region_system(
+    mut commands: Commands,
+    mut regions: Query<(Entity, &mut TileRegion, &RegionTiles)>,
+    tiles: Query<(&Tile, &TileMesh, &Transform, Has<TileHighlight>)>,
+    asset_server: Res<AssetServer>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut palette: Palette,
+) {
+    'regions: for (entity, mut region, region_tiles) in &mut regions {
+        if region.built {
+            continue;
+        }
+        let mut merged = MergedMesh::default();
+        let mut vertices = HashMap::new();
+        for &tile_entity in region_tiles.0.iter() {
+            let Ok((tile, TileMesh(handle), transform, highlight)) = tiles.get(tile_entity) else {
+                continue;
+            };
+            let Some(mesh) = meshes.get(handle) else {
+                // A mesh that failed to load is left out rather than holding
+                // up the whole region.
+                if asset_server.load_state(handle).is_failed() {
+                    continue;
+                }
+                continue 'regions;
+            };
+            let range = merged.push(mesh, transform, tile_color(tile, highlight));
+            vertices.insert(tile_entity, range);
+        }
+
+        region.built = true;
+        region.vertices = vertices;
+        if let Some(mesh) = merged.into_mesh() {
+            commands.entity(entity).insert((
+                Mesh3d(meshes.add(mesh)),
+                MeshMaterial3d(palette.material(Color::WHITE)),
+            ));
+        }
+    }
+}
+
+type ColorChanged = Or<(Changed<Tile>, Changed<TileHighlight>)>;
+
+/// Rewrites the vertex colours of tiles whose colour or highlight changed.
+fn tile_color_system(
+    changed: Query<Entity, ColorChanged>,
+    mut unhighlighted: RemovedComponents<TileHighlight>,
+    tiles: Query<(&Tile, Has<TileHighlight>, &InRegion)>,
+    regions: Query<(&TileRegion, &Mesh3d)>,
+    mut meshes: ResMut<Assets<Mesh>>,
+) {
+    for entity in changed.iter().chain(unhighlighted.read()) {
+        let Ok((tile, highlight, InRegion(region))) = tiles.get(entity) else {
+            continue;
+        };
+        let Ok((region, Mesh3d(handle))) = regions.get(*region) else {
+            continue;
+        };
+        let Some(range) = region.vertices.get(&entity) else {
+            continue;
+        };
+        let Some(VertexAttributeValues::Float32x4(colors)) = meshes
+            .get_mut(handle)
+            .and_then(|mesh| mesh.attribute_mut(Mesh::ATTRIBUTE_COLOR))
+        else {
+            continue;
+        };
+        let color = tile_color(tile, highlight).to_linear().to_f32_array();
+        colors[range.clone()].fill(color);
+    }
+}
+
+fn tile_color(tile: &Tile, highlight: bool) -> Color {
+    if highlight {
+        tile.color.lighter(0.2)
+    } else {
+        tile.color
+    }
+}
+
+/// The tile of the region `region` that `hit` landed on.
+fn hit_tile(
+    region: Entity,
+    hit: &HitData,
+    regions: &Query<&RegionTiles, With<TileRegion>>,
+    tiles: &Query<&Transform, With<Tile>>,
+) -> Option<Entity> {
+    // Step just inside the surface that was hit.
+    let point = hit.position? - hit.normal.unwrap_or(Vec3::Y) * 0.01;
+    // Tiles sit on a one unit grid, and layers of them can be stacked.
+    regions
+        .get(region)
+        .ok()?
+        .0
+        .iter()
+        .filter_map(|&tile| Some((tile, tiles.get(tile).ok()?.translation)))
+        .filter(|(_, translation)| {
+            (point.x - translation.x).abs() <= 0.5 && (point.z - translation.z).abs() <= 0.5
+        })
+        .min_by(|(_, a), (_, b)| {
+            (point.y - a.y - 0.5)
+                .abs()
+                .total_cmp(&(point.y - b.y - 0.5).abs())
+        })
+        .map(|(tile, _)| tile)
+}
+
+fn hover_tile(
+    moved: Trigger<Pointer<Move>>,
+    regions: Query<&RegionTiles, With<TileRegion>>,
+    tiles: Query<&Transform, With<Tile>>,
+    mut hovered: ResMut<HoveredTile>,
+    mut commands: Commands,
+) {
+    let Some(tile) = hit_tile(moved.target(), &moved.hit, &regions, &tiles) else {
+        return;
+    };
+    if hovered.0 == Some(tile) {
+        return;
+    }
+    if let Some(previous) = hovered.0.replace(tile) {
+        commands.entity(previous).try_remove::<TileHighlight>();
+    }
+    commands.entity(tile).try_insert(TileHighlight);
+}
+
+fn leave_tile(
+    out: Trigger<Pointer<Out>>,
+    regions: Query<(), With<TileRegion>>,
+    mut hovered: ResMut<HoveredTile>,
+    mut commands: Commands,
+) {
+    if !regions.contains(out.target()) {
+        return;
+    }
+    if let Some(previous) = hovered.0.take() {
+        commands.entity(previous).try_remove::<TileHighlight>();
+    }
+}
+
+fn click_tile(
+    released: Trigger<Pointer<Released>>,
+    regions: Query<&RegionTiles, With<TileRegion>>,
+    tiles: Query<&Transform, With<Tile>>,
+    mut commands: Commands,
+) {
+    if released.button != PointerButton::Primary {
+        return;
+    }
+    if let Some(tile) = hit_tile(released.target(), &released.hit, &regions, &tiles) {
+        commands.trigger_targets(TileClicked, tile);
+    }
+}
+
+/// Tile meshes moved into place and appended into one, with a vertex colour
+/// per tile.
+#[derive(Default)]
+struct MergedMesh {
+    positions: Vec<[f32; 3]>,
+    normals: Vec<[f32; 3]>,
+    uvs: Vec<[f32; 2]>,
+    colors: Vec<[f32; 4]>,
+    indices: Vec<u32>,
+}
+
+impl MergedMesh {
+    /// Returns the vertices `mesh` was given.
+    fn push(&mut self, mesh: &Mesh, transform: &Transform, color: Color) -> Range<usize> {
+        let start = self.positions.len();
+        let Some(positions) = mesh
+            .attribute(Mesh::ATTRIBUTE_POSITION)
+            .and_then(VertexAttributeValues::as_float3)
+        else {
+            return start..start;
+        };
+        let matrix = transform.compute_matrix();
+        self.positions.extend(
+            positions
+                .iter()
+                .map(|position| matrix.transform_point3(Vec3::from(*position)).to_array()),
+        );
+
+        let normals = mesh
+            .attribute(Mesh::ATTRIBUTE_NORMAL)
+            .and_then(VertexAttributeValues::as_float3);
+        match normals {
+            Some(normals) => self.normals.extend(
+                normals
+                    .iter()
+                    .map(|normal| (transform.rotation * Vec3::from(*normal)).to_array()),
+            ),
+            None => self
+                .normals
+                .extend(positions.iter().map(|_| [0.0, 1.0, 0.0])),
+        }
+        match mesh.attribute(Mesh::ATTRIBUTE_UV_0) {
+            Some(VertexAttributeValues::Float32x2(uvs)) => self.uvs.extend(uvs),
+            _ => self.uvs.extend(positions.iter().map(|_| [0.0, 0.0])),
+        }
+        let color = color.to_linear().to_f32_array();
+        self.colors.extend(positions.iter().map(|_| color));
+
+        match mesh.indices() {
+            Some(indices) => self
+                .indices
+                .extend(indices.iter().map(|index| (start + index) as u32)),
+            None => self
+                .indices
+                .extend((start..start + positions.len()).map(|index| index as u32)),
+        }
+        start..self.positions.len()
+    }
+
+    fn into_mesh(self) -> Option<Mesh> {
+        if self.indices.is_empty() {
+            return None;
+        }
+        Some(
+            Mesh::new(
+                PrimitiveTopology::TriangleList,
+                RenderAssetUsages::default(),
+            )
+            .with_inserted_attribute(Mesh::ATTRIBUTE_POSITION, self.positions)
+            .with_inserted_attribute(Mesh::ATTRIBUTE_NORMAL, self.normals)
+            .with_inserted_attribute(Mesh::ATTRIBUTE_UV_0, self.uvs)
+            .with_inserted_attribute(Mesh::ATTRIBUTE_COLOR, self.colors)
+            .with_inserted_indices(Indices::U32(self.indices)),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bevy::ecs::system::RunSystemOnce;
+    use bevy::render::mesh::MeshVertexAttributeId;
+
+    /// One triangle with only positions, as a model without normals, UVs or
+    /// indices loads.
+    fn bare_triangle() -> Mesh {
+        Mesh::new(
+            PrimitiveTopology::TriangleList,
+            RenderAssetUsages::default(),
+        )
+        .with_inserted_attribute(
+            Mesh::ATTRIBUTE_POSITION,
+            vec![[0.0, 0.0, 0.0], [1.0, 0.0, 0.0], [0.0, 0.0, 1.0]],
+        )
+    }
+
+    fn float3(mesh: &Mesh, attribute: impl Into<MeshVertexAttributeId>) -> &[[f32; 3]] {
+        mesh.attribute(attribute)
+            .and_then(VertexAttributeValues::as_float3)
+            .unwrap()
+    }
+
+    #[test]
+    fn merges_meshes() {
+        let square = Mesh::from(Rectangle::new(1.0, 1.0));
+        let square_indices: Vec<_> = square.indices().unwrap().iter().collect();
+        let mut merged = MergedMesh::default();
+        // Lying flat, so its normals point up.
+        let flat = Transform::from_xyz(2.0, 0.0, 0.0)
+            .with_rotation(Quat::from_rotation_x(-std::f32::consts::FRAC_PI_2));
+        let first = merged.push(&square, &flat, Color::WHITE);
+        let second = merged.push(
+            &bare_triangle(),
+            &Transform::from_xyz(0.0, 1.0, 0.0),
+            Color::BLACK,
+        );
+        assert_eq!((first.clone(), second.clone()), (0..4, 4..7));
+
+        let mesh = merged.into_mesh().unwrap();
+        let Some(Indices::U32(indices)) = mesh.indices() else {
+            panic!("merged mesh has no indices");
+        };
+        // The second mesh has no indices, so it gets one per vertex, offset
+        // past the vertices of the first.
+        let (first_indices, second_indices) = indices.split_at(square_indices.len());
+        assert!(
+            first_indices
+                .iter()
+                .map(|index| *index as usize)
+                .eq(square_indices)
+        );
+        assert_eq!(second_indices, [4, 5, 6]);
+
+        let positions = float3(&mesh, Mesh::ATTRIBUTE_POSITION);
+        assert!(positions[first].iter().all(|position| position[0] > 1.0));
+        assert_eq!(
+            positions[4..],
+            [[0.0, 1.0, 0.0], [1.0, 1.0, 0.0], [0.0, 1.0, 1.0]]
+        );
+        let normals = float3(&mesh, Mesh::ATTRIBUTE_NORMAL);
+        assert!(
+            normals
+                .iter()
+                .all(|normal| Vec3::from(*normal).abs_diff_eq(Vec3::Y, 1e-6))
+        );
+        let Some(VertexAttributeValues::Float32x2(uvs)) = mesh.attribute(Mesh::ATTRIBUTE_UV_0)
+        else {
+            panic!("merged mesh has no UVs");
+        };
+        assert_eq!(uvs[second.clone()], [[0.0, 0.0]; 3]);
+        let Some(VertexAttributeValues::Float32x4(colors)) = mesh.attribute(Mesh::ATTRIBUTE_COLOR)
+        else {
+            panic!("merged mesh has no colours");
+        };
+        assert_eq!(colors[second], [[0.0, 0.0, 0.0, 1.0]; 3]);
+    }
+
+    #[test]
+    fn recolours_only_the_changed_tile() {
+        let mut app = App::new();
+        app.init_resource::<Assets<Mesh>>()
+            .add_systems(Update, tile_color_system);
+        let color = Color::srgb(0.2, 0.4, 0.6);
+        let tiles = [0.0, 1.0].map(|x| {
+            app.world_mut()
+                .spawn((Tile { color }, Transform::from_xyz(x, 0.0, 0.0)))
+                .id()
+        });
+        let mut merged = MergedMesh::default();
+        let square = Mesh::from(Rectangle::new(1.0, 1.0));
+        let vertices: HashMap<_, _> = tiles
+            .iter()
+            .map(|&tile| {
+                let transform = app.world().get::<Transform>(tile).unwrap();
+                (tile, merged.push(&square, transform, color))
+            })
+            .collect();
+        let mesh = app
+            .world_mut()
+            .resource_mut::<Assets<Mesh>>()
+            .add(merged.into_mesh().unwrap());
+        let region = app
+            .world_mut()
+            .spawn((
+                TileRegion {
+                    built: true,
+                    vertices: vertices.clone(),
+                },
+                Mesh3d(mesh.clone()),
+            ))
+            .id();
+        for tile in tiles {
+            app.world_mut().entity_mut(tile).insert(InRegion(region));
+        }
+        app.update();
+
+        app.world_mut().entity_mut(tiles[1]).insert(TileHighlight);
+        app.update();
+
+        let meshes = app.world().resource::<Assets<Mesh>>();
+        let Some(VertexAttributeValues::Float32x4(colors)) =
+            meshes.get(&mesh).unwrap().attribute(Mesh::ATTRIBUTE_COLOR)
+        else {
+            panic!("region mesh has no colours");
+        };
+        let linear = |color: Color| color.to_linear().to_f32_array();
+        let untouched = vertices[&tiles[0]].clone();
+        let highlighted = vertices[&tiles[1]].clone();
+        assert!(colors[untouched].iter().all(|c| *c == linear(color)));
+        assert!(
+            colors[highlighted]
+                .iter()
+                .all(|c| *c == linear(color.lighter(0.2)))
+        );
+    }
+
+    #[test]
+    fn finds_the_tile_that_was_hit() {
+        let mut world = World::new();
+        let lower = world
+            .spawn((
+                Tile {
+                    color: Color::WHITE,
+                },
+                Transform::default(),
+            ))
+            .id();
+        let upper = world
+            .spawn((
+                Tile {
+                    color: Color::WHITE,
+                },
+                Transform::from_xyz(0.0, 1.0, 0.0),
+            ))
+            .id();
+        let beside = world
+            .spawn((
+                Tile {
+                    color: Color::WHITE,
+                },
+                Transform::from_xyz(1.0, 0.0, 0.0),
+            ))
+            .id();
+        let region = world.spawn(TileRegion::default()).id();
+        for tile in [lower, upper, beside] {
+            world.entity_mut(tile).insert(InRegion(region));
+        }
+
+        let mut hit = |position: Vec3, normal: Vec3| {
+            let hit = HitData::new(Entity::PLACEHOLDER, 1.0, Some(position), Some(normal));
+            world
+                .run_system_once(
+                    move |regions: Query<&RegionTiles, With<TileRegion>>,
+                          tiles: Query<&Transform, With<Tile>>| {
+                        hit_tile(region, &hit, &regions, &tiles)
+                    },
+                )
+                .unwrap()
+        };
+        // The top of the stack, the side of its bottom tile, and the top of
+        // the tile next to it.
+        assert_eq!(hit(Vec3::new(0.1, 1.5, 0.2), Vec3::Y), Some(upper));
+        assert_eq!(hit(Vec3::new(-0.5, 0.2, 0.0), Vec3::NEG_X), Some(lower));
+        assert_eq!(hit(Vec3::new(1.2, 0.5, -0.3), Vec3::Y), Some(beside));
+        assert_eq!(hit(Vec3::new(5.0, 0.5, 0.0), Vec3::Y), None);
+    }
+}
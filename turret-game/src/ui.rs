@@ -1,7 +1,8 @@
 use crate::Canvas;
-use bevy::color::palettes::css::*;
+use crate::buildings::{BuildRequest, BuildingTypes, Cost};
+use crate::tiles::TileClicked;
 use bevy::prelude::*;
-use bevy_builder::BuilderExt;
+use bevy_builder::{Activate, BuilderExt, Dialogs, LocalizedText, Widget, WidgetText};
 
 #[derive(Component)]
 pub struct PlayerResources {
@@ -9,6 +10,30 @@ pub struct PlayerResources {
     pub food: u32,
 }
 
+impl PlayerResources {
+    /// Adds `amount` of the resource named `resource`, or returns `false` if
+    /// there is no such resource.
+    pub fn add(&mut self, resource: &str, amount: u32) -> bool {
+        let total = match resource {
+            "gold" => &mut self.gold,
+            "food" => &mut self.food,
+            _ => return false,
+        };
+        *total = total.saturating_add(amount);
+        true
+    }
+
+    /// Takes away the cost, or returns `false` if there is not enough.
+    pub fn pay(&mut self, cost: Cost) -> bool {
+        if self.gold < cost.gold || self.food < cost.food {
+            return false;
+        }
+        self.gold -= cost.gold;
+        self.food -= cost.food;
+        true
+    }
+}
+
 pub fn setup_player_resources(mut commands: Commands) {
     info!("For shits");
     commands.spawn(PlayerResources { gold: 0, food: 0 });
@@ -17,106 +42,93 @@ pub fn setup_player_resources(mut commands: Commands) {
 #[derive(Component)]
 pub struct Ui;
 
+#[derive(Component)]
+pub struct GoldCounter;
+
+#[derive(Component)]
+pub struct FoodCounter;
+
 pub fn init_ui(mut commands: Commands, canvas: Single<Entity, With<Canvas>>) {
     commands
         .entity(*canvas)
-        .insert((Text::new(format!("Gold: {}, Food: {}", 0, 0)), Ui));
+        .insert((Text::default(), Ui))
+        .with_children(|parent| {
+            parent.spawn((
+                TextSpan::default(),
+                LocalizedText::new("hud.gold").with_arg("count", 0),
+                GoldCounter,
+            ));
+            parent.spawn(TextSpan::new(", "));
+            parent.spawn((
+                TextSpan::default(),
+                LocalizedText::new("hud.food").with_arg("count", 0),
+                FoodCounter,
+            ));
+        });
 }
 
 pub fn player_resources_ui(
     player_resources: Single<&PlayerResources>,
-    mut player_resources_ui: Query<&mut Text, With<Ui>>,
+    mut gold: Single<&mut LocalizedText, (With<GoldCounter>, Without<FoodCounter>)>,
+    mut food: Single<&mut LocalizedText, (With<FoodCounter>, Without<GoldCounter>)>,
 ) {
-    // Implement UI for player resources
-
-    for mut ui in player_resources_ui.iter_mut() {
-        **ui = format!(
-            "Gold: {}, Food: {}",
-            player_resources.gold, player_resources.food
-        );
-    }
+    gold.set_if_neq(LocalizedText::new("hud.gold").with_arg("count", player_resources.gold));
+    food.set_if_neq(LocalizedText::new("hud.food").with_arg("count", player_resources.food));
 }
 
-#[derive(Component)]
+/// Marks the build popup opened by clicking a tile.
+#[derive(Component, Default)]
 pub struct BuilderUi;
 
-#[derive(Component)]
-pub struct BuilderCanvas;
-
-pub fn spawn_builder_ui(_: Trigger<Pointer<Released>>, mut commands: Commands) {
-    let canvas_node = Node::builder()
-        .width(Val::Percent(100.))
-        .height(Val::Percent(100.))
-        .justify_content(JustifyContent::Center)
-        .align_items(AlignItems::Center)
-        .build();
-
-    let builder_node = Node::builder()
-        .width(Val::Percent(80.))
-        .height(Val::Percent(80.))
-        .flex_direction(FlexDirection::Column)
-        .build();
-
-    let title_bar = Node::builder()
-        .width(Val::Percent(100.))
-        .height(Val::Percent(5.))
-        .build();
-
-    let close_button = Node::builder()
-        .width(Val::Percent(5.))
-        .height(Val::Percent(100.))
-        .left(Val::Percent(95.))
-        .build();
-
-    let button_node = Node::builder()
-        .width(Val::Percent(50.))
-        .height(Val::Percent(50.))
-        .margin(UiRect {
-            left: Val::Px(5.),
-            right: Val::Px(5.),
-            top: Val::Px(5.),
-            bottom: Val::Px(5.),
-        })
-        .build();
-
-    let text_node = Node::builder()
-        .width(Val::Percent(100.))
-        .height(Val::Percent(20.))
-        .build();
-
-    let canvas_bundle = (canvas_node, BackgroundColor(RED.into()), BuilderCanvas);
-    let builder_bundle = (builder_node, BackgroundColor(Color::WHITE), BuilderUi);
-    let button_bundle = (button_node, BackgroundColor(BLUE.into()));
-    let title_bar_bundle = (title_bar, BackgroundColor(Color::BLACK));
-    let close_button_bundle = (close_button, Button, BackgroundColor(GREEN.into()));
-    let text_bundle = (
-        text_node,
-        BackgroundColor(Color::WHITE),
-        Text::new("Farm"),
-        TextColor(GOLD.into()),
+/// Lists every [`BuildingType`](crate::buildings::BuildingType) with its
+/// cost, and builds the one picked on the clicked tile.
+pub fn spawn_builder_ui(
+    clicked: Trigger<TileClicked>,
+    types: Res<BuildingTypes>,
+    mut dialogs: Dialogs,
+) {
+    let tile = clicked.target();
+    let mut builder = Widget::panel(Node::builder().styled("builder-window")).child(
+        Widget::title_bar(
+            Node::builder().styled("title-bar"),
+            LocalizedText::new("build.title"),
+        )
+        .child(
+            Widget::button(Node::builder().styled("close-button"), "X")
+                .observe(builder_menu_close_system),
+        ),
     );
+    for building in types.iter() {
+        let title: WidgetText = match &building.title {
+            Some(title) => title.as_str().into(),
+            None => LocalizedText::new(format!("building.{}", building.name)).into(),
+        };
+        let cost = LocalizedText::new("build.cost")
+            .with_arg("gold", building.cost.gold)
+            .with_arg("food", building.cost.food);
+        let kind = building.name.clone();
+        builder = builder.child(
+            Widget::panel(Node::builder().styled("build-option"))
+                .child(
+                    Widget::button(Node::builder().styled("build-button"), title).observe(
+                        move |_: Trigger<Activate>,
+                              mut requests: EventWriter<BuildRequest>,
+                              mut dialogs: Dialogs| {
+                            requests.write(BuildRequest {
+                                tile,
+                                kind: kind.clone(),
+                            });
+                            dialogs.close::<BuilderUi>();
+                        },
+                    ),
+                )
+                .child(Widget::label(cost)),
+        );
+    }
 
-    let canvas = commands.spawn(canvas_bundle).id();
-    let builder = commands.spawn(builder_bundle).insert(ChildOf(canvas)).id();
-    let title_bar = commands
-        .spawn(title_bar_bundle)
-        .insert(ChildOf(builder))
-        .id();
-    let _close_button = commands
-        .spawn(close_button_bundle)
-        .observe(builder_menu_close_system)
-        .insert(ChildOf(title_bar))
-        .id();
-    let button = commands.spawn(button_bundle).insert(ChildOf(builder)).id();
-    let _text = commands.spawn(text_bundle).insert(ChildOf(button)).id();
+    dialogs.open::<BuilderUi>(builder, true);
 }
 
-pub fn builder_menu_close_system(
-    _: Trigger<Pointer<Released>>,
-    mut commands: Commands,
-    builder_ui: Query<Entity, With<BuilderCanvas>>,
-) {
-    for entity in builder_ui.iter() {
-        commands.entity(entity).despawn();
-    }
+pub fn builder_menu_close_system(_: Trigger<Activate>, mut dialogs: Dialogs) {
+    dialogs.close::<BuilderUi>();
 }
@@ -1,18 +1,301 @@
-use bevy::input::mouse::AccumulatedMouseScroll;
+use crate::AppState;
+use crate::file::FileSystems;
+use crate::map::EditedMap;
+use crate::tools::HoveredVoxel;
+use bevy::input::mouse::{AccumulatedMouseMotion, AccumulatedMouseScroll, MouseScrollUnit};
 use bevy::prelude::*;
+use bevy::render::camera::ScalingMode;
+use bevy_builder::DialogStack;
+use serde::Deserialize;
+use std::f32::consts::FRAC_PI_2;
+use voxel_map::CameraView;
 
+const CAMERA_SETTINGS_PATH: &str = "camera_settings.json5";
+/// Pixel scrolling, as from a touchpad, counts this many pixels as one line.
+const PIXELS_PER_LINE: f32 = 16.0;
+const MIN_DISTANCE: f32 = 1.0;
+const MAX_DISTANCE: f32 = 1000.0;
+/// Keeps perspective views from flipping over the poles.
+const MAX_PITCH: f32 = FRAC_PI_2 - 0.01;
+
+/// Orbits the [`EditorCamera`] around a focus point with the right mouse
+/// button, pans with the middle button or Shift and the right button, and
+/// zooms with the wheel. F focuses on the voxel under the pointer, or on the
+/// whole map, and the numpad switches to orthographic views: 7 from the top,
+/// 1 from the front, 3 from the side and 5 back to perspective.
+///
+/// The view is saved with the map and restored when it is opened.
 pub struct CameraPlugin;
 
 impl Plugin for CameraPlugin {
     fn build(&self, app: &mut App) {
-        app.add_systems(Update, move_camera);
+        app.init_resource::<CameraSettings>()
+            .init_resource::<CurrentView>()
+            .add_systems(Startup, load_camera_settings)
+            .add_systems(OnEnter(AppState::InApp), frame_map)
+            .add_systems(
+                Update,
+                (
+                    restore_view_system,
+                    camera_shortcut_system,
+                    move_camera_system,
+                    current_view_system.before(FileSystems::Actions),
+                )
+                    .chain()
+                    .run_if(in_state(AppState::InApp)),
+            )
+            .add_systems(
+                PostUpdate,
+                camera_transform_system.before(TransformSystem::TransformPropagate),
+            );
+    }
+}
+
+/// How fast the camera moves. Read from `camera_settings.json5` in the
+/// working directory when there is one.
+#[derive(Resource, Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct CameraSettings {
+    /// Radians per pixel dragged.
+    pub orbit_sensitivity: f32,
+    /// Fraction of the distance to the focus per pixel dragged.
+    pub pan_sensitivity: f32,
+    /// Fraction of the distance to the focus per line scrolled.
+    pub zoom_sensitivity: f32,
+}
+
+impl Default for CameraSettings {
+    fn default() -> Self {
+        CameraSettings {
+            orbit_sensitivity: 0.005,
+            pan_sensitivity: 0.0015,
+            zoom_sensitivity: 0.1,
+        }
+    }
+}
+
+/// The camera the map is edited through, placed by its [`CameraView`].
+#[derive(Component, Debug, Clone)]
+#[require(Camera3d)]
+pub struct EditorCamera {
+    pub view: CameraView,
+    /// The view of the edited map the camera last took on, to notice when
+    /// another map is opened.
+    restored: Option<CameraView>,
+}
+
+impl Default for EditorCamera {
+    fn default() -> Self {
+        EditorCamera {
+            view: CameraView {
+                focus: [0.0; 3],
+                yaw: -0.27,
+                pitch: -0.45,
+                distance: 10.5,
+                orthographic: false,
+            },
+            restored: None,
+        }
+    }
+}
+
+/// The view of the [`EditorCamera`], kept up to date so that saving the map
+/// does not depend on when the camera moves within the frame.
+#[derive(Resource, Debug, Default, Clone, Copy, PartialEq)]
+pub struct CurrentView(pub Option<CameraView>);
+
+fn load_camera_settings(mut settings: ResMut<CameraSettings>) {
+    let Ok(json) = std::fs::read_to_string(CAMERA_SETTINGS_PATH) else {
+        return;
+    };
+    match serde_json5::from_str(&json) {
+        Ok(loaded) => *settings = loaded,
+        Err(err) => warn!("Ignoring {CAMERA_SETTINGS_PATH}: {err}"),
+    }
+}
+
+/// Looks at the whole map from above and in front.
+fn map_view(map: &EditedMap) -> CameraView {
+    let dimensions = map.0.dimensions().as_uvec3().as_vec3();
+    let centre = map.translation(UVec3::ZERO) + (dimensions - Vec3::ONE) / 2.0;
+    CameraView {
+        focus: centre.to_array(),
+        distance: (dimensions.max_element() * 1.5).clamp(MIN_DISTANCE, MAX_DISTANCE),
+        ..EditorCamera::default().view
+    }
+}
+
+fn frame_map(map: Res<EditedMap>, mut camera: Single<&mut EditorCamera>) {
+    camera.view = map.0.camera.unwrap_or_else(|| map_view(&map));
+    camera.restored = map.0.camera;
+}
+
+/// Takes on the view of a map opened while another one was being edited, or
+/// frames it if it was saved without one.
+fn restore_view_system(map: Res<EditedMap>, mut camera: Single<&mut EditorCamera>) {
+    if map.is_added() || (map.is_changed() && map.0.camera != camera.restored) {
+        camera.view = map.0.camera.unwrap_or_else(|| map_view(&map));
+        camera.restored = map.0.camera;
+    }
+}
+
+fn camera_shortcut_system(
+    keys: Res<ButtonInput<KeyCode>>,
+    dialogs: Res<DialogStack>,
+    map: Res<EditedMap>,
+    hovered: Res<HoveredVoxel>,
+    mut camera: Single<&mut EditorCamera>,
+) {
+    if dialogs.has_modal() {
+        return;
     }
+
+    let view = &mut camera.bypass_change_detection().view;
+    let before = *view;
+    if keys.just_pressed(KeyCode::KeyF) {
+        match hovered.0 {
+            Some((position, _)) => view.focus = map.translation(position).to_array(),
+            None => *view = map_view(&map),
+        }
+    }
+    let presets = [
+        (KeyCode::Numpad7, 0.0, -FRAC_PI_2),
+        (KeyCode::Numpad1, 0.0, 0.0),
+        (KeyCode::Numpad3, FRAC_PI_2, 0.0),
+    ];
+    for (key, yaw, pitch) in presets {
+        if keys.just_pressed(key) {
+            view.yaw = yaw;
+            view.pitch = pitch;
+            view.orthographic = true;
+        }
+    }
+    if keys.just_pressed(KeyCode::Numpad5) {
+        view.pitch = view.pitch.clamp(-MAX_PITCH, MAX_PITCH);
+        view.orthographic = false;
+    }
+    if *view != before {
+        camera.set_changed();
+    }
+}
+
+fn move_camera_system(
+    buttons: Res<ButtonInput<MouseButton>>,
+    keys: Res<ButtonInput<KeyCode>>,
+    motion: Res<AccumulatedMouseMotion>,
+    scroll: Res<AccumulatedMouseScroll>,
+    settings: Res<CameraSettings>,
+    mut camera: Single<&mut EditorCamera>,
+) {
+    let shift = keys.any_pressed([KeyCode::ShiftLeft, KeyCode::ShiftRight]);
+    let orbit = buttons.pressed(MouseButton::Right) && !shift;
+    let pan = buttons.pressed(MouseButton::Middle) || buttons.pressed(MouseButton::Right) && shift;
+    let lines = match scroll.unit {
+        MouseScrollUnit::Line => scroll.delta.y,
+        MouseScrollUnit::Pixel => scroll.delta.y / PIXELS_PER_LINE,
+    };
+    let dragged = (orbit || pan) && motion.delta != Vec2::ZERO;
+    if !dragged && lines == 0.0 {
+        return;
+    }
+
+    let view = &mut camera.view;
+    if dragged && orbit {
+        // Turning away from a fixed view goes back to perspective.
+        view.orthographic = false;
+        view.yaw -= motion.delta.x * settings.orbit_sensitivity;
+        view.pitch =
+            (view.pitch - motion.delta.y * settings.orbit_sensitivity).clamp(-MAX_PITCH, MAX_PITCH);
+    } else if dragged {
+        let rotation = view_rotation(view);
+        let along = (rotation * Vec3::NEG_X * motion.delta.x + rotation * Vec3::Y * motion.delta.y)
+            * view.distance
+            * settings.pan_sensitivity;
+        view.focus = (Vec3::from_array(view.focus) + along).to_array();
+    }
+    if lines != 0.0 {
+        // Each line moves a fixed fraction of the way, so zooming feels the
+        // same close up and far away.
+        let factor = (1.0 - settings.zoom_sensitivity).powf(lines);
+        view.distance = (view.distance * factor).clamp(MIN_DISTANCE, MAX_DISTANCE);
+    }
+}
+
+fn current_view_system(mut current: ResMut<CurrentView>, camera: Single<&EditorCamera>) {
+    current.set_if_neq(CurrentView(Some(camera.view)));
 }
 
-fn move_camera(camera: Single<&mut Transform, With<Camera>>, input: Res<AccumulatedMouseScroll>) {
-    let mut camera = camera.into_inner();
-    let x = input.delta.x;
-    let y = input.delta.y;
-    let translation = camera.translation + Vec3::new(y, 0.0, x);
-    *camera = Transform::from_translation(translation).looking_at(Vec3::ZERO, Vec3::Y);
+fn view_rotation(view: &CameraView) -> Quat {
+    Quat::from_euler(EulerRot::YXZ, view.yaw, view.pitch, 0.0)
+}
+
+fn camera_transform_system(
+    mut cameras: Query<(&EditorCamera, &mut Transform, &mut Projection), Changed<EditorCamera>>,
+) {
+    for (camera, mut transform, mut projection) in &mut cameras {
+        let view = &camera.view;
+        let rotation = view_rotation(view);
+        *transform = Transform::from_translation(
+            Vec3::from_array(view.focus) + rotation * Vec3::Z * view.distance,
+        )
+        .with_rotation(rotation);
+        *projection = if view.orthographic {
+            Projection::Orthographic(OrthographicProjection {
+                scaling_mode: ScalingMode::FixedVertical {
+                    viewport_height: view.distance,
+                },
+                ..OrthographicProjection::default_3d()
+            })
+        } else {
+            Projection::Perspective(default())
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use voxel_map::{Dimensions, VoxelMap};
+
+    fn view(app: &mut App) -> CameraView {
+        let mut cameras = app.world_mut().query::<&EditorCamera>();
+        cameras.single(app.world()).unwrap().view
+    }
+
+    /// Opens `map` the way the file menu does, replacing the edited one.
+    fn open(app: &mut App, map: VoxelMap) {
+        app.world_mut().remove_resource::<EditedMap>();
+        app.world_mut().insert_resource(EditedMap(map));
+        app.update();
+    }
+
+    #[test]
+    fn takes_on_the_view_of_opened_maps() {
+        let mut app = App::new();
+        app.add_systems(Update, restore_view_system);
+        app.world_mut().spawn(EditorCamera::default());
+        let small = VoxelMap::new(Dimensions::new(4, 2, 4));
+        open(&mut app, small.clone());
+        assert_eq!(view(&mut app), map_view(&EditedMap(small)));
+
+        let saved = CameraView {
+            focus: [1.0, 2.0, 3.0],
+            distance: 20.0,
+            ..EditorCamera::default().view
+        };
+        let mut with_view = VoxelMap::new(Dimensions::new(8, 2, 8));
+        with_view.camera = Some(saved);
+        open(&mut app, with_view);
+        assert_eq!(view(&mut app), saved);
+
+        // Another map saved without a view is framed, rather than seen from
+        // wherever the camera was.
+        let large = VoxelMap::new(Dimensions::new(40, 4, 40));
+        open(&mut app, large.clone());
+        assert_eq!(view(&mut app), map_view(&EditedMap(large.clone())));
+        let mut cameras = app.world_mut().query::<&mut EditorCamera>();
+        cameras.single_mut(app.world_mut()).unwrap().view = saved;
+        open(&mut app, large.clone());
+        assert_eq!(view(&mut app), map_view(&EditedMap(large)));
+    }
 }
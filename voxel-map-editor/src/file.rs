@@ -0,0 +1,667 @@
+use crate::AppState;
+use crate::camera::CurrentView;
+use crate::history::History;
+use crate::map::EditedMap;
+use bevy::prelude::*;
+use bevy::window::WindowCloseRequested;
+use bevy_builder::{Activate, BuilderExt, Dialogs, LocalizedText, NumberField, TextField, Widget};
+use std::path::{Path, PathBuf};
+use voxel_map::VoxelMap;
+
+/// Suggested by Save As for a map that has never been saved.
+const DEFAULT_MAP_PATH: &str = "untitled.vmap";
+/// Suggested by Export for a map that has never been saved.
+const DEFAULT_EXPORT_PATH: &str = "untitled.vox";
+/// Besides the working directory, the Open dialog lists maps found here.
+const MAP_DIRECTORY: &str = "maps";
+const RECENT_FILES_PATH: &str = "recent_maps.json5";
+const MAX_RECENT_FILES: usize = 8;
+
+/// New, Open, Save, Save As and Export for the edited map, with their
+/// shortcuts, and a prompt before unsaved changes are thrown away.
+///
+/// MagicaVoxel `.vox` files open as untitled maps, since saving them back
+/// would lose tile types, metadata and annotations. They are written by
+/// Export, as are `.obj` meshes for the game to load as models.
+pub struct FilePlugin;
+
+impl Plugin for FilePlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<CurrentFile>()
+            .init_resource::<RecentFiles>()
+            .init_resource::<PendingAction>()
+            .add_event::<FileAction>()
+            .add_systems(Startup, load_recent_files)
+            .add_systems(
+                Update,
+                (
+                    (
+                        file_shortcut_system,
+                        close_requested_system,
+                        escape_pending_system,
+                    )
+                        .in_set(FileSystems::Requests),
+                    file_action_system.in_set(FileSystems::Actions),
+                    mark_dirty_system.run_if(resource_exists::<EditedMap>),
+                )
+                    .chain(),
+            );
+    }
+}
+
+/// Systems that need to see a [`FileAction`] before it is carried out run
+/// between these.
+#[derive(SystemSet, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum FileSystems {
+    /// Shortcuts and closing the window send actions.
+    Requests,
+    Actions,
+}
+
+/// Where the edited map lives on disk, and whether it has changed since.
+#[derive(Resource, Debug, Default)]
+pub struct CurrentFile {
+    pub path: Option<PathBuf>,
+    /// The [`History::revision`] the map was last opened or saved at.
+    saved: Option<u64>,
+    dirty: bool,
+}
+
+impl CurrentFile {
+    pub fn new(path: Option<PathBuf>, revision: u64) -> Self {
+        CurrentFile {
+            path,
+            saved: Some(revision),
+            dirty: false,
+        }
+    }
+
+    pub fn is_dirty(&self) -> bool {
+        self.dirty
+    }
+
+    /// The file name without its directory, or `None` for a map that was never saved.
+    pub fn name(&self) -> Option<String> {
+        self.path
+            .as_deref()
+            .and_then(Path::file_name)
+            .map(|name| name.to_string_lossy().into_owned())
+    }
+}
+
+/// Recently opened or saved maps, newest first. Kept in `recent_maps.json5`
+/// in the working directory.
+#[derive(Resource, Debug, Default)]
+pub struct RecentFiles(pub Vec<PathBuf>);
+
+impl RecentFiles {
+    fn push(&mut self, path: &Path) {
+        self.0.retain(|recent| recent != path);
+        self.0.insert(0, path.to_path_buf());
+        self.0.truncate(MAX_RECENT_FILES);
+
+        let saved = serde_json5::to_string(&self.0)
+            .map_err(|err| err.to_string())
+            .and_then(|json| {
+                std::fs::write(RECENT_FILES_PATH, json).map_err(|err| err.to_string())
+            });
+        if let Err(err) = saved {
+            warn!("Could not save recent files: {err}");
+        }
+    }
+}
+
+#[derive(Event, Debug, Clone)]
+pub enum FileAction {
+    /// Back to the menu to pick the dimensions of a new map.
+    New,
+    Open(PathBuf),
+    /// Saves to the current path, or asks for one if the map was never saved.
+    Save,
+    SaveAs(PathBuf),
+    /// Writes the box from `min` to `max` of the map in the format of the
+    /// path's extension, without changing where the map is saved.
+    Export {
+        path: PathBuf,
+        min: UVec3,
+        max: UVec3,
+    },
+    Quit,
+}
+
+/// The action waiting on the unsaved changes prompt. Set while the prompt, or
+/// the Save As dialog it led to, is open and until the save they asked for is
+/// done. Cancelling either of them drops it.
+#[derive(Resource, Debug, Default)]
+struct PendingAction(Option<FileAction>);
+
+#[derive(Component, Default)]
+struct OpenDialog;
+
+#[derive(Component, Default)]
+struct SaveAsDialog;
+
+#[derive(Component, Default)]
+struct ExportDialog;
+
+#[derive(Component, Default)]
+struct UnsavedDialog;
+
+#[derive(Component)]
+struct OpenPathInput;
+
+#[derive(Component)]
+struct SaveAsPathInput;
+
+#[derive(Component)]
+struct ExportPathInput;
+
+/// A coordinate of the first (0) or last (1) corner of the exported box.
+#[derive(Component, Clone, Copy)]
+struct ExportCornerInput {
+    corner: usize,
+    axis: usize,
+}
+
+fn load_recent_files(mut recent: ResMut<RecentFiles>) {
+    let Ok(json) = std::fs::read_to_string(RECENT_FILES_PATH) else {
+        return;
+    };
+    match serde_json5::from_str(&json) {
+        Ok(paths) => recent.0 = paths,
+        Err(err) => warn!("Ignoring {RECENT_FILES_PATH}: {err}"),
+    }
+}
+
+fn file_shortcut_system(
+    keys: Res<ButtonInput<KeyCode>>,
+    mut actions: EventWriter<FileAction>,
+    mut dialogs: Dialogs,
+    current: Res<CurrentFile>,
+    recent: Res<RecentFiles>,
+    map: Option<Res<EditedMap>>,
+) {
+    let ctrl = keys.any_pressed([KeyCode::ControlLeft, KeyCode::ControlRight]);
+    let shift = keys.any_pressed([KeyCode::ShiftLeft, KeyCode::ShiftRight]);
+    if !ctrl || dialogs.stack().has_modal() {
+        return;
+    }
+
+    if keys.just_pressed(KeyCode::KeyN) {
+        actions.write(FileAction::New);
+    } else if keys.just_pressed(KeyCode::KeyO) {
+        show_open_dialog(&mut dialogs, &recent);
+    } else if keys.just_pressed(KeyCode::KeyS) && shift {
+        show_save_as_dialog(&mut dialogs, &current);
+    } else if keys.just_pressed(KeyCode::KeyS) {
+        actions.write(FileAction::Save);
+    } else if keys.just_pressed(KeyCode::KeyE)
+        && let Some(map) = map
+    {
+        show_export_dialog(&mut dialogs, &current, &map);
+    }
+}
+
+/// The window only closes through [`FileAction::Quit`], so unsaved changes
+/// are not lost. See `close_when_requested` in `main`.
+fn close_requested_system(
+    mut requests: EventReader<WindowCloseRequested>,
+    mut actions: EventWriter<FileAction>,
+) {
+    if requests.read().count() > 0 {
+        actions.write(FileAction::Quit);
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn file_action_system(
+    mut actions: EventReader<FileAction>,
+    mut current: ResMut<CurrentFile>,
+    mut recent: ResMut<RecentFiles>,
+    mut pending: ResMut<PendingAction>,
+    mut history: ResMut<History>,
+    mut map: Option<ResMut<EditedMap>>,
+    view: Res<CurrentView>,
+    mut dialogs: Dialogs,
+    state: Res<State<AppState>>,
+    mut next_state: ResMut<NextState<AppState>>,
+    mut exit: EventWriter<AppExit>,
+    mut commands: Commands,
+) {
+    for action in actions.read() {
+        let discards = matches!(
+            action,
+            FileAction::New | FileAction::Open(_) | FileAction::Quit
+        );
+        if discards && current.is_dirty() {
+            pending.0 = Some(action.clone());
+            show_unsaved_dialog(&mut dialogs);
+            continue;
+        }
+
+        match action {
+            FileAction::New => {
+                *current = CurrentFile::default();
+                next_state.set(AppState::Menu);
+            }
+            FileAction::Open(path) => match open(path) {
+                Ok(map) => {
+                    info!("Opened map {}", path.display());
+                    history.clear();
+                    *current = if is_vox(path) {
+                        CurrentFile {
+                            dirty: true,
+                            ..default()
+                        }
+                    } else {
+                        CurrentFile::new(Some(path.clone()), history.revision())
+                    };
+                    recent.push(path);
+                    // Removed first, so the new map is meshed as a whole.
+                    commands.remove_resource::<EditedMap>();
+                    commands.insert_resource(EditedMap(map));
+                    if *state.get() != AppState::InApp {
+                        next_state.set(AppState::InApp);
+                    }
+                }
+                Err(err) => error!("Could not open map {}: {err}", path.display()),
+            },
+            FileAction::Save => match current.path.clone() {
+                Some(path) => save(
+                    &path,
+                    &mut current,
+                    &mut recent,
+                    &mut pending,
+                    map.as_mut(),
+                    *view,
+                    history.revision(),
+                    &mut commands,
+                ),
+                None => show_save_as_dialog(&mut dialogs, &current),
+            },
+            FileAction::SaveAs(path) => {
+                let mut path = path.clone();
+                if path.extension().is_none() {
+                    path.set_extension("vmap");
+                }
+                save(
+                    &path,
+                    &mut current,
+                    &mut recent,
+                    &mut pending,
+                    map.as_mut(),
+                    *view,
+                    history.revision(),
+                    &mut commands,
+                );
+            }
+            FileAction::Export { path, min, max } => {
+                let mut path = path.clone();
+                if path.extension().is_none() {
+                    path.set_extension("vox");
+                }
+                if let Some(map) = &map {
+                    export(&path, &map.0.cropped(*min, *max));
+                }
+            }
+            FileAction::Quit => {
+                exit.write(AppExit::Success);
+            }
+        }
+    }
+}
+
+fn is_vox(path: &Path) -> bool {
+    path.extension().is_some_and(|extension| extension == "vox")
+}
+
+fn open(path: &Path) -> Result<VoxelMap, String> {
+    if is_vox(path) {
+        VoxelMap::load_vox(path).map_err(|err| err.to_string())
+    } else {
+        VoxelMap::load(path).map_err(|err| err.to_string())
+    }
+}
+
+fn export(path: &Path, map: &VoxelMap) {
+    let exported = match path.extension().and_then(|extension| extension.to_str()) {
+        Some("vox") => map.save_vox(path).map_err(|err| err.to_string()),
+        Some("obj") => map.save_obj(path).map_err(|err| err.to_string()),
+        _ => Err("export to .vox or .obj".to_string()),
+    };
+    match exported {
+        Ok(()) => info!("Exported map to {}", path.display()),
+        Err(err) => error!("Could not export map to {}: {err}", path.display()),
+    }
+}
+
+/// Saves the edited map to `path` with the camera view, then carries on with
+/// whatever the unsaved changes prompt was holding up.
+#[allow(clippy::too_many_arguments)]
+fn save(
+    path: &Path,
+    current: &mut CurrentFile,
+    recent: &mut RecentFiles,
+    pending: &mut PendingAction,
+    map: Option<&mut ResMut<EditedMap>>,
+    view: CurrentView,
+    revision: u64,
+    commands: &mut Commands,
+) {
+    let Some(map) = map else {
+        pending.0 = None;
+        return;
+    };
+    // Only written when it moved, so the map is not marked changed for nothing.
+    if view.0.is_some() && map.0.camera != view.0 {
+        map.0.camera = view.0;
+    }
+    if let Err(err) = map.0.save(path) {
+        error!("Could not save map to {}: {err}", path.display());
+        pending.0 = None;
+        return;
+    }
+
+    info!("Saved map to {}", path.display());
+    *current = CurrentFile::new(Some(path.to_path_buf()), revision);
+    recent.push(path);
+    if let Some(action) = pending.0.take() {
+        commands.send_event(action);
+    }
+}
+
+/// Escape closes the unsaved changes prompt or the Save As dialog, the only
+/// dialogs open while an action is pending, so it cancels the action too.
+fn escape_pending_system(keys: Res<ButtonInput<KeyCode>>, mut pending: ResMut<PendingAction>) {
+    if keys.just_pressed(KeyCode::Escape) {
+        pending.0 = None;
+    }
+}
+
+fn mark_dirty_system(history: Res<History>, mut current: ResMut<CurrentFile>) {
+    if history.is_changed() {
+        // Only write when it flips, so the navbar title is not rebuilt on every edit.
+        let dirty = current.saved != Some(history.revision());
+        if current.dirty != dirty {
+            current.dirty = dirty;
+        }
+    }
+}
+
+/// Lists maps and `.vox` files in the working directory, in `maps/` and
+/// recently used ones.
+pub fn show_open_dialog(dialogs: &mut Dialogs, recent: &RecentFiles) {
+    let mut paths: Vec<PathBuf> = [Path::new("."), Path::new(MAP_DIRECTORY)]
+        .into_iter()
+        .filter_map(|directory| std::fs::read_dir(directory).ok())
+        .flatten()
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| {
+            path.extension()
+                .is_some_and(|extension| extension == "vmap" || extension == "vox")
+        })
+        .map(|path| {
+            path.strip_prefix(".")
+                .map(Path::to_path_buf)
+                .unwrap_or(path)
+        })
+        .collect();
+    paths.sort();
+    let recent: Vec<PathBuf> = recent
+        .0
+        .iter()
+        .filter(|path| !paths.contains(path))
+        .cloned()
+        .collect();
+
+    let window = Widget::panel(Node::builder().styled("dialog-window"))
+        .child(Widget::label(LocalizedText::new("file.open_title")))
+        .child(Widget::text_field(Node::builder().styled("path-input"), "").insert(OpenPathInput))
+        .child(
+            Widget::panel(Node::builder().styled("file-list"))
+                .children(recent.into_iter().chain(paths).map(file_button)),
+        )
+        .child(
+            Widget::panel(Node::builder().styled("dialog-buttons"))
+                .child(
+                    Widget::button(
+                        Node::builder().styled("dialog-button"),
+                        LocalizedText::new("file.open"),
+                    )
+                    .observe(open_typed_path),
+                )
+                .child(
+                    Widget::button(
+                        Node::builder().styled("dialog-button"),
+                        LocalizedText::new("file.cancel"),
+                    )
+                    .observe(|_: Trigger<Activate>, mut dialogs: Dialogs| {
+                        dialogs.close::<OpenDialog>()
+                    }),
+                ),
+        );
+    dialogs.open::<OpenDialog>(window, true);
+}
+
+fn file_button(path: PathBuf) -> Widget {
+    Widget::button(
+        Node::builder().styled("file-button"),
+        path.display().to_string(),
+    )
+    .observe(
+        move |_: Trigger<Activate>, mut dialogs: Dialogs, mut actions: EventWriter<FileAction>| {
+            dialogs.close::<OpenDialog>();
+            actions.write(FileAction::Open(path.clone()));
+        },
+    )
+}
+
+fn open_typed_path(
+    _: Trigger<Activate>,
+    input: Single<&TextField, With<OpenPathInput>>,
+    mut dialogs: Dialogs,
+    mut actions: EventWriter<FileAction>,
+) {
+    let path = input.value.trim();
+    if path.is_empty() {
+        return;
+    }
+    dialogs.close::<OpenDialog>();
+    actions.write(FileAction::Open(PathBuf::from(path)));
+}
+
+pub fn show_save_as_dialog(dialogs: &mut Dialogs, current: &CurrentFile) {
+    let path = current
+        .path
+        .as_ref()
+        .map_or(DEFAULT_MAP_PATH.to_string(), |path| {
+            path.display().to_string()
+        });
+
+    let window = Widget::panel(Node::builder().styled("dialog-window"))
+        .child(Widget::label(LocalizedText::new("file.save_as_title")))
+        .child(
+            Widget::text_field(Node::builder().styled("path-input"), path).insert(SaveAsPathInput),
+        )
+        .child(
+            Widget::panel(Node::builder().styled("dialog-buttons"))
+                .child(
+                    Widget::button(
+                        Node::builder().styled("dialog-button"),
+                        LocalizedText::new("file.save"),
+                    )
+                    .observe(save_typed_path),
+                )
+                .child(
+                    Widget::button(
+                        Node::builder().styled("dialog-button"),
+                        LocalizedText::new("file.cancel"),
+                    )
+                    .observe(
+                        |_: Trigger<Activate>,
+                         mut dialogs: Dialogs,
+                         mut pending: ResMut<PendingAction>| {
+                            pending.0 = None;
+                            dialogs.close::<SaveAsDialog>();
+                        },
+                    ),
+                ),
+        );
+    dialogs.open::<SaveAsDialog>(window, true);
+}
+
+fn save_typed_path(
+    _: Trigger<Activate>,
+    input: Single<&TextField, With<SaveAsPathInput>>,
+    mut dialogs: Dialogs,
+    mut actions: EventWriter<FileAction>,
+) {
+    let path = input.value.trim();
+    if path.is_empty() {
+        return;
+    }
+    dialogs.close::<SaveAsDialog>();
+    actions.write(FileAction::SaveAs(PathBuf::from(path)));
+}
+
+/// Suggests the map's own path as a `.vox` file, and the whole map as the box
+/// to export.
+pub fn show_export_dialog(dialogs: &mut Dialogs, current: &CurrentFile, map: &EditedMap) {
+    let last = map.0.dimensions().as_uvec3().saturating_sub(UVec3::ONE);
+    let corners = [("file.export_from", UVec3::ZERO), ("file.export_to", last)];
+    let path = current
+        .path
+        .as_ref()
+        .map_or(DEFAULT_EXPORT_PATH.to_string(), |path| {
+            path.with_extension("vox").display().to_string()
+        });
+
+    let window = Widget::panel(Node::builder().styled("dialog-window"))
+        .child(Widget::label(LocalizedText::new("file.export_title")))
+        .child(
+            Widget::text_field(Node::builder().styled("path-input"), path).insert(ExportPathInput),
+        )
+        .children(
+            corners
+                .into_iter()
+                .enumerate()
+                .map(|(corner, (label, value))| {
+                    Widget::panel(Node::builder().styled("dialog-row"))
+                        .child(
+                            Widget::label(LocalizedText::new(label))
+                                .insert(Node::builder().styled("dimension-label")),
+                        )
+                        .children((0..3).map(|axis| {
+                            Widget::number_field(
+                                Node::builder().styled("corner-input"),
+                                value[axis],
+                            )
+                            .insert(ExportCornerInput { corner, axis })
+                        }))
+                }),
+        )
+        .child(
+            Widget::panel(Node::builder().styled("dialog-buttons"))
+                .child(
+                    Widget::button(
+                        Node::builder().styled("dialog-button"),
+                        LocalizedText::new("file.export"),
+                    )
+                    .observe(export_typed_path),
+                )
+                .child(
+                    Widget::button(
+                        Node::builder().styled("dialog-button"),
+                        LocalizedText::new("file.cancel"),
+                    )
+                    .observe(|_: Trigger<Activate>, mut dialogs: Dialogs| {
+                        dialogs.close::<ExportDialog>()
+                    }),
+                ),
+        );
+    dialogs.open::<ExportDialog>(window, true);
+}
+
+fn export_typed_path(
+    _: Trigger<Activate>,
+    input: Single<&TextField, With<ExportPathInput>>,
+    corners: Query<(&NumberField, &ExportCornerInput)>,
+    mut dialogs: Dialogs,
+    mut actions: EventWriter<FileAction>,
+) {
+    let path = input.value.trim();
+    if path.is_empty() {
+        return;
+    }
+    let mut values = [UVec3::ZERO; 2];
+    for (field, input) in &corners {
+        values[input.corner][input.axis] = field.value;
+    }
+    dialogs.close::<ExportDialog>();
+    // Either corner can be typed first.
+    let [first, last] = values;
+    actions.write(FileAction::Export {
+        path: PathBuf::from(path),
+        min: first.min(last),
+        max: first.max(last),
+    });
+}
+
+/// Asks whether to save before [`PendingAction`] goes ahead.
+fn show_unsaved_dialog(dialogs: &mut Dialogs) {
+    let window = Widget::panel(Node::builder().styled("dialog-window"))
+        .child(Widget::label(LocalizedText::new("file.unsaved")))
+        .child(
+            Widget::panel(Node::builder().styled("dialog-buttons"))
+                .child(
+                    Widget::button(
+                        Node::builder().styled("dialog-button"),
+                        LocalizedText::new("file.save"),
+                    )
+                    .observe(
+                        |_: Trigger<Activate>,
+                         mut dialogs: Dialogs,
+                         mut actions: EventWriter<FileAction>| {
+                            dialogs.close::<UnsavedDialog>();
+                            actions.write(FileAction::Save);
+                        },
+                    ),
+                )
+                .child(
+                    Widget::button(
+                        Node::builder().styled("dialog-button"),
+                        LocalizedText::new("file.discard"),
+                    )
+                    .observe(discard_changes),
+                )
+                .child(
+                    Widget::button(
+                        Node::builder().styled("dialog-button"),
+                        LocalizedText::new("file.cancel"),
+                    )
+                    .observe(
+                        |_: Trigger<Activate>,
+                         mut dialogs: Dialogs,
+                         mut pending: ResMut<PendingAction>| {
+                            pending.0 = None;
+                            dialogs.close::<UnsavedDialog>();
+                        },
+                    ),
+                ),
+        );
+    dialogs.open::<UnsavedDialog>(window, true);
+}
+
+fn discard_changes(
+    _: Trigger<Activate>,
+    mut current: ResMut<CurrentFile>,
+    mut pending: ResMut<PendingAction>,
+    mut dialogs: Dialogs,
+    mut actions: EventWriter<FileAction>,
+) {
+    current.dirty = false;
+    dialogs.close::<UnsavedDialog>();
+    if let Some(action) = pending.0.take() {
+        actions.write(action);
+    }
+}
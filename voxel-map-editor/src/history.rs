@@ -0,0 +1,487 @@
+use crate::AppState;
+use crate::map::EditedMap;
+use bevy::prelude::*;
+use bevy_builder::DialogStack;
+use std::collections::{HashMap, HashSet};
+use voxel_map::{Dimensions, Palette, Voxel, VoxelMap};
+
+/// The oldest commands are forgotten past this many.
+const MAX_HISTORY: usize = 100;
+
+/// Ctrl+Z and Ctrl+Shift+Z over the [`History`] of the edited map.
+pub struct HistoryPlugin;
+
+impl Plugin for HistoryPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<History>().add_systems(
+            Update,
+            undo_shortcut_system
+                .run_if(in_state(AppState::InApp).and(resource_exists::<EditedMap>)),
+        );
+    }
+}
+
+/// One voxel changed by a [`MapCommand::SetVoxels`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct VoxelChange {
+    pub position: UVec3,
+    pub before: Voxel,
+    pub after: Voxel,
+}
+
+/// A reversible change to a [`VoxelMap`].
+#[derive(Debug, Clone, PartialEq)]
+pub enum MapCommand {
+    /// Voxels placed, erased or recoloured. A whole brush stroke is one command.
+    SetVoxels(Vec<VoxelChange>),
+    /// Keeps the whole map from before, since shrinking drops voxels.
+    Resize {
+        before: Box<VoxelMap>,
+        dimensions: Dimensions,
+    },
+    /// Materials edited, added or replaced by an imported palette.
+    SetPalette { before: Palette, after: Palette },
+}
+
+impl MapCommand {
+    fn apply(&self, map: &mut VoxelMap) {
+        match self {
+            MapCommand::SetVoxels(changes) => {
+                for change in changes {
+                    map.set(change.position, change.after);
+                }
+            }
+            MapCommand::Resize { before, dimensions } => *map = before.resized(*dimensions),
+            MapCommand::SetPalette { after, .. } => map.palette = after.clone(),
+        }
+    }
+
+    fn revert(&self, map: &mut VoxelMap) {
+        match self {
+            MapCommand::SetVoxels(changes) => {
+                for change in changes.iter().rev() {
+                    map.set(change.position, change.before);
+                }
+            }
+            MapCommand::Resize { before, .. } => *map = (**before).clone(),
+            MapCommand::SetPalette { before, .. } => map.palette = before.clone(),
+        }
+    }
+}
+
+/// What [`History`] changed in the map since [`History::take_changes`], so
+/// only the chunks around those voxels are remeshed.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct MapChanges {
+    pub voxels: HashSet<UVec3>,
+    /// Any voxel may have moved.
+    pub resized: bool,
+}
+
+/// Every change made to the edited map, so it can be undone and redone.
+///
+/// Only works on a [`VoxelMap`], so it can be driven without an app or a
+/// renderer. Changes go through [`History::set_voxel`] and
+/// [`History::resize`], which apply them as well as recording them.
+#[derive(Resource, Debug, Default)]
+pub struct History {
+    commands: Vec<MapCommand>,
+    /// The [`History::revision`] after each of `commands`.
+    revisions: Vec<u64>,
+    /// The revision with none of `commands` applied.
+    first_revision: u64,
+    last_revision: u64,
+    /// How many of `commands` are applied to the map. The rest were undone.
+    applied: usize,
+    /// Where each voxel of the open stroke is in the last command, which
+    /// [`History::set_voxel`] adds to. Empty when no stroke is open.
+    stroke: HashMap<UVec3, usize>,
+    changes: MapChanges,
+}
+
+impl History {
+    pub fn commands(&self) -> &[MapCommand] {
+        &self.commands
+    }
+
+    pub fn applied(&self) -> usize {
+        self.applied
+    }
+
+    /// Tells the states of the map apart: it is the same whenever the map is
+    /// back to the same state through undo or redo, and different after any
+    /// other change.
+    pub fn revision(&self) -> u64 {
+        match self.applied {
+            0 => self.first_revision,
+            applied => self.revisions[applied - 1],
+        }
+    }
+
+    pub fn take_changes(&mut self) -> MapChanges {
+        std::mem::take(&mut self.changes)
+    }
+
+    pub fn can_undo(&self) -> bool {
+        self.applied > 0
+    }
+
+    pub fn can_redo(&self) -> bool {
+        self.applied < self.commands.len()
+    }
+
+    pub fn is_stroke_open(&self) -> bool {
+        !self.stroke.is_empty()
+    }
+
+    /// Sets one voxel. Calls until the next [`History::end_stroke`] merge into
+    /// a single command. Returns `false` if nothing changed.
+    pub fn set_voxel(&mut self, map: &mut VoxelMap, position: UVec3, voxel: Voxel) -> bool {
+        let Some(before) = map.set(position, voxel).filter(|before| *before != voxel) else {
+            return false;
+        };
+
+        if !self.is_stroke_open() {
+            self.push(MapCommand::SetVoxels(Vec::new()));
+        }
+        let Some(MapCommand::SetVoxels(changes)) = self.commands.last_mut() else {
+            unreachable!("an open stroke is the last command");
+        };
+        self.last_revision += 1;
+        self.revisions[self.applied - 1] = self.last_revision;
+        self.changes.voxels.insert(position);
+        // Going over a voxel twice in one stroke keeps what it was at the start.
+        match self.stroke.get(&position) {
+            Some(&index) => changes[index].after = voxel,
+            None => {
+                self.stroke.insert(position, changes.len());
+                changes.push(VoxelChange {
+                    position,
+                    before,
+                    after: voxel,
+                });
+            }
+        }
+        true
+    }
+
+    pub fn resize(&mut self, map: &mut VoxelMap, dimensions: Dimensions) -> bool {
+        if map.dimensions() == dimensions {
+            return false;
+        }
+        let command = MapCommand::Resize {
+            before: Box::new(map.clone()),
+            dimensions,
+        };
+        command.apply(map);
+        self.push(command);
+        self.changes.resized = true;
+        true
+    }
+
+    pub fn set_palette(&mut self, map: &mut VoxelMap, palette: Palette) -> bool {
+        if map.palette == palette {
+            return false;
+        }
+        let command = MapCommand::SetPalette {
+            before: map.palette.clone(),
+            after: palette,
+        };
+        command.apply(map);
+        self.push(command);
+        true
+    }
+
+    pub fn end_stroke(&mut self) {
+        self.stroke.clear();
+    }
+
+    pub fn undo(&mut self, map: &mut VoxelMap) -> bool {
+        self.stroke.clear();
+        if !self.can_undo() {
+            return false;
+        }
+        self.applied -= 1;
+        self.commands[self.applied].revert(map);
+        self.record_changes(self.applied);
+        true
+    }
+
+    pub fn redo(&mut self, map: &mut VoxelMap) -> bool {
+        self.stroke.clear();
+        if !self.can_redo() {
+            return false;
+        }
+        self.commands[self.applied].apply(map);
+        self.record_changes(self.applied);
+        self.applied += 1;
+        true
+    }
+
+    /// Undoes or redoes until `applied` commands are applied.
+    pub fn jump(&mut self, map: &mut VoxelMap, applied: usize) {
+        let applied = applied.min(self.commands.len());
+        while self.applied > applied && self.undo(map) {}
+        while self.applied < applied && self.redo(map) {}
+    }
+
+    pub fn clear(&mut self) {
+        *self = History::default();
+    }
+
+    /// Drops whatever was undone, since it can no longer be redone.
+    fn push(&mut self, command: MapCommand) {
+        self.commands.truncate(self.applied);
+        self.revisions.truncate(self.applied);
+        self.last_revision += 1;
+        self.commands.push(command);
+        self.revisions.push(self.last_revision);
+        if self.commands.len() > MAX_HISTORY {
+            self.commands.remove(0);
+            self.first_revision = self.revisions.remove(0);
+        }
+        self.applied = self.commands.len();
+        self.stroke.clear();
+    }
+
+    /// Notes the voxels `self.commands[index]` changes, whichever way it went.
+    fn record_changes(&mut self, index: usize) {
+        match &self.commands[index] {
+            MapCommand::SetVoxels(changes) => self
+                .changes
+                .voxels
+                .extend(changes.iter().map(|change| change.position)),
+            MapCommand::Resize { .. } => self.changes.resized = true,
+            MapCommand::SetPalette { .. } => {}
+        }
+    }
+}
+
+fn undo_shortcut_system(
+    keys: Res<ButtonInput<KeyCode>>,
+    dialogs: Res<DialogStack>,
+    mut history: ResMut<History>,
+    mut map: ResMut<EditedMap>,
+) {
+    let ctrl = keys.any_pressed([KeyCode::ControlLeft, KeyCode::ControlRight]);
+    let shift = keys.any_pressed([KeyCode::ShiftLeft, KeyCode::ShiftRight]);
+    if !ctrl || !keys.just_pressed(KeyCode::KeyZ) || dialogs.has_modal() {
+        return;
+    }
+
+    // Only touch the map when there is something to do, so it is not rebuilt.
+    if shift && history.can_redo() {
+        history.redo(&mut map.0);
+    } else if !shift && history.can_undo() {
+        history.undo(&mut map.0);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use voxel_map::{TileType, VoxelMaterial};
+
+    fn ground(material: u8) -> Voxel {
+        Voxel::new(TileType::Ground, material)
+    }
+
+    fn map() -> VoxelMap {
+        VoxelMap::new(Dimensions::new(4, 4, 4))
+    }
+
+    #[test]
+    fn undoes_and_redoes_voxels() {
+        let mut map = map();
+        let mut history = History::default();
+        assert!(history.set_voxel(&mut map, UVec3::ZERO, ground(1)));
+        history.end_stroke();
+        let edited = map.clone();
+
+        assert!(history.undo(&mut map));
+        assert_eq!(map.get(UVec3::ZERO), Some(Voxel::EMPTY));
+        assert!(!history.undo(&mut map));
+        assert!(history.redo(&mut map));
+        assert_eq!(map, edited);
+        assert!(!history.redo(&mut map));
+    }
+
+    #[test]
+    fn unchanged_voxels_are_not_recorded() {
+        let mut map = map();
+        let mut history = History::default();
+        assert!(!history.set_voxel(&mut map, UVec3::ZERO, Voxel::EMPTY));
+        assert!(!history.set_voxel(&mut map, UVec3::splat(9), ground(1)));
+        assert!(history.commands().is_empty());
+    }
+
+    #[test]
+    fn undoes_and_redoes_resize() {
+        let mut map = map();
+        let mut history = History::default();
+        history.set_voxel(&mut map, UVec3::new(3, 3, 3), ground(2));
+        let before = map.clone();
+
+        assert!(history.resize(&mut map, Dimensions::new(2, 2, 2)));
+        assert!(!history.resize(&mut map, Dimensions::new(2, 2, 2)));
+        assert_eq!(map.dimensions(), Dimensions::new(2, 2, 2));
+        history.undo(&mut map);
+        // The voxel dropped by shrinking comes back.
+        assert_eq!(map, before);
+        history.redo(&mut map);
+        assert_eq!(map.dimensions(), Dimensions::new(2, 2, 2));
+    }
+
+    #[test]
+    fn undoes_and_redoes_palette() {
+        let mut map = map();
+        let mut history = History::default();
+        let before = map.palette.clone();
+        let mut after = before.clone();
+        after.push(VoxelMaterial::new(Srgba::RED));
+
+        assert!(history.set_palette(&mut map, after.clone()));
+        assert!(!history.set_palette(&mut map, after.clone()));
+        history.undo(&mut map);
+        assert_eq!(map.palette, before);
+        history.redo(&mut map);
+        assert_eq!(map.palette, after);
+    }
+
+    #[test]
+    fn merges_a_stroke() {
+        let mut map = map();
+        let mut history = History::default();
+        history.set_voxel(&mut map, UVec3::ZERO, ground(1));
+        history.set_voxel(&mut map, UVec3::X, ground(1));
+        // Going over a voxel again keeps what it was before the stroke.
+        history.set_voxel(&mut map, UVec3::ZERO, ground(2));
+        assert!(history.is_stroke_open());
+        history.end_stroke();
+        history.set_voxel(&mut map, UVec3::Y, ground(3));
+
+        assert_eq!(history.commands().len(), 2);
+        assert_eq!(
+            history.commands()[0],
+            MapCommand::SetVoxels(vec![
+                VoxelChange {
+                    position: UVec3::ZERO,
+                    before: Voxel::EMPTY,
+                    after: ground(2),
+                },
+                VoxelChange {
+                    position: UVec3::X,
+                    before: Voxel::EMPTY,
+                    after: ground(1),
+                },
+            ])
+        );
+        history.undo(&mut map);
+        history.undo(&mut map);
+        assert_eq!(map, self::map());
+    }
+
+    #[test]
+    fn undo_ends_the_stroke() {
+        let mut map = map();
+        let mut history = History::default();
+        history.set_voxel(&mut map, UVec3::ZERO, ground(1));
+        history.set_voxel(&mut map, UVec3::X, ground(1));
+        history.undo(&mut map);
+        assert!(!history.is_stroke_open());
+        history.set_voxel(&mut map, UVec3::Y, ground(1));
+
+        // The undone stroke is dropped rather than added to.
+        assert_eq!(history.commands().len(), 1);
+        assert!(!history.can_redo());
+        assert_eq!(map.get(UVec3::ZERO), Some(Voxel::EMPTY));
+    }
+
+    #[test]
+    fn forgets_the_oldest_commands() {
+        let mut map = VoxelMap::new(Dimensions::new(MAX_HISTORY as u32 + 10, 1, 1));
+        let mut history = History::default();
+        for x in 0..MAX_HISTORY as u32 + 10 {
+            history.set_voxel(&mut map, UVec3::new(x, 0, 0), ground(1));
+            history.end_stroke();
+        }
+
+        assert_eq!(history.commands().len(), MAX_HISTORY);
+        assert_eq!(history.applied(), MAX_HISTORY);
+        while history.undo(&mut map) {}
+        // The first ten could not be undone.
+        assert_eq!(map.get(UVec3::new(9, 0, 0)), Some(ground(1)));
+        assert_eq!(map.get(UVec3::new(10, 0, 0)), Some(Voxel::EMPTY));
+    }
+
+    #[test]
+    fn jumps_through_history() {
+        let mut map = map();
+        let mut history = History::default();
+        let mut states = vec![map.clone()];
+        for x in 0..4 {
+            history.set_voxel(&mut map, UVec3::new(x, 0, 0), ground(1));
+            history.end_stroke();
+            states.push(map.clone());
+        }
+
+        history.jump(&mut map, 1);
+        assert_eq!(history.applied(), 1);
+        assert_eq!(map, states[1]);
+        history.jump(&mut map, 3);
+        assert_eq!(map, states[3]);
+        history.jump(&mut map, 99);
+        assert_eq!(history.applied(), 4);
+        assert_eq!(map, states[4]);
+        history.jump(&mut map, 0);
+        assert_eq!(map, states[0]);
+    }
+
+    #[test]
+    fn revision_follows_undo_and_redo() {
+        let mut map = map();
+        let mut history = History::default();
+        let start = history.revision();
+        history.set_voxel(&mut map, UVec3::ZERO, ground(1));
+        let first = history.revision();
+        history.set_voxel(&mut map, UVec3::X, ground(1));
+        // Adding to the open stroke is a new state of the map.
+        assert_ne!(history.revision(), first);
+        let stroke = history.revision();
+
+        history.undo(&mut map);
+        assert_eq!(history.revision(), start);
+        history.redo(&mut map);
+        assert_eq!(history.revision(), stroke);
+        history.undo(&mut map);
+        history.set_voxel(&mut map, UVec3::Y, ground(1));
+        assert_ne!(history.revision(), start);
+        assert_ne!(history.revision(), stroke);
+    }
+
+    #[test]
+    fn records_changed_voxels() {
+        let mut map = map();
+        let mut history = History::default();
+        history.set_voxel(&mut map, UVec3::ZERO, ground(1));
+        history.set_voxel(&mut map, UVec3::X, ground(1));
+        history.set_palette(&mut map, Palette::generated(2));
+        assert_eq!(
+            history.take_changes(),
+            MapChanges {
+                voxels: [UVec3::ZERO, UVec3::X].into(),
+                resized: false,
+            }
+        );
+        assert_eq!(history.take_changes(), MapChanges::default());
+
+        history.undo(&mut map);
+        history.undo(&mut map);
+        assert_eq!(
+            history.take_changes().voxels,
+            [UVec3::ZERO, UVec3::X].into()
+        );
+        history.resize(&mut map, Dimensions::new(2, 2, 2));
+        assert!(history.take_changes().resized);
+    }
+}
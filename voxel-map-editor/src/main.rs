@@ -1,24 +1,46 @@
 mod camera;
+mod file;
+mod history;
 mod map;
 mod menu;
 mod overlay;
+mod palette;
+mod tools;
 
-use crate::camera::CameraPlugin;
+use crate::camera::{CameraPlugin, EditorCamera};
+use crate::file::FilePlugin;
+use crate::history::HistoryPlugin;
 use crate::map::MapPlugin;
 use crate::menu::MenuPlugin;
+use crate::overlay::OverlayPlugin;
+use crate::palette::PalettePlugin;
+use crate::tools::ToolsPlugin;
 use bevy::prelude::*;
+use bevy_builder::{DialogPlugin, LocalizationPlugin, UiStylePlugin, UiThemePlugin};
 use bevy_obj::ObjPlugin;
-use bevy_ui_text_input::TextInputPlugin;
 
 fn main() {
     App::new()
         .add_plugins((
-            DefaultPlugins,
+            // Closing the window goes through `FilePlugin`, which asks about
+            // unsaved changes first.
+            DefaultPlugins.set(WindowPlugin {
+                close_when_requested: false,
+                ..default()
+            }),
             MenuPlugin,
-            TextInputPlugin,
+            DialogPlugin,
+            UiThemePlugin,
+            UiStylePlugin::new("ui.style.json5"),
+            LocalizationPlugin::new("locales", ["en", "de"]),
             MapPlugin,
             ObjPlugin,
             CameraPlugin,
+            FilePlugin,
+            HistoryPlugin,
+            OverlayPlugin,
+            PalettePlugin,
+            ToolsPlugin,
         ))
         .init_state::<AppState>()
         .add_systems(Startup, setup_camera)
@@ -40,8 +62,5 @@ fn setup_state(mut app_state: ResMut<NextState<AppState>>) {
 }
 
 fn setup_camera(mut commands: Commands) {
-    commands.spawn((
-        Camera3d::default(),
-        Transform::from_xyz(-2.5, 4.5, 9.0).looking_at(Vec3::ZERO, Vec3::Y),
-    ));
+    commands.spawn(EditorCamera::default());
 }
@@ -1,147 +1,251 @@
 use crate::AppState;
+use crate::file::CurrentFile;
+use crate::history::History;
 use bevy::pbr::wireframe::{WireframeConfig, WireframePlugin};
 use bevy::prelude::*;
+use std::collections::{HashMap, HashSet};
+use voxel_map::{
+    CHUNK_SIZE, Dimensions, MAX_MATERIALS, Palette, Voxel, VoxelMap, VoxelMaterial, chunk_meshes,
+};
 
 pub struct MapPlugin;
 
 impl Plugin for MapPlugin {
     fn build(&self, app: &mut App) {
-        app.add_systems(OnEnter(AppState::Generating), generate_map)
+        app.init_resource::<PaletteMaterials>()
+            .add_event::<EditVoxel>()
+            .add_event::<ResizeMap>()
+            .add_event::<EditPalette>()
+            .add_systems(OnEnter(AppState::Generating), generate_map)
+            .add_systems(OnExit(AppState::InApp), despawn_map)
+            .add_systems(
+                Update,
+                (
+                    apply_edits_system,
+                    palette_materials_system,
+                    mesh_map_system,
+                )
+                    .chain()
+                    .run_if(in_state(AppState::InApp)),
+            )
             .add_plugins(WireframePlugin::default());
     }
 }
 
-#[derive(Clone, Copy)]
-struct Dimensions {
-    width: u32,
-    length: u32,
-    height: u32,
-}
+/// The map being edited. The chunks around the voxels [`History`] changed are
+/// remeshed, and all of them when it is inserted again to replace the map.
+#[derive(Resource, Debug)]
+pub struct EditedMap(pub VoxelMap);
+
+impl EditedMap {
+    /// Where the cube for the voxel at `position` sits in the scene. The map
+    /// is centred on the origin, with its bottom layer at `y = 0`.
+    pub fn translation(&self, position: UVec3) -> Vec3 {
+        let dimensions = self.0.dimensions();
+        let origin = Vec3::new(dimensions.width as f32, 0.0, dimensions.length as f32) / 2.0;
+        position.as_vec3() - origin
+    }
 
-impl Dimensions {
-    fn magnitude(&self) -> u32 {
-        self.width * self.length * self.height
+    /// The voxel whose cube contains `point` in the scene, if it is inside the map.
+    pub fn position(&self, point: Vec3) -> Option<UVec3> {
+        let position = (point - self.translation(UVec3::ZERO) + Vec3::splat(0.5)).floor();
+        UVec3::try_from(position.as_ivec3())
+            .ok()
+            .filter(|position| self.0.dimensions().contains(*position))
     }
-}
 
-impl From<(u32, u32, u32)> for Dimensions {
-    fn from((width, length, height): (u32, u32, u32)) -> Self {
-        Dimensions {
-            width,
-            length,
-            height,
-        }
+    /// The colour voxels of `material` are drawn in.
+    pub fn material_color(&self, material: u8) -> Color {
+        self.0
+            .palette
+            .get(material)
+            .map_or(VoxelMaterial::default().color, |material| material.color)
+            .into()
     }
 }
 
-#[derive(Default)]
-pub struct MapBuilder {
-    width: u32,
-    length: u32,
-    height: u32,
+/// One mesh covering the voxels of one material in a chunk of the map.
+#[derive(Component, Debug, Clone, Copy)]
+pub struct Chunk {
+    /// In chunks.
+    pub position: UVec3,
+    pub material: u8,
 }
 
-impl MapBuilder {
-    pub fn width(mut self, width: u32) -> Self {
-        self.width = width;
-        self
-    }
-
-    pub fn length(mut self, length: u32) -> Self {
-        self.length = length;
-        self
-    }
-
-    pub fn height(mut self, height: u32) -> Self {
-        self.height = height;
-        self
-    }
-
-    pub fn build(self) -> Map {
-        let nodes: Vec<Tile> = (0..self.height)
-            .flat_map(move |z| {
-                (0..self.length).flat_map(move |y| {
-                    (0..self.width).map(move |x| {
-                        Tile::new(
-                            x as f32 - self.width as f32 / 2.0,
-                            y as f32 - self.width as f32 / 2.0,
-                            z as f32,
-                        )
-                    })
-                })
-            })
-            .collect();
-
-        Map {
-            dimensions: Dimensions {
-                width: self.width,
-                length: self.length,
-                height: self.height,
-            },
-            nodes,
-        }
-    }
+/// One material for every possible palette index, kept up to date with the
+/// palette of the map. Chunks keep their handle when the palette changes.
+#[derive(Resource, Default)]
+struct PaletteMaterials {
+    palette: Option<Palette>,
+    handles: Vec<Handle<StandardMaterial>>,
 }
 
-#[derive(Component, Clone, Copy)]
-struct Tile {
-    x: f32,
-    y: f32,
-    z: f32,
+#[derive(Event)]
+pub struct GenerateMapEvent(pub VoxelMap);
+
+/// Replaces one voxel of the [`EditedMap`]. Editing tools send these instead
+/// of writing to the map themselves, so every change can be undone.
+#[derive(Event, Debug, Clone, Copy)]
+pub struct EditVoxel {
+    pub position: UVec3,
+    pub voxel: Voxel,
 }
 
-impl From<Tile> for (f32, f32, f32) {
-    fn from(tile: Tile) -> Self {
-        (tile.x, tile.y, tile.z)
-    }
-}
+#[derive(Event, Debug, Clone, Copy)]
+pub struct ResizeMap(pub Dimensions);
 
-impl Tile {
-    fn new(x: f32, y: f32, z: f32) -> Self {
-        Tile { x, y, z }
-    }
-}
+/// Replaces the palette of the [`EditedMap`].
+#[derive(Event, Debug, Clone)]
+pub struct EditPalette(pub Palette);
 
-#[derive(Clone)]
-pub struct Map {
-    dimensions: Dimensions,
-    nodes: Vec<Tile>,
+fn generate_map(
+    mut event_reader: EventReader<GenerateMapEvent>,
+    mut commands: Commands,
+    mut wireframe_config: ResMut<WireframeConfig>,
+    mut app_state: ResMut<NextState<AppState>>,
+    mut current: ResMut<CurrentFile>,
+    history: Res<History>,
+) {
+    wireframe_config.global = !wireframe_config.global;
+    if let Some(event) = event_reader.read().last() {
+        *current = CurrentFile::new(None, history.revision());
+        commands.insert_resource(EditedMap(event.0.clone()));
+        app_state.set(AppState::InApp);
+    }
 }
 
-impl Map {
-    pub fn builder() -> MapBuilder {
-        MapBuilder::default()
+pub fn apply_edits_system(
+    mut edits: EventReader<EditVoxel>,
+    mut resizes: EventReader<ResizeMap>,
+    mut palettes: EventReader<EditPalette>,
+    mut map: ResMut<EditedMap>,
+    mut history: ResMut<History>,
+) {
+    for edit in edits.read() {
+        if map
+            .0
+            .get(edit.position)
+            .is_some_and(|voxel| voxel != edit.voxel)
+        {
+            history.set_voxel(&mut map.0, edit.position, edit.voxel);
+        }
+    }
+    for ResizeMap(dimensions) in resizes.read() {
+        if map.0.dimensions() != *dimensions {
+            history.resize(&mut map.0, *dimensions);
+        }
+    }
+    for EditPalette(palette) in palettes.read() {
+        if map.0.palette != *palette {
+            history.set_palette(&mut map.0, palette.clone());
+        }
     }
 }
 
-#[derive(Event)]
-pub struct GenerateMapEvent(pub Map);
-
-impl GenerateMapEvent {
-    fn map(&self) -> Map {
-        self.0.clone()
+fn palette_materials_system(
+    map: Res<EditedMap>,
+    mut synced: ResMut<PaletteMaterials>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    asset_server: Res<AssetServer>,
+) {
+    if !map.is_changed() || synced.palette.as_ref() == Some(&map.0.palette) {
+        return;
+    }
+    let old = synced.palette.replace(map.0.palette.clone());
+    for index in 0..MAX_MATERIALS {
+        let material = map.0.palette.get(index as u8);
+        if old
+            .as_ref()
+            .is_some_and(|old| old.get(index as u8) == material)
+        {
+            continue;
+        }
+        let material = material
+            .cloned()
+            .unwrap_or_default()
+            .to_standard_material(&asset_server);
+        match synced.handles.get(index) {
+            Some(handle) => {
+                if let Some(old) = materials.get_mut(handle) {
+                    *old = material;
+                }
+            }
+            None => {
+                let handle = materials.add(material);
+                synced.handles.push(handle);
+            }
+        }
     }
 }
 
-fn generate_map(
-    mut event_reader: EventReader<GenerateMapEvent>,
+/// Remeshes the chunks around the voxels that changed since the last run, or
+/// all of them when the map was replaced or resized.
+fn mesh_map_system(
+    map: Res<EditedMap>,
+    mut history: ResMut<History>,
+    chunks: Query<(Entity, &Chunk)>,
+    materials: Res<PaletteMaterials>,
     mut commands: Commands,
-    asset_server: Res<AssetServer>,
     mut meshes: ResMut<Assets<Mesh>>,
-    mut materials: ResMut<Assets<StandardMaterial>>,
-    mut wireframe_config: ResMut<WireframeConfig>,
 ) {
-    wireframe_config.global = !wireframe_config.global;
-    for event in event_reader.read() {
-        for tile in event.map().nodes.into_iter() {
-            // Process each tile here
-            let cuboid = meshes.add(Cuboid::default());
-            let (x, y, z): (f32, f32, f32) = tile.into();
+    if !map.is_changed() {
+        return;
+    }
+
+    let mut spawned: HashMap<UVec3, Vec<(u8, Entity)>> = HashMap::new();
+    for (entity, chunk) in &chunks {
+        spawned
+            .entry(chunk.position)
+            .or_default()
+            .push((chunk.material, entity));
+    }
+    let changes = history.take_changes();
+    let dirty: HashSet<UVec3> = if map.is_added() || changes.resized {
+        for (_, entity) in spawned.drain().flat_map(|(_, chunks)| chunks) {
+            commands.entity(entity).despawn();
+        }
+        map.0.chunks().collect()
+    } else {
+        let dimensions = map.0.dimensions();
+        changes
+            .voxels
+            .into_iter()
+            .flat_map(|position| dimensions.chunks_around(position))
+            .collect()
+    };
+
+    for position in dirty {
+        let mut chunk_meshes = chunk_meshes(&map.0, position);
+        for (material, entity) in spawned.remove(&position).unwrap_or_default() {
+            match chunk_meshes.remove(&material) {
+                Some(mesh) => {
+                    commands.entity(entity).insert(Mesh3d(meshes.add(mesh)));
+                }
+                None => commands.entity(entity).despawn(),
+            }
+        }
+        // Mesh vertices start at the corner of the chunk, half a voxel before
+        // the centre of its first voxel.
+        let translation = map.translation(position * CHUNK_SIZE) - Vec3::splat(0.5);
+        for (material, mesh) in chunk_meshes {
             commands.spawn((
-                Mesh3d(cuboid),
-                MeshMaterial3d(materials.add(Color::srgb_u8(124, 144, 255))),
-                Transform::from_translation(Vec3::new(x, y, z)),
+                Chunk { position, material },
+                Mesh3d(meshes.add(mesh)),
+                MeshMaterial3d(materials.handles[material as usize].clone()),
+                Transform::from_translation(translation),
             ));
         }
     }
 }
+
+fn despawn_map(
+    mut commands: Commands,
+    chunks: Query<Entity, With<Chunk>>,
+    mut history: ResMut<History>,
+) {
+    commands.remove_resource::<EditedMap>();
+    history.clear();
+    for entity in &chunks {
+        commands.entity(entity).despawn();
+    }
+}
@@ -1,33 +1,332 @@
+use crate::AppState;
+use crate::file::{
+    CurrentFile, FileAction, RecentFiles, show_export_dialog, show_open_dialog, show_save_as_dialog,
+};
+use crate::history::{History, MapCommand};
+use crate::map::{EditedMap, ResizeMap};
+use crate::palette::PalettePanel;
+use crate::tools::{Brush, Tool};
 use bevy::prelude::*;
 use bevy_builder::*;
+use voxel_map::Dimensions;
 
-struct OverlayPlugin;
+pub struct OverlayPlugin;
 
 impl Plugin for OverlayPlugin {
     fn build(&self, app: &mut App) {
-        app.add_systems(Startup, setup_overlay);
+        app.add_systems(OnEnter(AppState::InApp), setup_overlay)
+            .add_systems(OnExit(AppState::InApp), despawn_overlay)
+            .add_systems(
+                Update,
+                (
+                    file_title_system,
+                    tool_button_system,
+                    brush_swatch_system,
+                    history_panel_system,
+                )
+                    .run_if(in_state(AppState::InApp)),
+            );
     }
 }
 
+#[derive(Component)]
+struct Overlay;
+
+/// Shows the name of the open map, and whether it has unsaved changes.
+#[derive(Component)]
+struct FileTitle;
+
+#[derive(Component)]
+struct ToolButton(Tool);
+
+/// Shows the colour of the brush material.
+#[derive(Component)]
+struct BrushSwatch;
+
+#[derive(Component)]
+struct BrushLabel;
+
+/// Lists the latest commands in the [`History`]. Clicking one undoes or
+/// redoes up to it.
+#[derive(Component)]
+struct HistoryPanel;
+
+/// The history panel lists this many of the latest commands.
+const HISTORY_PANEL_LENGTH: usize = 15;
+
+#[derive(Component, Default)]
+struct ResizeDialog;
+
+#[derive(Component)]
+struct ResizeInput(usize);
+
 /// This system will be an overlay similar to the one in magicka voxel editor.
-/// Things we need:
-/// - Map Dimension Input
+/// So far it is the navbar with the file actions and resizing, the toolbar,
+/// the palette and the history. Things we still need:
 /// - Tile Dimension Input
-/// - Navbar for importing tiles or old maps.
 fn setup_overlay(mut commands: Commands) {
-    let canvas_node = Node::builder()
-        .width(Val::Percent(100.0))
-        .height(Val::Percent(100.0))
-        .build();
+    let button_node = Node::builder().styled("navbar-button");
+
+    Widget::panel(Node::builder().styled("navbar"))
+        .insert((Overlay, TabGroup::default()))
+        .child(
+            Widget::button(button_node.clone(), LocalizedText::new("file.new")).observe(
+                |_: Trigger<Activate>, mut actions: EventWriter<FileAction>| {
+                    actions.write(FileAction::New);
+                },
+            ),
+        )
+        .child(
+            Widget::button(button_node.clone(), LocalizedText::new("file.open")).observe(
+                |_: Trigger<Activate>, mut dialogs: Dialogs, recent: Res<RecentFiles>| {
+                    show_open_dialog(&mut dialogs, &recent);
+                },
+            ),
+        )
+        .child(
+            Widget::button(button_node.clone(), LocalizedText::new("file.save")).observe(
+                |_: Trigger<Activate>, mut actions: EventWriter<FileAction>| {
+                    actions.write(FileAction::Save);
+                },
+            ),
+        )
+        .child(
+            Widget::button(button_node.clone(), LocalizedText::new("file.save_as")).observe(
+                |_: Trigger<Activate>, mut dialogs: Dialogs, current: Res<CurrentFile>| {
+                    show_save_as_dialog(&mut dialogs, &current);
+                },
+            ),
+        )
+        .child(
+            Widget::button(button_node, LocalizedText::new("file.export")).observe(
+                |_: Trigger<Activate>,
+                 mut dialogs: Dialogs,
+                 current: Res<CurrentFile>,
+                 map: Res<EditedMap>| {
+                    show_export_dialog(&mut dialogs, &current, &map);
+                },
+            ),
+        )
+        .child(
+            Widget::button(
+                Node::builder().styled("navbar-button"),
+                LocalizedText::new("map.resize"),
+            )
+            .observe(show_resize_dialog),
+        )
+        .child(
+            Widget::label(LocalizedText::new("file.untitled"))
+                .insert((Node::builder().styled("navbar-title"), FileTitle)),
+        )
+        .spawn(&mut commands);
 
-    let bar_node = Node::builder()
-        .width(Val::Percent(100.0))
-        .height(Val::Percent(5.0))
-        .build();
+    Widget::panel(Node::builder().styled("toolbar"))
+        .insert((Overlay, TabGroup::new(1)))
+        .children(Tool::ALL.map(|tool| {
+            Widget::button(
+                Node::builder().styled("tool-button"),
+                LocalizedText::new(tool.key()),
+            )
+            .insert(ToolButton(tool))
+            .observe(move |_: Trigger<Activate>, mut current: ResMut<Tool>| {
+                current.set_if_neq(tool);
+            })
+        }))
+        .child(Widget::new((
+            Node::builder().styled("brush-swatch"),
+            BackgroundColor::default(),
+            BrushSwatch,
+        )))
+        .child(Widget::label(LocalizedText::new("tool.material")).insert(BrushLabel))
+        .spawn(&mut commands);
 
-    let canvas_bundle = (canvas_node);
+    Widget::panel(Node::builder().styled("palette-panel"))
+        .insert((Overlay, PalettePanel, TabGroup::new(3)))
+        .spawn(&mut commands);
 
-    let canvas = commands.spawn(canvas_bundle).id();
+    Widget::panel(Node::builder().styled("history-panel"))
+        .insert((Overlay, HistoryPanel, TabGroup::new(2)))
+        .spawn(&mut commands);
+}
+
+fn despawn_overlay(mut commands: Commands, overlay: Query<Entity, With<Overlay>>) {
+    for entity in &overlay {
+        commands.entity(entity).despawn();
+    }
+}
+
+// These also run when the overlay is respawned, since the resources they
+// show may not have changed since it was last up.
 
-    let bar = commands.spawn(bar_node).insert(ChildOf(canvas)).id();
+fn file_title_system(
+    current: Res<CurrentFile>,
+    title: Single<(&mut LocalizedText, Ref<FileTitle>)>,
+) {
+    let (mut title, marker) = title.into_inner();
+    if !current.is_changed() && !marker.is_added() {
+        return;
+    }
+    let text = match (current.name(), current.is_dirty()) {
+        (Some(name), false) => LocalizedText::new("file.title").with_arg("name", name),
+        (Some(name), true) => LocalizedText::new("file.title_modified").with_arg("name", name),
+        (None, false) => LocalizedText::new("file.untitled"),
+        (None, true) => LocalizedText::new("file.untitled_modified"),
+    };
+    title.set_if_neq(text);
+}
+
+fn tool_button_system(
+    mut commands: Commands,
+    tool: Res<Tool>,
+    buttons: Query<(Entity, Ref<ToolButton>)>,
+) {
+    if !tool.is_changed() && !buttons.iter().any(|(_, button)| button.is_added()) {
+        return;
+    }
+    for (entity, button) in &buttons {
+        if button.0 == *tool {
+            commands.entity(entity).insert(Selected);
+        } else {
+            commands.entity(entity).remove::<Selected>();
+        }
+    }
+}
+
+fn brush_swatch_system(
+    brush: Res<Brush>,
+    map: Res<EditedMap>,
+    swatch: Single<(&mut BackgroundColor, Ref<BrushSwatch>)>,
+    mut label: Single<&mut LocalizedText, With<BrushLabel>>,
+) {
+    let (mut swatch, marker) = swatch.into_inner();
+    if !brush.is_changed() && !map.is_changed() && !marker.is_added() {
+        return;
+    }
+    swatch.set_if_neq(BackgroundColor(map.material_color(brush.0.material)));
+    label.set_if_neq(
+        LocalizedText::new("tool.material").with_arg("index", u32::from(brush.0.material)),
+    );
+}
+
+fn history_panel_system(
+    mut commands: Commands,
+    history: Res<History>,
+    panel: Single<(Entity, Ref<HistoryPanel>)>,
+) {
+    let (panel, marker) = panel.into_inner();
+    if !history.is_changed() && !marker.is_added() {
+        return;
+    }
+
+    commands.entity(panel).despawn_related::<Children>();
+    let first = history
+        .commands()
+        .len()
+        .saturating_sub(HISTORY_PANEL_LENGTH);
+    let entries = history.commands()[first..]
+        .iter()
+        .enumerate()
+        .map(|(index, command)| (first + index + 1, history_label(command)));
+    let entries = (first == 0)
+        .then(|| (0, LocalizedText::new("history.start")))
+        .into_iter()
+        .chain(entries);
+
+    for (applied, label) in entries {
+        let mut entry = Widget::button(Node::builder().styled("history-entry"), label).observe(
+            move |_: Trigger<Activate>,
+                  mut history: ResMut<History>,
+                  mut map: ResMut<EditedMap>| {
+                if history.applied() != applied {
+                    history.jump(&mut map.0, applied);
+                }
+            },
+        );
+        if applied == history.applied() {
+            entry = entry.insert(Selected);
+        }
+        entry.spawn_child_of(panel, &mut commands);
+    }
+}
+
+fn history_label(command: &MapCommand) -> LocalizedText {
+    match command {
+        MapCommand::SetVoxels(changes) => {
+            let key = if changes.iter().all(|change| change.before.is_empty()) {
+                "history.place"
+            } else if changes.iter().all(|change| change.after.is_empty()) {
+                "history.erase"
+            } else {
+                "history.paint"
+            };
+            LocalizedText::new(key).with_arg("count", changes.len() as u32)
+        }
+        MapCommand::Resize { dimensions, .. } => LocalizedText::new("history.resize")
+            .with_arg("width", dimensions.width)
+            .with_arg("height", dimensions.height)
+            .with_arg("length", dimensions.length),
+        MapCommand::SetPalette { .. } => LocalizedText::new("history.palette"),
+    }
+}
+
+/// Asks for new dimensions, filled in with the current ones.
+fn show_resize_dialog(_: Trigger<Activate>, mut dialogs: Dialogs, map: Res<EditedMap>) {
+    let dimensions = map.0.dimensions();
+    let rows = [
+        ("dimensions.width", dimensions.width),
+        ("dimensions.length", dimensions.length),
+        ("dimensions.height", dimensions.height),
+    ];
+
+    let window = Widget::panel(Node::builder().styled("dialog-window"))
+        .child(Widget::label(LocalizedText::new("map.resize_title")))
+        .children(rows.into_iter().enumerate().map(|(index, (label, value))| {
+            Widget::panel(Node::builder().styled("dialog-row"))
+                .child(
+                    Widget::label(LocalizedText::new(label))
+                        .insert(Node::builder().styled("dimension-label")),
+                )
+                .child(
+                    Widget::number_field(Node::builder().styled("dimension-input"), value)
+                        .insert(ResizeInput(index)),
+                )
+        }))
+        .child(
+            Widget::panel(Node::builder().styled("dialog-buttons"))
+                .child(
+                    Widget::button(
+                        Node::builder().styled("dialog-button"),
+                        LocalizedText::new("map.resize_apply"),
+                    )
+                    .observe(apply_resize),
+                )
+                .child(
+                    Widget::button(
+                        Node::builder().styled("dialog-button"),
+                        LocalizedText::new("file.cancel"),
+                    )
+                    .observe(|_: Trigger<Activate>, mut dialogs: Dialogs| {
+                        dialogs.close::<ResizeDialog>()
+                    }),
+                ),
+        );
+    dialogs.open::<ResizeDialog>(window, true);
+}
+
+fn apply_resize(
+    _: Trigger<Activate>,
+    inputs: Query<(&NumberField, &ResizeInput)>,
+    mut dialogs: Dialogs,
+    mut resizes: EventWriter<ResizeMap>,
+) {
+    let mut values = [0; 3];
+    for (field, input) in &inputs {
+        values[input.0] = field.value;
+    }
+    if values.contains(&0) {
+        return;
+    }
+    let [width, length, height] = values;
+    dialogs.close::<ResizeDialog>();
+    resizes.write(ResizeMap(Dimensions::new(width, height, length)));
 }
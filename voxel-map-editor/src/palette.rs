@@ -0,0 +1,392 @@
+use crate::AppState;
+use crate::map::{EditPalette, EditedMap};
+use crate::tools::Brush;
+use bevy::prelude::*;
+use bevy_builder::*;
+use std::path::{Path, PathBuf};
+use voxel_map::Palette;
+
+/// Where palettes are imported from and exported to, unless another path is typed.
+const PALETTE_DIRECTORY: &str = "palettes";
+
+/// The palette panel of the overlay, and the dialogs to edit a material and
+/// to import or export the whole palette.
+pub struct PalettePlugin;
+
+impl Plugin for PalettePlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(
+            Update,
+            palette_panel_system.run_if(in_state(AppState::InApp)),
+        );
+    }
+}
+
+/// Lists the materials of the edited map. Clicking one picks it for the brush.
+#[derive(Component)]
+pub struct PalettePanel;
+
+#[derive(Component, Default)]
+struct MaterialDialog;
+
+#[derive(Component, Default)]
+struct PaletteFileDialog;
+
+#[derive(Component, Clone, Copy, PartialEq, Eq)]
+enum MaterialInput {
+    Name,
+    Color,
+    Roughness,
+    Metallic,
+    Emissive,
+    Texture,
+}
+
+#[derive(Component)]
+struct PalettePathInput;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum PaletteFileAction {
+    Import,
+    Export,
+}
+
+fn palette_panel_system(
+    mut commands: Commands,
+    map: Res<EditedMap>,
+    brush: Res<Brush>,
+    panel: Single<(Entity, Ref<PalettePanel>)>,
+    mut shown: Local<Option<Palette>>,
+) {
+    let (panel, marker) = panel.into_inner();
+    // The map changes on every edit, so only rebuild when the palette did.
+    if !marker.is_added() && !brush.is_changed() && shown.as_ref() == Some(&map.0.palette) {
+        return;
+    }
+    *shown = Some(map.0.palette.clone());
+
+    commands.entity(panel).despawn_related::<Children>();
+    Widget::panel(Node::builder().styled("palette-swatches"))
+        .children(map.0.palette.iter().enumerate().map(|(index, material)| {
+            let index = index as u8;
+            let border = if index == brush.0.material {
+                Color::WHITE
+            } else {
+                Color::NONE
+            };
+            Widget::new((
+                Node::builder().styled("palette-swatch"),
+                Button,
+                BackgroundColor(material.color.into()),
+                BorderColor(border),
+            ))
+            .observe(move |_: Trigger<Activate>, mut brush: ResMut<Brush>| {
+                brush.0.material = index;
+            })
+        }))
+        .spawn_child_of(panel, &mut commands);
+
+    Widget::panel(Node::builder().styled("palette-buttons"))
+        .child(
+            Widget::button(
+                Node::builder().styled("palette-button"),
+                LocalizedText::new("palette.edit"),
+            )
+            .observe(show_material_dialog),
+        )
+        .child(
+            Widget::button(
+                Node::builder().styled("palette-button"),
+                LocalizedText::new("palette.add"),
+            )
+            .observe(add_material),
+        )
+        .child(
+            Widget::button(
+                Node::builder().styled("palette-button"),
+                LocalizedText::new("palette.import"),
+            )
+            .observe(
+                |_: Trigger<Activate>, mut dialogs: Dialogs, map: Res<EditedMap>| {
+                    show_palette_file_dialog(&mut dialogs, &map, PaletteFileAction::Import);
+                },
+            ),
+        )
+        .child(
+            Widget::button(
+                Node::builder().styled("palette-button"),
+                LocalizedText::new("palette.export"),
+            )
+            .observe(
+                |_: Trigger<Activate>, mut dialogs: Dialogs, map: Res<EditedMap>| {
+                    show_palette_file_dialog(&mut dialogs, &map, PaletteFileAction::Export);
+                },
+            ),
+        )
+        .spawn_child_of(panel, &mut commands);
+}
+
+/// Appends a copy of the brush material and picks it.
+fn add_material(
+    _: Trigger<Activate>,
+    map: Res<EditedMap>,
+    mut brush: ResMut<Brush>,
+    mut edits: EventWriter<EditPalette>,
+) {
+    let mut palette = map.0.palette.clone();
+    let material = palette.get(brush.0.material).cloned().unwrap_or_default();
+    if let Some(index) = palette.push(material) {
+        brush.0.material = index;
+        edits.write(EditPalette(palette));
+    }
+}
+
+/// Edits the brush material. Roughness and metallic are in percent.
+fn show_material_dialog(
+    _: Trigger<Activate>,
+    mut dialogs: Dialogs,
+    map: Res<EditedMap>,
+    brush: Res<Brush>,
+) {
+    let index = brush.0.material;
+    let material = map.0.palette.get(index).cloned().unwrap_or_default();
+    let percent = |value: f32| (value * 100.0).round() as u32;
+    let rows = [
+        (
+            "palette.name",
+            Widget::text_field(Node::builder().styled("material-input"), material.name)
+                .insert(MaterialInput::Name),
+        ),
+        (
+            "palette.color",
+            Widget::text_field(
+                Node::builder().styled("material-input"),
+                material.color.to_hex(),
+            )
+            .insert(MaterialInput::Color),
+        ),
+        (
+            "palette.roughness",
+            Widget::number_field(
+                Node::builder().styled("material-input"),
+                percent(material.roughness),
+            )
+            .insert(MaterialInput::Roughness),
+        ),
+        (
+            "palette.metallic",
+            Widget::number_field(
+                Node::builder().styled("material-input"),
+                percent(material.metallic),
+            )
+            .insert(MaterialInput::Metallic),
+        ),
+        (
+            "palette.emissive",
+            Widget::text_field(
+                Node::builder().styled("material-input"),
+                material.emissive.to_hex(),
+            )
+            .insert(MaterialInput::Emissive),
+        ),
+        (
+            "palette.texture",
+            Widget::text_field(
+                Node::builder().styled("material-input"),
+                material.texture.unwrap_or_default(),
+            )
+            .insert(MaterialInput::Texture),
+        ),
+    ];
+
+    let window = Widget::panel(Node::builder().styled("dialog-window"))
+        .child(Widget::label(
+            LocalizedText::new("palette.edit_title").with_arg("index", u32::from(index)),
+        ))
+        .children(rows.into_iter().map(|(label, field)| {
+            Widget::panel(Node::builder().styled("dialog-row"))
+                .child(
+                    Widget::label(LocalizedText::new(label))
+                        .insert(Node::builder().styled("material-label")),
+                )
+                .child(field)
+        }))
+        .child(
+            Widget::panel(Node::builder().styled("dialog-buttons"))
+                .child(
+                    Widget::button(
+                        Node::builder().styled("dialog-button"),
+                        LocalizedText::new("palette.apply"),
+                    )
+                    .observe(apply_material),
+                )
+                .child(
+                    Widget::button(
+                        Node::builder().styled("dialog-button"),
+                        LocalizedText::new("file.cancel"),
+                    )
+                    .observe(|_: Trigger<Activate>, mut dialogs: Dialogs| {
+                        dialogs.close::<MaterialDialog>()
+                    }),
+                ),
+        );
+    dialogs.open::<MaterialDialog>(window, true);
+}
+
+/// Keeps the dialog open while a colour does not parse.
+fn apply_material(
+    _: Trigger<Activate>,
+    texts: Query<(&TextField, &MaterialInput)>,
+    numbers: Query<(&NumberField, &MaterialInput)>,
+    map: Res<EditedMap>,
+    brush: Res<Brush>,
+    mut dialogs: Dialogs,
+    mut edits: EventWriter<EditPalette>,
+) {
+    let index = brush.0.material;
+    let mut material = map.0.palette.get(index).cloned().unwrap_or_default();
+    for (field, input) in &texts {
+        let value = field.value.trim();
+        match input {
+            MaterialInput::Name => material.name = value.to_string(),
+            MaterialInput::Color => match Srgba::hex(value) {
+                Ok(color) => material.color = color,
+                Err(_) => return,
+            },
+            MaterialInput::Emissive => match Srgba::hex(value) {
+                Ok(color) => material.emissive = color,
+                Err(_) => return,
+            },
+            MaterialInput::Texture => {
+                material.texture = Some(value.to_string()).filter(|path| !path.is_empty());
+            }
+            MaterialInput::Roughness | MaterialInput::Metallic => {}
+        }
+    }
+    for (field, input) in &numbers {
+        let value = field.value.min(100) as f32 / 100.0;
+        match input {
+            MaterialInput::Roughness => material.roughness = value,
+            MaterialInput::Metallic => material.metallic = value,
+            _ => {}
+        }
+    }
+
+    dialogs.close::<MaterialDialog>();
+    let mut palette = map.0.palette.clone();
+    if palette.set(index, material).is_some() {
+        edits.write(EditPalette(palette));
+    }
+}
+
+/// Asks for a `.vpal` file, listing those in `palettes/`.
+fn show_palette_file_dialog(dialogs: &mut Dialogs, map: &EditedMap, action: PaletteFileAction) {
+    let name = Some(map.0.metadata.name.as_str())
+        .filter(|name| !name.is_empty())
+        .unwrap_or("palette");
+    let path = Path::new(PALETTE_DIRECTORY).join(format!("{name}.vpal"));
+    let mut paths: Vec<PathBuf> = std::fs::read_dir(PALETTE_DIRECTORY)
+        .into_iter()
+        .flatten()
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| {
+            path.extension()
+                .is_some_and(|extension| extension == "vpal")
+        })
+        .collect();
+    paths.sort();
+
+    let (title, confirm) = match action {
+        PaletteFileAction::Import => ("palette.import_title", "palette.import"),
+        PaletteFileAction::Export => ("palette.export_title", "palette.export"),
+    };
+    let window = Widget::panel(Node::builder().styled("dialog-window"))
+        .child(Widget::label(LocalizedText::new(title)))
+        .child(
+            Widget::text_field(
+                Node::builder().styled("path-input"),
+                path.display().to_string(),
+            )
+            .insert(PalettePathInput),
+        )
+        .child(
+            Widget::panel(Node::builder().styled("file-list")).children(paths.into_iter().map(
+                |path| {
+                    Widget::button(
+                        Node::builder().styled("file-button"),
+                        path.display().to_string(),
+                    )
+                    .observe(
+                        move |_: Trigger<Activate>,
+                              mut input: Single<&mut TextField, With<PalettePathInput>>| {
+                            input.value = path.display().to_string();
+                        },
+                    )
+                },
+            )),
+        )
+        .child(
+            Widget::panel(Node::builder().styled("dialog-buttons"))
+                .child(
+                    Widget::button(
+                        Node::builder().styled("dialog-button"),
+                        LocalizedText::new(confirm),
+                    )
+                    .observe(
+                        move |_: Trigger<Activate>,
+                              input: Single<&TextField, With<PalettePathInput>>,
+                              map: Res<EditedMap>,
+                              mut dialogs: Dialogs,
+                              mut edits: EventWriter<EditPalette>| {
+                            let path = input.value.trim();
+                            if path.is_empty() {
+                                return;
+                            }
+                            dialogs.close::<PaletteFileDialog>();
+                            palette_file(action, Path::new(path), &map, &mut edits);
+                        },
+                    ),
+                )
+                .child(
+                    Widget::button(
+                        Node::builder().styled("dialog-button"),
+                        LocalizedText::new("file.cancel"),
+                    )
+                    .observe(|_: Trigger<Activate>, mut dialogs: Dialogs| {
+                        dialogs.close::<PaletteFileDialog>()
+                    }),
+                ),
+        );
+    dialogs.open::<PaletteFileDialog>(window, true);
+}
+
+fn palette_file(
+    action: PaletteFileAction,
+    path: &Path,
+    map: &EditedMap,
+    edits: &mut EventWriter<EditPalette>,
+) {
+    match action {
+        PaletteFileAction::Import => match Palette::load(path) {
+            Ok(palette) => {
+                info!("Imported palette from {}", path.display());
+                edits.write(EditPalette(palette));
+            }
+            Err(err) => error!("Could not import palette {}: {err}", path.display()),
+        },
+        PaletteFileAction::Export => {
+            if let Some(directory) = path
+                .parent()
+                .filter(|parent| !parent.as_os_str().is_empty())
+                && let Err(err) = std::fs::create_dir_all(directory)
+            {
+                error!("Could not create {}: {err}", directory.display());
+                return;
+            }
+            match map.0.palette.save(path) {
+                Ok(()) => info!("Exported palette to {}", path.display()),
+                Err(err) => error!("Could not export palette to {}: {err}", path.display()),
+            }
+        }
+    }
+}
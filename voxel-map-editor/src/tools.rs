@@ -0,0 +1,302 @@
+use crate::AppState;
+use crate::history::History;
+use crate::map::{Chunk, EditVoxel, EditedMap, apply_edits_system};
+use bevy::picking::backend::HitData;
+use bevy::prelude::*;
+use bevy_builder::DialogStack;
+use voxel_map::{TileType, Voxel};
+
+/// `frame.obj` spans 1.6 units and sits on `y = 0`. Scaled to just wrap a voxel.
+const CURSOR_SCALE: f32 = 1.05 / 1.6;
+
+/// Place, Erase, Paint and Eyedropper. The voxel under the pointer is found
+/// with mesh picking, and the edits are sent as [`EditVoxel`] events.
+pub struct ToolsPlugin;
+
+impl Plugin for ToolsPlugin {
+    fn build(&self, app: &mut App) {
+        if !app.is_plugin_added::<MeshPickingPlugin>() {
+            app.add_plugins(MeshPickingPlugin);
+        }
+
+        app.init_resource::<Tool>()
+            .init_resource::<Brush>()
+            .init_resource::<HoveredVoxel>()
+            .add_observer(hover_voxel)
+            .add_observer(leave_voxel)
+            .add_observer(press_voxel)
+            .add_systems(OnEnter(AppState::InApp), spawn_cursor)
+            .add_systems(OnExit(AppState::InApp), despawn_cursor)
+            .add_systems(
+                Update,
+                (
+                    tool_shortcut_system,
+                    cursor_system,
+                    end_stroke_system.after(apply_edits_system),
+                )
+                    .chain()
+                    .run_if(in_state(AppState::InApp)),
+            );
+    }
+}
+
+#[derive(Resource, Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Tool {
+    /// Adds a voxel against the face under the pointer.
+    #[default]
+    Place,
+    Erase,
+    /// Gives the voxel under the pointer the brush material.
+    Paint,
+    /// Copies the voxel under the pointer into the brush.
+    Eyedropper,
+}
+
+impl Tool {
+    pub const ALL: [Tool; 4] = [Tool::Place, Tool::Erase, Tool::Paint, Tool::Eyedropper];
+
+    /// The localization key of the tool's name.
+    pub fn key(&self) -> &'static str {
+        match self {
+            Tool::Place => "tool.place",
+            Tool::Erase => "tool.erase",
+            Tool::Paint => "tool.paint",
+            Tool::Eyedropper => "tool.eyedropper",
+        }
+    }
+
+    /// Erase and Paint keep going while the button is held over more voxels.
+    fn strokes(&self) -> bool {
+        matches!(self, Tool::Erase | Tool::Paint)
+    }
+}
+
+/// The voxel Place adds and Paint takes its material from.
+#[derive(Resource, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Brush(pub Voxel);
+
+impl Default for Brush {
+    fn default() -> Self {
+        Brush(Voxel::new(TileType::Ground, 0))
+    }
+}
+
+/// The voxel under the pointer, and which of its faces, as a unit normal.
+#[derive(Resource, Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct HoveredVoxel(pub Option<(UVec3, IVec3)>);
+
+/// The `frame.obj` outline around the voxel a tool would change.
+#[derive(Component)]
+struct VoxelCursor;
+
+fn spawn_cursor(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+) {
+    commands.spawn((
+        VoxelCursor,
+        Mesh3d(asset_server.load("frame.obj")),
+        MeshMaterial3d(materials.add(StandardMaterial {
+            base_color: Color::WHITE,
+            unlit: true,
+            ..default()
+        })),
+        Transform::from_scale(Vec3::splat(CURSOR_SCALE)),
+        Visibility::Hidden,
+        Pickable::IGNORE,
+    ));
+}
+
+fn despawn_cursor(
+    mut commands: Commands,
+    cursor: Query<Entity, With<VoxelCursor>>,
+    mut hovered: ResMut<HoveredVoxel>,
+) {
+    for entity in &cursor {
+        commands.entity(entity).despawn();
+    }
+    hovered.0 = None;
+}
+
+/// The voxel `hit` landed on, and which of its faces.
+fn hit_voxel(map: &EditedMap, hit: &HitData) -> Option<(UVec3, IVec3)> {
+    let normal = hit.normal?;
+    let axis = normal.abs().max_element();
+    let face = if normal.x.abs() == axis {
+        IVec3::new(normal.x.signum() as i32, 0, 0)
+    } else if normal.y.abs() == axis {
+        IVec3::new(0, normal.y.signum() as i32, 0)
+    } else {
+        IVec3::new(0, 0, normal.z.signum() as i32)
+    };
+    // The hit is on the surface, so step half a voxel inside.
+    let position = map.position(hit.position? - face.as_vec3() * 0.5)?;
+    Some((position, face))
+}
+
+#[allow(clippy::too_many_arguments)]
+fn hover_voxel(
+    moved: Trigger<Pointer<Move>>,
+    chunks: Query<(), With<Chunk>>,
+    mut hovered: ResMut<HoveredVoxel>,
+    buttons: Res<ButtonInput<MouseButton>>,
+    tool: Res<Tool>,
+    mut brush: ResMut<Brush>,
+    map: Option<Res<EditedMap>>,
+    mut edits: EventWriter<EditVoxel>,
+) {
+    let Some(map) = map.filter(|_| chunks.contains(moved.target())) else {
+        return;
+    };
+    let Some(target) = hit_voxel(&map, &moved.hit) else {
+        return;
+    };
+    let previous = hovered.0;
+    hovered.set_if_neq(HoveredVoxel(Some(target)));
+
+    // Drag strokes change each voxel once, when the pointer gets to it.
+    let reached = previous.map(|(position, _)| position) != Some(target.0);
+    if reached && tool.strokes() && buttons.pressed(MouseButton::Left) {
+        use_tool(*tool, &mut brush, &map, target, &mut edits);
+    }
+}
+
+fn leave_voxel(
+    out: Trigger<Pointer<Out>>,
+    chunks: Query<(), With<Chunk>>,
+    mut hovered: ResMut<HoveredVoxel>,
+) {
+    if chunks.contains(out.target()) && hovered.0.is_some() {
+        hovered.0 = None;
+    }
+}
+
+fn press_voxel(
+    pressed: Trigger<Pointer<Pressed>>,
+    chunks: Query<(), With<Chunk>>,
+    tool: Res<Tool>,
+    mut brush: ResMut<Brush>,
+    map: Option<Res<EditedMap>>,
+    mut edits: EventWriter<EditVoxel>,
+) {
+    if pressed.button != PointerButton::Primary || !chunks.contains(pressed.target()) {
+        return;
+    }
+    let Some(map) = map else {
+        return;
+    };
+    if let Some(target) = hit_voxel(&map, &pressed.hit) {
+        use_tool(*tool, &mut brush, &map, target, &mut edits);
+    }
+}
+
+/// The voxel `tool` would change when used on the face `face` of `position`.
+fn tool_target(tool: Tool, map: &EditedMap, (position, face): (UVec3, IVec3)) -> Option<UVec3> {
+    match tool {
+        Tool::Place => {
+            let target = position.as_ivec3() + face;
+            let target = UVec3::try_from(target).ok()?;
+            map.0
+                .get(target)
+                .filter(|voxel| voxel.is_empty())
+                .map(|_| target)
+        }
+        _ => map
+            .0
+            .get(position)
+            .filter(|voxel| !voxel.is_empty())
+            .map(|_| position),
+    }
+}
+
+fn use_tool(
+    tool: Tool,
+    brush: &mut Brush,
+    map: &EditedMap,
+    hovered: (UVec3, IVec3),
+    edits: &mut EventWriter<EditVoxel>,
+) {
+    let Some(position) = tool_target(tool, map, hovered) else {
+        return;
+    };
+    let Some(current) = map.0.get(position) else {
+        return;
+    };
+
+    let voxel = match tool {
+        Tool::Place => brush.0,
+        Tool::Erase => Voxel::EMPTY,
+        Tool::Paint => Voxel::new(current.tile, brush.0.material),
+        Tool::Eyedropper => {
+            brush.0 = current;
+            return;
+        }
+    };
+    if voxel != current {
+        edits.write(EditVoxel { position, voxel });
+    }
+}
+
+/// Everything changed between pressing and releasing the button is undone
+/// in one go.
+fn end_stroke_system(buttons: Res<ButtonInput<MouseButton>>, mut history: ResMut<History>) {
+    if history.is_stroke_open() && !buttons.pressed(MouseButton::Left) {
+        history.end_stroke();
+    }
+}
+
+/// 1 to 4 pick a tool, `[` and `]` step through the palette.
+fn tool_shortcut_system(
+    keys: Res<ButtonInput<KeyCode>>,
+    dialogs: Res<DialogStack>,
+    map: Res<EditedMap>,
+    mut tool: ResMut<Tool>,
+    mut brush: ResMut<Brush>,
+) {
+    if dialogs.has_modal() {
+        return;
+    }
+
+    let digits = [
+        KeyCode::Digit1,
+        KeyCode::Digit2,
+        KeyCode::Digit3,
+        KeyCode::Digit4,
+    ];
+    for (key, shortcut) in digits.into_iter().zip(Tool::ALL) {
+        if keys.just_pressed(key) {
+            tool.set_if_neq(shortcut);
+        }
+    }
+    let materials = map.0.palette.len().max(1);
+    let step = |material: u8, by: usize| ((material as usize + by) % materials) as u8;
+    if keys.just_pressed(KeyCode::BracketLeft) {
+        brush.0.material = step(brush.0.material, materials - 1);
+    }
+    if keys.just_pressed(KeyCode::BracketRight) {
+        brush.0.material = step(brush.0.material, 1);
+    }
+}
+
+fn cursor_system(
+    hovered: Res<HoveredVoxel>,
+    tool: Res<Tool>,
+    map: Res<EditedMap>,
+    cursor: Single<(&mut Transform, &mut Visibility), With<VoxelCursor>>,
+) {
+    if !hovered.is_changed() && !tool.is_changed() && !map.is_changed() {
+        return;
+    }
+    let (mut transform, mut visibility) = cursor.into_inner();
+    let target = hovered
+        .0
+        .and_then(|hovered| tool_target(*tool, &map, hovered));
+    match target {
+        Some(position) => {
+            transform.translation = map.translation(position) - Vec3::Y * CURSOR_SCALE * 0.8;
+            *visibility = Visibility::Visible;
+        }
+        None => *visibility = Visibility::Hidden,
+    }
+}
@@ -0,0 +1,126 @@
+//! Compares drawing a map the way the editor used to, one cube entity with its
+//! own material per voxel, against greedy meshes per chunk and material.
+//!
+//! Run with `cargo bench -p voxel-map --bench meshing`. Frame times come from a
+//! headless app without a renderer, so they show the cost of the entities
+//! alone. Expect the gap to be wider once draw calls are added.
+
+use bevy::prelude::*;
+use std::time::{Duration, Instant};
+use voxel_map::{CHUNK_SIZE, Dimensions, TileType, Voxel, VoxelMap, chunk_meshes};
+
+const FRAMES: u32 = 30;
+
+fn main() {
+    let map = terrain(Dimensions::new(100, 50, 100));
+    let solid = map.iter().filter(|(_, voxel)| !voxel.is_empty()).count();
+    println!(
+        "map: {}x{}x{}, {solid} solid voxels",
+        map.dimensions().width,
+        map.dimensions().height,
+        map.dimensions().length
+    );
+
+    let (entities, spawn, frame) = bench_app(|world| {
+        let cuboid = world.resource_mut::<Assets<Mesh>>().add(Cuboid::default());
+        for (position, voxel) in map.iter() {
+            if voxel.is_empty() {
+                continue;
+            }
+            let material = world
+                .resource_mut::<Assets<StandardMaterial>>()
+                .add(color(voxel));
+            world.spawn((
+                Mesh3d(cuboid.clone()),
+                MeshMaterial3d(material),
+                Transform::from_translation(position.as_vec3()),
+            ));
+        }
+    });
+    report("per voxel", entities, spawn, frame);
+
+    let mut quads = 0;
+    let (entities, spawn, frame) = bench_app(|world| {
+        let materials: Vec<_> = (0..=1)
+            .map(|material| {
+                world
+                    .resource_mut::<Assets<StandardMaterial>>()
+                    .add(color(Voxel::new(TileType::Ground, material)))
+            })
+            .collect();
+        for chunk in map.chunks() {
+            for (material, mesh) in chunk_meshes(&map, chunk) {
+                quads += mesh.indices().map_or(0, |indices| indices.len() / 6);
+                let mesh = world.resource_mut::<Assets<Mesh>>().add(mesh);
+                world.spawn((
+                    Mesh3d(mesh),
+                    MeshMaterial3d(materials[material as usize].clone()),
+                    Transform::from_translation((chunk * CHUNK_SIZE).as_vec3()),
+                ));
+            }
+        }
+    });
+    report("chunked", entities, spawn, frame);
+    println!("  {quads} quads after greedy meshing");
+
+    // What an edit costs: remeshing the one chunk it touched.
+    let chunk = UVec3::new(3, 1, 3);
+    let start = Instant::now();
+    for _ in 0..FRAMES {
+        std::hint::black_box(chunk_meshes(&map, chunk));
+    }
+    println!("remesh one chunk: {:?}", start.elapsed() / FRAMES);
+}
+
+/// Rolling hills with a band of a second material, so there is something to merge
+/// and something not to.
+fn terrain(dimensions: Dimensions) -> VoxelMap {
+    let mut map = VoxelMap::new(dimensions);
+    for z in 0..dimensions.length {
+        for x in 0..dimensions.width {
+            let hills = (x as f32 * 0.2).sin() * 5.0 + (z as f32 * 0.15).cos() * 5.0;
+            let height = (dimensions.height as f32 * 0.4 + hills) as u32;
+            for y in 0..height.min(dimensions.height) {
+                let material = if y + 3 >= height { 1 } else { 0 };
+                map.set(UVec3::new(x, y, z), Voxel::new(TileType::Ground, material));
+            }
+        }
+    }
+    map
+}
+
+fn color(voxel: Voxel) -> Color {
+    match voxel.material {
+        0 => Color::srgb_u8(124, 144, 255),
+        _ => Color::srgb_u8(96, 160, 64),
+    }
+}
+
+/// Spawns a scene with `spawn` and returns the entity count, how long spawning
+/// took and the mean frame time.
+fn bench_app(spawn: impl FnOnce(&mut World)) -> (u32, Duration, Duration) {
+    let mut app = App::new();
+    app.add_plugins((MinimalPlugins, TransformPlugin, AssetPlugin::default()))
+        .init_asset::<Mesh>()
+        .init_asset::<StandardMaterial>();
+    app.update();
+
+    let start = Instant::now();
+    spawn(app.world_mut());
+    app.update();
+    let spawned = start.elapsed();
+
+    let start = Instant::now();
+    for _ in 0..FRAMES {
+        app.update();
+    }
+    (
+        app.world().entities().len(),
+        spawned,
+        start.elapsed() / FRAMES,
+    )
+}
+
+fn report(name: &str, entities: u32, spawn: Duration, frame: Duration) {
+    println!("{name}: {entities} entities, spawned in {spawn:?}, {frame:?} per frame");
+}
@@ -0,0 +1,37 @@
+use crate::{MapError, VoxelMap};
+use bevy::asset::{AssetLoader, LoadContext, io::Reader};
+use bevy::prelude::*;
+
+/// Registers [`VoxelMap`] as an asset loaded from `.vmap` files.
+pub struct VoxelMapPlugin;
+
+impl Plugin for VoxelMapPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_asset::<VoxelMap>()
+            .init_asset_loader::<VoxelMapLoader>();
+    }
+}
+
+#[derive(Default)]
+pub struct VoxelMapLoader;
+
+impl AssetLoader for VoxelMapLoader {
+    type Asset = VoxelMap;
+    type Settings = ();
+    type Error = MapError;
+
+    async fn load(
+        &self,
+        reader: &mut dyn Reader,
+        _settings: &(),
+        _load_context: &mut LoadContext<'_>,
+    ) -> Result<VoxelMap, Self::Error> {
+        let mut bytes = Vec::new();
+        reader.read_to_end(&mut bytes).await?;
+        VoxelMap::from_bytes(&bytes)
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["vmap"]
+    }
+}
@@ -0,0 +1,261 @@
+use crate::{
+    Annotation, CameraView, Dimensions, MAX_MATERIALS, MapMetadata, PALETTE_VERSION, Palette,
+    Voxel, VoxelMap,
+};
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+use thiserror::Error;
+
+/// The version written by [`VoxelMap::to_bytes`]. Bump it whenever the file
+/// layout changes, and teach [`VoxelMap::from_bytes`] to read the old one.
+///
+/// - 1: the first version.
+/// - 2: adds the material palette. Version 1 maps get one generated to cover
+///   the materials they use.
+/// - 3: adds the editor camera.
+pub const FORMAT_VERSION: u32 = 3;
+
+#[derive(Debug, Error)]
+pub enum MapError {
+    #[error("could not read map: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("could not parse map: {0}")]
+    Json5(#[from] serde_json5::Error),
+    #[error(
+        "map was saved by a newer version (format {0}, this build reads up to {FORMAT_VERSION})"
+    )]
+    UnsupportedVersion(u32),
+    #[error(
+        "palette was saved by a newer version (format {0}, this build reads up to {PALETTE_VERSION})"
+    )]
+    UnsupportedPaletteVersion(u32),
+    #[error("map is {expected} voxels in size but holds {found}")]
+    SizeMismatch { expected: usize, found: usize },
+    #[error("palette has {0} materials, more than the {MAX_MATERIALS} a voxel can refer to")]
+    PaletteTooLarge(usize),
+}
+
+/// Just enough of a file to tell which version wrote it.
+#[derive(Deserialize)]
+struct VersionHeader {
+    version: u32,
+}
+
+/// The on-disk layout. Voxels are run-length encoded, since maps are mostly
+/// large stretches of the same voxel.
+#[derive(Serialize, Deserialize)]
+struct MapFile {
+    version: u32,
+    dimensions: Dimensions,
+    voxels: Vec<Run>,
+    /// Missing from version 1 maps.
+    #[serde(default)]
+    palette: Option<Palette>,
+    #[serde(default)]
+    metadata: MapMetadata,
+    #[serde(default)]
+    annotations: Vec<Annotation>,
+    /// Missing from maps before version 3, and from maps never saved by the editor.
+    #[serde(default)]
+    camera: Option<CameraView>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Run {
+    count: u32,
+    #[serde(flatten)]
+    voxel: Voxel,
+}
+
+impl VoxelMap {
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, MapError> {
+        let header: VersionHeader = serde_json5::from_slice(bytes)?;
+        if header.version > FORMAT_VERSION {
+            return Err(MapError::UnsupportedVersion(header.version));
+        }
+        let file: MapFile = serde_json5::from_slice(bytes)?;
+
+        let expected = file.dimensions.volume();
+        let found = file.voxels.iter().map(|run| run.count as usize).sum();
+        if found != expected {
+            return Err(MapError::SizeMismatch { expected, found });
+        }
+        let voxels = file
+            .voxels
+            .iter()
+            .flat_map(|run| std::iter::repeat_n(run.voxel, run.count as usize))
+            .collect::<Vec<_>>();
+        let palette = match file.palette {
+            Some(palette) => palette,
+            None => {
+                let used = voxels.iter().map(|voxel| voxel.material).max();
+                Palette::generated(used.map_or(1, |material| material as usize + 1))
+            }
+        };
+        palette.check()?;
+
+        Ok(VoxelMap {
+            dimensions: file.dimensions,
+            voxels,
+            palette,
+            metadata: file.metadata,
+            annotations: file.annotations,
+            camera: file.camera,
+        })
+    }
+
+    pub fn to_bytes(&self) -> Result<Vec<u8>, MapError> {
+        let mut voxels: Vec<Run> = Vec::new();
+        for voxel in &self.voxels {
+            match voxels.last_mut() {
+                Some(run) if run.voxel == *voxel => run.count += 1,
+                _ => voxels.push(Run {
+                    count: 1,
+                    voxel: *voxel,
+                }),
+            }
+        }
+        let file = MapFile {
+            version: FORMAT_VERSION,
+            dimensions: self.dimensions,
+            voxels,
+            palette: Some(self.palette.clone()),
+            metadata: self.metadata.clone(),
+            annotations: self.annotations.clone(),
+            camera: self.camera,
+        };
+        Ok(serde_json5::to_string(&file)?.into_bytes())
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, MapError> {
+        VoxelMap::from_bytes(&std::fs::read(path)?)
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), MapError> {
+        std::fs::write(path, self.to_bytes()?)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::TileType;
+    use bevy::math::UVec3;
+
+    fn sample_map() -> VoxelMap {
+        let mut map = VoxelMap::new(Dimensions::new(4, 2, 3));
+        map.set(UVec3::new(1, 0, 0), Voxel::new(TileType::Ground, 2));
+        map.set(UVec3::new(2, 0, 0), Voxel::new(TileType::Ground, 2));
+        map.set(UVec3::new(3, 1, 2), Voxel::new(TileType::Water, 5));
+        map.metadata.name = "Plateau".to_string();
+        map.metadata.author = "Someone".to_string();
+        map.metadata
+            .properties
+            .insert("waves".to_string(), "3".to_string());
+        map.annotations.push(Annotation {
+            position: [1, 0, 0],
+            kind: "spawn".to_string(),
+            properties: [("team".to_string(), "red".to_string())].into(),
+        });
+        map
+    }
+
+    #[test]
+    fn round_trips() {
+        let map = sample_map();
+        let loaded = VoxelMap::from_bytes(&map.to_bytes().unwrap()).unwrap();
+        assert_eq!(loaded, map);
+        assert_eq!(loaded.metadata.properties["waves"], "3");
+        assert_eq!(loaded.annotations[0].properties["team"], "red");
+    }
+
+    #[test]
+    fn writes_runs_of_equal_voxels() {
+        let file: MapFile = serde_json5::from_slice(&sample_map().to_bytes().unwrap()).unwrap();
+        let runs: Vec<_> = file
+            .voxels
+            .iter()
+            .map(|run| (run.count, run.voxel))
+            .collect();
+        let ground = Voxel::new(TileType::Ground, 2);
+        let water = Voxel::new(TileType::Water, 5);
+        assert_eq!(
+            runs,
+            [
+                (1, Voxel::EMPTY),
+                (2, ground),
+                (20, Voxel::EMPTY),
+                (1, water),
+            ]
+        );
+    }
+
+    #[test]
+    fn rejects_wrong_voxel_count() {
+        let bytes = br#"{
+            version: 3,
+            dimensions: { width: 2, height: 1, length: 2 },
+            voxels: [{ count: 3, tile: "ground", material: 0 }],
+        }"#;
+        assert!(matches!(
+            VoxelMap::from_bytes(bytes),
+            Err(MapError::SizeMismatch {
+                expected: 4,
+                found: 3
+            })
+        ));
+    }
+
+    #[test]
+    fn rejects_newer_versions() {
+        let bytes = format!("{{ version: {}, something: \"new\" }}", FORMAT_VERSION + 1);
+        assert!(matches!(
+            VoxelMap::from_bytes(bytes.as_bytes()),
+            Err(MapError::UnsupportedVersion(version)) if version == FORMAT_VERSION + 1
+        ));
+    }
+
+    #[test]
+    fn loads_version_1() {
+        let bytes = br#"{
+            version: 1,
+            dimensions: { width: 2, height: 1, length: 1 },
+            voxels: [
+                { count: 1, tile: "ground", material: 0 },
+                { count: 1, tile: "path", material: 3 },
+            ],
+            metadata: { name: "Old" },
+        }"#;
+        let map = VoxelMap::from_bytes(bytes).unwrap();
+        assert_eq!(
+            map.get(UVec3::new(1, 0, 0)),
+            Some(Voxel::new(TileType::Path, 3))
+        );
+        assert_eq!(map.metadata.name, "Old");
+        // A palette is generated to cover the materials in use.
+        assert_eq!(map.palette, Palette::generated(4));
+        assert_eq!(map.camera, None);
+    }
+
+    #[test]
+    fn loads_version_2() {
+        let bytes = br##"{
+            version: 2,
+            dimensions: { width: 1, height: 1, length: 1 },
+            voxels: [{ count: 1, tile: "water", material: 1 }],
+            palette: [
+                { name: "sand", color: "#e0c080" },
+                { name: "sea", color: "#2040ff", roughness: 0.1 },
+            ],
+            annotations: [{ position: [0, 0, 0], kind: "spawn" }],
+        }"##;
+        let map = VoxelMap::from_bytes(bytes).unwrap();
+        assert_eq!(map.get(UVec3::ZERO), Some(Voxel::new(TileType::Water, 1)));
+        assert_eq!(map.palette.len(), 2);
+        assert_eq!(map.palette.get(1).unwrap().name, "sea");
+        assert_eq!(map.palette.get(1).unwrap().roughness, 0.1);
+        assert_eq!(map.annotations[0].kind, "spawn");
+        assert_eq!(map.camera, None);
+    }
+}
@@ -0,0 +1,307 @@
+mod asset;
+mod format;
+mod mesh;
+mod obj;
+mod palette;
+mod vox;
+
+pub use asset::*;
+pub use format::*;
+pub use mesh::*;
+pub use obj::*;
+pub use palette::*;
+pub use vox::*;
+
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+/// The size of a map in voxels. `height` runs along the Y axis.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub struct Dimensions {
+    pub width: u32,
+    pub height: u32,
+    pub length: u32,
+}
+
+impl Dimensions {
+    pub fn new(width: u32, height: u32, length: u32) -> Self {
+        Dimensions {
+            width,
+            height,
+            length,
+        }
+    }
+
+    pub fn volume(&self) -> usize {
+        self.width as usize * self.height as usize * self.length as usize
+    }
+
+    pub fn contains(&self, position: UVec3) -> bool {
+        position.x < self.width && position.y < self.height && position.z < self.length
+    }
+
+    pub fn as_uvec3(&self) -> UVec3 {
+        UVec3::new(self.width, self.height, self.length)
+    }
+
+    /// Voxels are stored one horizontal layer at a time, rows along X.
+    fn index(&self, position: UVec3) -> Option<usize> {
+        self.contains(position).then(|| {
+            position.x as usize
+                + position.z as usize * self.width as usize
+                + position.y as usize * self.width as usize * self.length as usize
+        })
+    }
+
+    fn position(&self, index: usize) -> UVec3 {
+        let layer = self.width as usize * self.length as usize;
+        UVec3::new(
+            (index % self.width as usize) as u32,
+            (index / layer) as u32,
+            (index % layer / self.width as usize) as u32,
+        )
+    }
+}
+
+/// What a voxel is, as far as gameplay is concerned.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TileType {
+    #[default]
+    Empty,
+    Ground,
+    Path,
+    Water,
+}
+
+impl TileType {
+    pub fn name(&self) -> &'static str {
+        match self {
+            TileType::Empty => "empty",
+            TileType::Ground => "ground",
+            TileType::Path => "path",
+            TileType::Water => "water",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default, Serialize, Deserialize)]
+pub struct Voxel {
+    pub tile: TileType,
+    /// Index into the map's material palette.
+    pub material: u8,
+}
+
+impl Voxel {
+    pub const EMPTY: Voxel = Voxel {
+        tile: TileType::Empty,
+        material: 0,
+    };
+
+    pub fn new(tile: TileType, material: u8) -> Self {
+        Voxel { tile, material }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.tile == TileType::Empty
+    }
+}
+
+/// Free-form information about the whole map.
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct MapMetadata {
+    pub name: String,
+    pub author: String,
+    pub description: String,
+    pub properties: BTreeMap<String, String>,
+}
+
+/// Marks a single voxel as something the game spawns, such as a spawn point
+/// or a building, in the same way as an object in a Tiled object layer.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Annotation {
+    pub position: [u32; 3],
+    pub kind: String,
+    #[serde(default)]
+    pub properties: BTreeMap<String, String>,
+}
+
+/// Where the editor camera looked when the map was saved. It orbits `focus`
+/// from `distance` away.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct CameraView {
+    pub focus: [f32; 3],
+    /// Radians around the Y axis. At 0 the camera looks along -Z.
+    pub yaw: f32,
+    /// Radians above the horizon, negative when looking down.
+    pub pitch: f32,
+    pub distance: f32,
+    #[serde(default)]
+    pub orthographic: bool,
+}
+
+/// A dense voxel map shared by the editor and the game.
+///
+/// Saved as JSON5 by [`VoxelMap::to_bytes`]. See [`FORMAT_VERSION`].
+#[derive(Asset, TypePath, Debug, Clone, PartialEq)]
+pub struct VoxelMap {
+    dimensions: Dimensions,
+    voxels: Vec<Voxel>,
+    pub palette: Palette,
+    pub metadata: MapMetadata,
+    pub annotations: Vec<Annotation>,
+    pub camera: Option<CameraView>,
+}
+
+impl VoxelMap {
+    /// An empty map.
+    pub fn new(dimensions: Dimensions) -> Self {
+        VoxelMap::filled(dimensions, Voxel::EMPTY)
+    }
+
+    pub fn filled(dimensions: Dimensions, voxel: Voxel) -> Self {
+        VoxelMap {
+            dimensions,
+            voxels: vec![voxel; dimensions.volume()],
+            palette: Palette::default(),
+            metadata: MapMetadata::default(),
+            annotations: Vec::new(),
+            camera: None,
+        }
+    }
+
+    pub fn dimensions(&self) -> Dimensions {
+        self.dimensions
+    }
+
+    /// A copy with new dimensions, anchored at the origin. Voxels and
+    /// annotations that no longer fit are dropped, and new space is empty.
+    pub fn resized(&self, dimensions: Dimensions) -> VoxelMap {
+        let mut map = VoxelMap::new(dimensions);
+        for (position, voxel) in self.iter() {
+            map.set(position, voxel);
+        }
+        map.palette = self.palette.clone();
+        map.metadata = self.metadata.clone();
+        map.camera = self.camera;
+        map.annotations = self
+            .annotations
+            .iter()
+            .filter(|annotation| dimensions.contains(UVec3::from_array(annotation.position)))
+            .cloned()
+            .collect();
+        map
+    }
+
+    /// A copy of the box from `min` to `max`, both included, moved to the
+    /// origin. Annotations inside the box move with it.
+    pub fn cropped(&self, min: UVec3, max: UVec3) -> VoxelMap {
+        let max = max.min(self.dimensions.as_uvec3().saturating_sub(UVec3::ONE));
+        let size = (max + UVec3::ONE).saturating_sub(min);
+        let dimensions = Dimensions::new(size.x, size.y, size.z);
+        let mut map = VoxelMap::new(dimensions);
+        for (position, voxel) in self.iter() {
+            if position.cmpge(min).all() {
+                map.set(position - min, voxel);
+            }
+        }
+        map.palette = self.palette.clone();
+        map.metadata = self.metadata.clone();
+        map.annotations = self
+            .annotations
+            .iter()
+            .filter_map(|annotation| {
+                let position = UVec3::from_array(annotation.position);
+                (position.cmpge(min).all() && dimensions.contains(position - min)).then(|| {
+                    Annotation {
+                        position: (position - min).to_array(),
+                        ..annotation.clone()
+                    }
+                })
+            })
+            .collect();
+        map
+    }
+
+    pub fn get(&self, position: UVec3) -> Option<Voxel> {
+        self.dimensions
+            .index(position)
+            .map(|index| self.voxels[index])
+    }
+
+    /// Replaces the voxel at `position` and returns the old one, or `None`
+    /// if `position` is outside the map.
+    pub fn set(&mut self, position: UVec3, voxel: Voxel) -> Option<Voxel> {
+        let index = self.dimensions.index(position)?;
+        Some(std::mem::replace(&mut self.voxels[index], voxel))
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (UVec3, Voxel)> + '_ {
+        self.voxels
+            .iter()
+            .enumerate()
+            .map(|(index, voxel)| (self.dimensions.position(index), *voxel))
+    }
+
+    /// The highest voxel that is not empty in the column at `x`, `z`.
+    pub fn top(&self, x: u32, z: u32) -> Option<(u32, Voxel)> {
+        (0..self.dimensions.height).rev().find_map(|y| {
+            self.get(UVec3::new(x, y, z))
+                .filter(|voxel| !voxel.is_empty())
+                .map(|voxel| (y, voxel))
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A map where every voxel has a material of its own.
+    fn numbered_map() -> VoxelMap {
+        let mut map = VoxelMap::new(Dimensions::new(4, 2, 3));
+        for (index, voxel) in map.voxels.iter_mut().enumerate() {
+            *voxel = Voxel::new(TileType::Ground, index as u8);
+        }
+        map.annotations = [[0, 0, 0], [3, 1, 2]]
+            .map(|position| Annotation {
+                position,
+                kind: "spawn".to_string(),
+                properties: BTreeMap::new(),
+            })
+            .into();
+        map
+    }
+
+    #[test]
+    fn crops_to_the_map() {
+        let map = numbered_map();
+        // Past the edge of the map on every axis.
+        let cropped = map.cropped(UVec3::new(2, 1, 1), UVec3::new(10, 10, 10));
+        assert_eq!(cropped.dimensions(), Dimensions::new(2, 1, 2));
+        for (position, voxel) in cropped.iter() {
+            assert_eq!(map.get(position + UVec3::new(2, 1, 1)), Some(voxel));
+        }
+        let positions: Vec<_> = cropped
+            .annotations
+            .iter()
+            .map(|annotation| annotation.position)
+            .collect();
+        assert_eq!(positions, [[1, 0, 1]]);
+    }
+
+    #[test]
+    fn crops_nothing_when_min_is_past_max() {
+        let map = numbered_map();
+        for (min, max) in [
+            (UVec3::new(3, 0, 0), UVec3::new(1, 1, 2)),
+            (UVec3::new(0, 0, 5), UVec3::new(3, 1, 8)),
+        ] {
+            let cropped = map.cropped(min, max);
+            assert_eq!(cropped.dimensions().volume(), 0);
+            assert!(cropped.annotations.is_empty());
+        }
+    }
+}
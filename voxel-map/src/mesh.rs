@@ -0,0 +1,312 @@
+use crate::{Dimensions, VoxelMap};
+use bevy::asset::RenderAssetUsages;
+use bevy::prelude::*;
+use bevy::render::mesh::{Indices, PrimitiveTopology};
+use std::collections::BTreeMap;
+
+/// Chunks are cubes of this many voxels along each side. Those at the far
+/// edges of a map may be smaller.
+pub const CHUNK_SIZE: u32 = 16;
+
+impl Dimensions {
+    /// How many chunks the map is split into along each axis.
+    pub fn chunks(&self) -> UVec3 {
+        (self.as_uvec3() + UVec3::splat(CHUNK_SIZE - 1)) / CHUNK_SIZE
+    }
+
+    /// The chunks whose mesh can change when the voxel at `position` does:
+    /// its own, and the next one along each axis where it is on the edge of
+    /// its chunk, since it can hide a face of that one.
+    pub fn chunks_around(&self, position: UVec3) -> Vec<UVec3> {
+        let chunks = self.chunks();
+        let chunk = position / CHUNK_SIZE;
+        let within = position % CHUNK_SIZE;
+        let mut around = vec![chunk];
+        for axis in 0..3 {
+            if within[axis] == 0 && chunk[axis] > 0 {
+                let mut neighbour = chunk;
+                neighbour[axis] -= 1;
+                around.push(neighbour);
+            }
+            if within[axis] == CHUNK_SIZE - 1 && chunk[axis] + 1 < chunks[axis] {
+                let mut neighbour = chunk;
+                neighbour[axis] += 1;
+                around.push(neighbour);
+            }
+        }
+        around
+    }
+}
+
+impl VoxelMap {
+    /// The position of every chunk, in chunks.
+    pub fn chunks(&self) -> impl Iterator<Item = UVec3> + use<> {
+        let chunks = self.dimensions.chunks();
+        (0..chunks.y).flat_map(move |y| {
+            (0..chunks.z).flat_map(move |z| (0..chunks.x).map(move |x| UVec3::new(x, y, z)))
+        })
+    }
+}
+
+/// Builds the meshes for the chunk at `chunk`, one for each material that has
+/// visible faces in it.
+///
+/// Faces between two solid voxels are skipped, and neighbouring faces of the
+/// same material are merged into larger quads (greedy meshing). Vertices are
+/// in voxels from the lower corner of the chunk, and UVs repeat once per voxel.
+pub fn chunk_meshes(map: &VoxelMap, chunk: UVec3) -> BTreeMap<u8, Mesh> {
+    let min = chunk * CHUNK_SIZE;
+    let size = (map.dimensions().as_uvec3() - min).min(UVec3::splat(CHUNK_SIZE));
+    greedy_quads(map, min, size)
+        .into_iter()
+        .map(|(material, quads)| (material, quads_mesh(&quads)))
+        .collect()
+}
+
+/// A rectangle of faces of one material, as merged by [`greedy_quads`].
+pub(crate) struct Quad {
+    /// Going along the first axis across the face, then the second.
+    pub corners: [Vec3; 4],
+    pub normal: IVec3,
+    /// The corners wind clockwise seen from the front.
+    flip: bool,
+    /// In voxels along each axis across the face.
+    pub size: UVec2,
+}
+
+impl Quad {
+    /// Indices into [`Quad::corners`], counter-clockwise seen from the front.
+    pub fn winding(&self) -> [usize; 4] {
+        if self.flip {
+            [0, 3, 2, 1]
+        } else {
+            [0, 1, 2, 3]
+        }
+    }
+
+    /// UVs of the corners, repeating once per voxel.
+    pub fn uvs(&self) -> [UVec2; 4] {
+        let UVec2 { x, y } = self.size;
+        [
+            UVec2::ZERO,
+            UVec2::new(x, 0),
+            UVec2::new(x, y),
+            UVec2::new(0, y),
+        ]
+    }
+}
+
+/// The visible faces of the voxels in the box of `size` at `min`, merged into
+/// as few quads as possible for each material. Corners are in voxels from
+/// `min`. Voxels outside the box still hide faces.
+pub(crate) fn greedy_quads(map: &VoxelMap, min: UVec3, size: UVec3) -> BTreeMap<u8, Vec<Quad>> {
+    let solid = |position: IVec3| {
+        UVec3::try_from(min.as_ivec3() + position)
+            .ok()
+            .and_then(|position| map.get(position))
+            .filter(|voxel| !voxel.is_empty())
+            .map(|voxel| voxel.material)
+    };
+
+    let mut quads: BTreeMap<u8, Vec<Quad>> = BTreeMap::new();
+    for axis in 0..3 {
+        // The two axes across each face, in the order that makes the front
+        // of a quad face along +axis.
+        let u = (axis + 1) % 3;
+        let v = (axis + 2) % 3;
+        let mut mask = vec![None; (size[u] * size[v]) as usize];
+        for direction in [-1, 1] {
+            let mut step = IVec3::ZERO;
+            step[axis] = direction;
+
+            for layer in 0..size[axis] {
+                for j in 0..size[v] {
+                    for i in 0..size[u] {
+                        let mut position = IVec3::ZERO;
+                        position[axis] = layer as i32;
+                        position[u] = i as i32;
+                        position[v] = j as i32;
+                        mask[(i + j * size[u]) as usize] =
+                            solid(position).filter(|_| solid(position + step).is_none());
+                    }
+                }
+
+                for j in 0..size[v] {
+                    let mut i = 0;
+                    while i < size[u] {
+                        let index = |i: u32, j: u32| (i + j * size[u]) as usize;
+                        let Some(material) = mask[index(i, j)] else {
+                            i += 1;
+                            continue;
+                        };
+                        let width = (i..size[u])
+                            .take_while(|&i| mask[index(i, j)] == Some(material))
+                            .count() as u32;
+                        let height = (j..size[v])
+                            .take_while(|&j| {
+                                (i..i + width).all(|i| mask[index(i, j)] == Some(material))
+                            })
+                            .count() as u32;
+                        for j in j..j + height {
+                            for i in i..i + width {
+                                mask[index(i, j)] = None;
+                            }
+                        }
+
+                        let mut corner = Vec3::ZERO;
+                        corner[axis] = (layer + u32::from(direction > 0)) as f32;
+                        corner[u] = i as f32;
+                        corner[v] = j as f32;
+                        let mut across = Vec3::ZERO;
+                        across[u] = width as f32;
+                        let mut up = Vec3::ZERO;
+                        up[v] = height as f32;
+                        quads.entry(material).or_default().push(Quad {
+                            corners: [corner, corner + across, corner + across + up, corner + up],
+                            normal: step,
+                            flip: direction < 0,
+                            size: UVec2::new(width, height),
+                        });
+                        i += width;
+                    }
+                }
+            }
+        }
+    }
+    quads
+}
+
+fn quads_mesh(quads: &[Quad]) -> Mesh {
+    let mut positions = Vec::new();
+    let mut normals = Vec::new();
+    let mut uvs = Vec::new();
+    let mut indices = Vec::new();
+    for quad in quads {
+        let first = positions.len() as u32;
+        positions.extend(quad.corners.map(|corner| corner.to_array()));
+        normals.extend([quad.normal.as_vec3().to_array(); 4]);
+        uvs.extend(quad.uvs().map(|uv| uv.as_vec2().to_array()));
+        let [a, b, c, d] = quad.winding().map(|index| first + index as u32);
+        indices.extend([a, b, c, a, c, d]);
+    }
+    Mesh::new(
+        PrimitiveTopology::TriangleList,
+        RenderAssetUsages::default(),
+    )
+    .with_inserted_attribute(Mesh::ATTRIBUTE_POSITION, positions)
+    .with_inserted_attribute(Mesh::ATTRIBUTE_NORMAL, normals)
+    .with_inserted_attribute(Mesh::ATTRIBUTE_UV_0, uvs)
+    .with_inserted_indices(Indices::U32(indices))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{TileType, Voxel};
+    use bevy::render::mesh::VertexAttributeValues;
+
+    fn ground(material: u8) -> Voxel {
+        Voxel::new(TileType::Ground, material)
+    }
+
+    /// The normal and size of every quad of `material`, in a fixed order.
+    fn faces(quads: &BTreeMap<u8, Vec<Quad>>, material: u8) -> Vec<(IVec3, UVec2)> {
+        let mut faces: Vec<_> = quads[&material]
+            .iter()
+            .map(|quad| (quad.normal, quad.size))
+            .collect();
+        faces.sort_by_key(|(normal, _)| normal.to_array());
+        faces
+    }
+
+    #[test]
+    fn merges_faces_of_the_same_material() {
+        let map = VoxelMap::filled(Dimensions::new(3, 1, 2), ground(1));
+        let quads = greedy_quads(&map, UVec3::ZERO, map.dimensions().as_uvec3());
+        assert_eq!(quads.keys().collect::<Vec<_>>(), [&1]);
+        // One quad for each side of the box, in voxels across the two other
+        // axes in turn.
+        assert_eq!(
+            faces(&quads, 1),
+            [
+                (IVec3::NEG_X, UVec2::new(1, 2)),
+                (IVec3::NEG_Y, UVec2::new(2, 3)),
+                (IVec3::NEG_Z, UVec2::new(3, 1)),
+                (IVec3::Z, UVec2::new(3, 1)),
+                (IVec3::Y, UVec2::new(2, 3)),
+                (IVec3::X, UVec2::new(1, 2)),
+            ]
+        );
+    }
+
+    #[test]
+    fn keeps_materials_apart() {
+        let mut map = VoxelMap::new(Dimensions::new(2, 1, 1));
+        map.set(UVec3::ZERO, ground(1));
+        map.set(UVec3::X, ground(2));
+        let quads = greedy_quads(&map, UVec3::ZERO, map.dimensions().as_uvec3());
+        // The faces between the two voxels are hidden, and the rest are not
+        // merged across materials.
+        let one = faces(&quads, 1);
+        let two = faces(&quads, 2);
+        assert!(one.iter().all(|(normal, _)| *normal != IVec3::X));
+        assert!(two.iter().all(|(normal, _)| *normal != IVec3::NEG_X));
+        assert_eq!((one.len(), two.len()), (5, 5));
+        assert!(one.iter().chain(&two).all(|(_, size)| *size == UVec2::ONE));
+    }
+
+    #[test]
+    fn hides_faces_across_chunk_borders() {
+        let map = VoxelMap::filled(Dimensions::new(CHUNK_SIZE + 1, 1, 1), ground(0));
+        let first = greedy_quads(&map, UVec3::ZERO, UVec3::new(CHUNK_SIZE, 1, 1));
+        let first = faces(&first, 0);
+        assert!(first.iter().all(|(normal, _)| *normal != IVec3::X));
+        assert!(first.contains(&(IVec3::Y, UVec2::new(1, CHUNK_SIZE))));
+        let second = greedy_quads(&map, UVec3::X * CHUNK_SIZE, UVec3::ONE);
+        let second = faces(&second, 0);
+        assert!(second.iter().all(|(normal, _)| *normal != IVec3::NEG_X));
+        assert_eq!((first.len(), second.len()), (5, 5));
+    }
+
+    #[test]
+    fn meshes_partial_chunks_at_the_edge() {
+        let map = VoxelMap::filled(Dimensions::new(CHUNK_SIZE + 4, 2, 1), ground(3));
+        assert_eq!(map.dimensions().chunks(), UVec3::new(2, 1, 1));
+        assert_eq!(map.chunks().count(), 2);
+
+        let meshes = chunk_meshes(&map, UVec3::X);
+        assert_eq!(meshes.keys().collect::<Vec<_>>(), [&3]);
+        let mesh = &meshes[&3];
+        // Five sides of a 4x2x1 box, the sixth is hidden by the first chunk.
+        assert_eq!(mesh.count_vertices(), 5 * 4);
+        let Some(VertexAttributeValues::Float32x3(positions)) =
+            mesh.attribute(Mesh::ATTRIBUTE_POSITION)
+        else {
+            panic!("mesh has no positions");
+        };
+        let max = positions
+            .iter()
+            .fold(Vec3::ZERO, |max, position| max.max(Vec3::from(*position)));
+        assert_eq!(max, Vec3::new(4.0, 2.0, 1.0));
+    }
+
+    #[test]
+    fn finds_the_chunks_around_a_voxel() {
+        let dimensions = Dimensions::new(3 * CHUNK_SIZE - 8, CHUNK_SIZE, CHUNK_SIZE);
+        assert_eq!(dimensions.chunks(), UVec3::new(3, 1, 1));
+        let around = |x, y, z| dimensions.chunks_around(UVec3::new(x, y, z));
+
+        assert_eq!(around(20, 5, 5), [UVec3::X]);
+        // On the edge of its chunk, next to another one.
+        assert_eq!(around(CHUNK_SIZE, 5, 5), [UVec3::X, UVec3::ZERO]);
+        assert_eq!(around(2 * CHUNK_SIZE - 1, 5, 5), [UVec3::X, UVec3::X * 2]);
+        assert_eq!(
+            around(CHUNK_SIZE, 5, CHUNK_SIZE - 1),
+            [UVec3::X, UVec3::ZERO]
+        );
+        // On the edge of the map, with no chunk beyond it.
+        assert_eq!(around(0, 0, 0), [UVec3::ZERO]);
+        assert_eq!(around(20, CHUNK_SIZE - 1, 0), [UVec3::X]);
+        assert_eq!(around(3 * CHUNK_SIZE - 9, 5, 5), [UVec3::X * 2]);
+    }
+}
@@ -0,0 +1,187 @@
+use crate::{MapError, VoxelMap, greedy_quads};
+use bevy::prelude::*;
+use std::collections::HashMap;
+use std::fmt::Write;
+use std::path::Path;
+
+/// Exported models are scaled so that their longer side across the ground is
+/// this long, like the MagicaVoxel exports `turret-game` ships with. The game
+/// draws models at a quarter of their size, so this fills one tile.
+pub const OBJ_MODEL_SIZE: f32 = 4.0;
+
+impl VoxelMap {
+    /// Exports the visible faces as a Wavefront OBJ, centred above the origin
+    /// with the bottom of the map on `y = 0`. Coplanar faces of one material
+    /// are merged.
+    ///
+    /// Returns the OBJ, which refers to its materials as `mtl_file`, and that
+    /// MTL file with a material for each palette index in use.
+    pub fn to_obj(&self, mtl_file: &str) -> (String, String) {
+        let size = self.dimensions.as_uvec3();
+        let quads = greedy_quads(self, UVec3::ZERO, size);
+        let scale = OBJ_MODEL_SIZE / size.x.max(size.z).max(1) as f32;
+        let offset = Vec3::new(size.x as f32, 0.0, size.z as f32) / 2.0;
+
+        let mut obj = String::new();
+        writeln!(obj, "mtllib {mtl_file}").unwrap();
+        let name = Some(self.metadata.name.as_str())
+            .filter(|name| !name.is_empty())
+            .unwrap_or("map");
+        writeln!(obj, "o {}", name.replace(char::is_whitespace, "_")).unwrap();
+        let normals = [
+            IVec3::NEG_X,
+            IVec3::X,
+            IVec3::NEG_Y,
+            IVec3::Y,
+            IVec3::NEG_Z,
+            IVec3::Z,
+        ];
+        for normal in normals {
+            writeln!(obj, "vn {} {} {}", normal.x, normal.y, normal.z).unwrap();
+        }
+
+        // Quads share corners and UVs where they meet. Indices start at 1.
+        let mut positions = HashMap::new();
+        let mut uvs = HashMap::new();
+        for (material, quads) in &quads {
+            writeln!(obj, "usemtl material{material}").unwrap();
+            for quad in quads {
+                let normal = normals
+                    .iter()
+                    .position(|normal| *normal == quad.normal)
+                    .unwrap()
+                    + 1;
+                let corner_uvs = quad.uvs();
+                let mut face = String::from("f");
+                for index in quad.winding() {
+                    let corner = quad.corners[index];
+                    let next = positions.len() + 1;
+                    let position = *positions.entry(corner.as_ivec3()).or_insert_with(|| {
+                        let vertex = (corner - offset) * scale;
+                        writeln!(obj, "v {} {} {}", vertex.x, vertex.y, vertex.z).unwrap();
+                        next
+                    });
+                    let uv = corner_uvs[index];
+                    let next = uvs.len() + 1;
+                    let uv = *uvs.entry(uv).or_insert_with(|| {
+                        writeln!(obj, "vt {} {}", uv.x, uv.y).unwrap();
+                        next
+                    });
+                    write!(face, " {position}/{uv}/{normal}").unwrap();
+                }
+                writeln!(obj, "{face}").unwrap();
+            }
+        }
+
+        let mut mtl = String::new();
+        for material in quads.keys() {
+            let voxel_material = self.palette.get(*material).cloned().unwrap_or_default();
+            let [red, green, blue, alpha] = voxel_material.color.to_f32_array();
+            let emissive = voxel_material.emissive;
+            writeln!(mtl, "newmtl material{material}").unwrap();
+            if !voxel_material.name.is_empty() {
+                writeln!(mtl, "# {}", voxel_material.name).unwrap();
+            }
+            writeln!(mtl, "Kd {red} {green} {blue}").unwrap();
+            writeln!(mtl, "Ks 0 0 0").unwrap();
+            writeln!(
+                mtl,
+                "Ke {} {} {}",
+                emissive.red, emissive.green, emissive.blue
+            )
+            .unwrap();
+            if alpha < 1.0 {
+                writeln!(mtl, "d {alpha}").unwrap();
+            }
+            // The PBR extension of the format.
+            writeln!(mtl, "Pr {}", voxel_material.roughness).unwrap();
+            writeln!(mtl, "Pm {}", voxel_material.metallic).unwrap();
+            if let Some(texture) = &voxel_material.texture {
+                writeln!(mtl, "map_Kd {texture}").unwrap();
+            }
+            writeln!(mtl, "illum 1").unwrap();
+        }
+        (obj, mtl)
+    }
+
+    /// Writes [`VoxelMap::to_obj`] to `path`, and the MTL file next to it with
+    /// the extension `.mtl`.
+    pub fn save_obj(&self, path: impl AsRef<Path>) -> Result<(), MapError> {
+        let path = path.as_ref();
+        let mtl_path = path.with_extension("mtl");
+        let mtl_file = mtl_path
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        let (obj, mtl) = self.to_obj(&mtl_file);
+        std::fs::write(path, obj)?;
+        std::fs::write(mtl_path, mtl)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Dimensions, TileType, Voxel, VoxelMaterial};
+
+    /// Parses an export as `bevy_obj` does when it loads a model.
+    fn load(map: &VoxelMap) -> (Vec<tobj::Model>, Vec<tobj::Material>) {
+        let (obj, mtl) = map.to_obj("map.mtl");
+        let (models, materials) =
+            tobj::load_obj_buf(&mut obj.as_bytes(), &tobj::GPU_LOAD_OPTIONS, |path| {
+                assert_eq!(path, Path::new("map.mtl"));
+                tobj::load_mtl_buf(&mut mtl.as_bytes())
+            })
+            .unwrap();
+        (models, materials.unwrap())
+    }
+
+    fn triangles(models: &[tobj::Model]) -> usize {
+        models
+            .iter()
+            .map(|model| model.mesh.indices.len() / 3)
+            .sum()
+    }
+
+    #[test]
+    fn merges_faces_into_a_loadable_model() {
+        let mut map = VoxelMap::filled(Dimensions::new(4, 1, 2), Voxel::new(TileType::Ground, 0));
+        map.palette.set(1, VoxelMaterial::new(Srgba::RED));
+        map.set(UVec3::new(3, 0, 1), Voxel::new(TileType::Ground, 1));
+        let (models, materials) = load(&map);
+
+        // Material 0 covers the box but one corner, in eight quads after
+        // merging, and four faces of the corner of material 1 show. Each quad
+        // is two triangles.
+        assert_eq!(triangles(&models), (8 + 4) * 2);
+        let names: Vec<_> = materials.iter().map(|material| &material.name).collect();
+        assert_eq!(names, ["material0", "material1"]);
+        assert_eq!(materials[1].diffuse, Some([1.0, 0.0, 0.0]));
+        assert!(models.iter().all(|model| model.mesh.material_id.is_some()));
+
+        // Centred above the origin, with the longer side OBJ_MODEL_SIZE long.
+        let positions = models
+            .iter()
+            .flat_map(|model| model.mesh.positions.chunks(3))
+            .map(Vec3::from_slice);
+        let (min, max) = positions.fold((Vec3::MAX, Vec3::MIN), |(min, max), position| {
+            (min.min(position), max.max(position))
+        });
+        let half = OBJ_MODEL_SIZE / 2.0;
+        assert_eq!(min, Vec3::new(-half, 0.0, -half / 2.0));
+        assert_eq!(max, Vec3::new(half, 1.0, half / 2.0));
+    }
+
+    #[test]
+    fn writes_one_material_for_each_used() {
+        let mut map = VoxelMap::new(Dimensions::new(3, 1, 1));
+        map.set(UVec3::ZERO, Voxel::new(TileType::Ground, 4));
+        map.set(UVec3::new(2, 0, 0), Voxel::new(TileType::Ground, 4));
+        let (_, mtl) = map.to_obj("map.mtl");
+        assert_eq!(mtl.matches("newmtl").count(), 1);
+        assert!(mtl.contains("newmtl material4"));
+        let (models, _) = load(&map);
+        assert_eq!(triangles(&models), 2 * 6 * 2);
+    }
+}
@@ -0,0 +1,236 @@
+use crate::MapError;
+use bevy::image::{ImageAddressMode, ImageLoaderSettings, ImageSampler, ImageSamplerDescriptor};
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+/// A palette holds at most this many materials, one for each value of
+/// [`Voxel::material`](crate::Voxel::material).
+pub const MAX_MATERIALS: usize = 256;
+
+/// The version of the palette file layout written by [`Palette::to_bytes`].
+/// Palette files are versioned apart from maps, so a change to the map
+/// format does not make older builds refuse palettes they could read.
+pub const PALETTE_VERSION: u32 = 1;
+
+/// How the voxels with one material index look.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct VoxelMaterial {
+    pub name: String,
+    #[serde(with = "hex")]
+    pub color: Srgba,
+    /// Perceptual roughness, from 0 for a mirror to 1.
+    pub roughness: f32,
+    pub metallic: f32,
+    #[serde(with = "hex")]
+    pub emissive: Srgba,
+    /// An image repeated once per voxel on every face, relative to the assets
+    /// folder.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub texture: Option<String>,
+}
+
+impl Default for VoxelMaterial {
+    fn default() -> Self {
+        VoxelMaterial {
+            name: String::new(),
+            color: Srgba::WHITE,
+            roughness: 0.5,
+            metallic: 0.0,
+            emissive: Srgba::BLACK,
+            texture: None,
+        }
+    }
+}
+
+impl VoxelMaterial {
+    pub fn new(color: impl Into<Srgba>) -> Self {
+        VoxelMaterial {
+            color: color.into(),
+            ..default()
+        }
+    }
+
+    pub fn to_standard_material(&self, asset_server: &AssetServer) -> StandardMaterial {
+        StandardMaterial {
+            base_color: self.color.into(),
+            perceptual_roughness: self.roughness,
+            metallic: self.metallic,
+            emissive: self.emissive.into(),
+            base_color_texture: self.texture.as_ref().map(|path| {
+                asset_server.load_with_settings(path, |settings: &mut ImageLoaderSettings| {
+                    settings.sampler = ImageSampler::Descriptor(ImageSamplerDescriptor {
+                        address_mode_u: ImageAddressMode::Repeat,
+                        address_mode_v: ImageAddressMode::Repeat,
+                        ..ImageSamplerDescriptor::nearest()
+                    });
+                })
+            }),
+            ..default()
+        }
+    }
+}
+
+/// The materials of a map, indexed by [`Voxel::material`](crate::Voxel::material).
+/// Indices past the end of the palette look like [`VoxelMaterial::default`].
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct Palette(Vec<VoxelMaterial>);
+
+impl Default for Palette {
+    fn default() -> Self {
+        Palette::generated(16)
+    }
+}
+
+impl Palette {
+    pub fn new() -> Self {
+        Palette(Vec::new())
+    }
+
+    /// `len` materials in the colours voxels had before maps carried a
+    /// palette. Material 0 is the colour voxels have always had.
+    pub fn generated(len: usize) -> Self {
+        Palette(
+            (0..len.min(MAX_MATERIALS))
+                .map(|index| match index {
+                    0 => VoxelMaterial::new(Srgba::rgb_u8(124, 144, 255)),
+                    _ => {
+                        // Rounded to what a saved palette holds.
+                        let color = Srgba::from(Hsla::hsl(index as f32 * 137.5 % 360.0, 0.6, 0.55));
+                        let [red, green, blue, _] = color.to_u8_array();
+                        VoxelMaterial::new(Srgba::rgb_u8(red, green, blue))
+                    }
+                })
+                .collect(),
+        )
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    pub fn is_full(&self) -> bool {
+        self.0.len() >= MAX_MATERIALS
+    }
+
+    pub fn get(&self, index: u8) -> Option<&VoxelMaterial> {
+        self.0.get(index as usize)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &VoxelMaterial> {
+        self.0.iter()
+    }
+
+    /// Adds a material and returns its index, or `None` if the palette is full.
+    pub fn push(&mut self, material: VoxelMaterial) -> Option<u8> {
+        if self.is_full() {
+            return None;
+        }
+        self.0.push(material);
+        Some((self.0.len() - 1) as u8)
+    }
+
+    /// Replaces a material and returns the old one, or `None` if `index` is
+    /// past the end of the palette.
+    pub fn set(&mut self, index: u8, material: VoxelMaterial) -> Option<VoxelMaterial> {
+        let slot = self.0.get_mut(index as usize)?;
+        Some(std::mem::replace(slot, material))
+    }
+
+    pub(crate) fn check(&self) -> Result<(), MapError> {
+        if self.0.len() > MAX_MATERIALS {
+            return Err(MapError::PaletteTooLarge(self.0.len()));
+        }
+        Ok(())
+    }
+}
+
+/// A palette saved on its own, to share it between maps.
+#[derive(Serialize, Deserialize)]
+struct PaletteFile {
+    version: u32,
+    materials: Palette,
+}
+
+impl Palette {
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, MapError> {
+        let file: PaletteFile = serde_json5::from_slice(bytes)?;
+        if file.version > PALETTE_VERSION {
+            return Err(MapError::UnsupportedPaletteVersion(file.version));
+        }
+        file.materials.check()?;
+        Ok(file.materials)
+    }
+
+    pub fn to_bytes(&self) -> Result<Vec<u8>, MapError> {
+        let file = PaletteFile {
+            version: PALETTE_VERSION,
+            materials: self.clone(),
+        };
+        Ok(serde_json5::to_string(&file)?.into_bytes())
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, MapError> {
+        Palette::from_bytes(&std::fs::read(path)?)
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), MapError> {
+        std::fs::write(path, self.to_bytes()?)?;
+        Ok(())
+    }
+}
+
+/// Colours are saved as `"#rrggbb"`, or `"#rrggbbaa"` when they are not
+/// opaque, as in the editor's style sheets.
+mod hex {
+    use bevy::color::Srgba;
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(color: &Srgba, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&color.to_hex())
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Srgba, D::Error> {
+        let hex = String::deserialize(deserializer)?;
+        Srgba::hex(&hex)
+            .map_err(|err| serde::de::Error::custom(format!("invalid colour `{hex}`: {err}")))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trips() {
+        let mut palette = Palette::generated(3);
+        palette.set(
+            1,
+            VoxelMaterial {
+                name: "Lava".to_string(),
+                emissive: Srgba::rgb_u8(255, 64, 0),
+                texture: Some("textures/lava.png".to_string()),
+                ..VoxelMaterial::new(Srgba::rgba_u8(200, 40, 0, 128))
+            },
+        );
+        assert_eq!(
+            Palette::from_bytes(&palette.to_bytes().unwrap()).unwrap(),
+            palette
+        );
+    }
+
+    #[test]
+    fn rejects_newer_versions() {
+        let bytes = format!("{{ version: {}, materials: [] }}", PALETTE_VERSION + 1);
+        assert!(matches!(
+            Palette::from_bytes(bytes.as_bytes()),
+            Err(MapError::UnsupportedPaletteVersion(version)) if version == PALETTE_VERSION + 1
+        ));
+    }
+}
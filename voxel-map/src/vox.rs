@@ -0,0 +1,622 @@
+use crate::{Dimensions, Palette, TileType, Voxel, VoxelMap, VoxelMaterial};
+use bevy::math::I64Vec3;
+use bevy::prelude::*;
+use std::collections::HashMap;
+use std::path::Path;
+use thiserror::Error;
+
+/// MagicaVoxel models are at most this many voxels along each side. Larger
+/// maps are exported as several models side by side.
+pub const VOX_MODEL_SIZE: u32 = 256;
+
+/// Imported scenes may span at most this many voxels, empty ones included,
+/// so a crafted file cannot make [`VoxelMap::from_vox`] allocate without end.
+pub const MAX_VOX_VOLUME: u64 = 1 << 27;
+
+/// The version written by [`VoxelMap::to_vox`].
+const VOX_VERSION: i32 = 150;
+
+#[derive(Debug, Error)]
+pub enum VoxError {
+    #[error("could not read .vox file: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("not a MagicaVoxel .vox file")]
+    NotVox,
+    #[error(".vox file ends in the middle of a {0} chunk")]
+    Truncated(String),
+    #[error(".vox file has no voxels")]
+    Empty,
+    #[error(".vox scene spans more than the {MAX_VOX_VOLUME} voxels a map can import")]
+    TooLarge,
+    #[error("material {0} has no colour index in a .vox file, which has 255")]
+    MaterialOutOfRange(u8),
+}
+
+/// The header and body of a chunk, and the chunks nested in it.
+struct Chunk<'a> {
+    id: [u8; 4],
+    content: Reader<'a>,
+    children: &'a [u8],
+}
+
+/// Reads little-endian values off the front of a byte slice.
+#[derive(Clone, Copy)]
+struct Reader<'a> {
+    bytes: &'a [u8],
+    /// The chunk being read, for errors.
+    chunk: [u8; 4],
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize) -> Result<&'a [u8], VoxError> {
+        if self.bytes.len() < len {
+            return Err(VoxError::Truncated(
+                String::from_utf8_lossy(&self.chunk).into_owned(),
+            ));
+        }
+        let (taken, rest) = self.bytes.split_at(len);
+        self.bytes = rest;
+        Ok(taken)
+    }
+
+    fn i32(&mut self) -> Result<i32, VoxError> {
+        Ok(i32::from_le_bytes(self.take(4)?.try_into().unwrap()))
+    }
+
+    fn len(&mut self) -> Result<usize, VoxError> {
+        Ok(self.i32()?.max(0) as usize)
+    }
+
+    fn string(&mut self) -> Result<String, VoxError> {
+        let len = self.len()?;
+        Ok(String::from_utf8_lossy(self.take(len)?).into_owned())
+    }
+
+    fn dict(&mut self) -> Result<HashMap<String, String>, VoxError> {
+        (0..self.len()?)
+            .map(|_| Ok((self.string()?, self.string()?)))
+            .collect()
+    }
+
+    fn chunks(mut self) -> Result<Vec<Chunk<'a>>, VoxError> {
+        let mut chunks = Vec::new();
+        while !self.bytes.is_empty() {
+            let id: [u8; 4] = self.take(4)?.try_into().unwrap();
+            self.chunk = id;
+            let content = self.len()?;
+            let children = self.len()?;
+            chunks.push(Chunk {
+                id,
+                content: Reader {
+                    bytes: self.take(content)?,
+                    chunk: id,
+                },
+                children: self.take(children)?,
+            });
+        }
+        Ok(chunks)
+    }
+}
+
+/// A rotation from an `nTRN` frame: a permutation of the axes with signs,
+/// stored as rows.
+#[derive(Debug, Clone, Copy)]
+struct Rotation([IVec3; 3]);
+
+impl Rotation {
+    const IDENTITY: Rotation = Rotation([IVec3::X, IVec3::Y, IVec3::Z]);
+
+    /// Bits 0-1 and 2-3 are the column of the non-zero entry in the first and
+    /// second rows, and bits 4-6 make the entry of each row negative.
+    fn from_byte(byte: u8) -> Self {
+        let first = (byte & 3) as usize;
+        let second = (byte >> 2 & 3) as usize;
+        let columns = [first, second, 3usize.saturating_sub(first + second).min(2)];
+        let mut rows = [IVec3::ZERO; 3];
+        for (row, column) in columns.into_iter().enumerate() {
+            rows[row][column] = if byte & (16 << row) != 0 { -1 } else { 1 };
+        }
+        Rotation(rows)
+    }
+
+    /// `None` if a coordinate overflows, which only crafted files get near.
+    fn apply(&self, vector: IVec3) -> Option<IVec3> {
+        let row = |row: IVec3| {
+            (0..3).try_fold(0i32, |sum, axis| {
+                sum.checked_add(row[axis].checked_mul(vector[axis])?)
+            })
+        };
+        Some(IVec3::new(
+            row(self.0[0])?,
+            row(self.0[1])?,
+            row(self.0[2])?,
+        ))
+    }
+
+    fn then(&self, inner: &Rotation) -> Rotation {
+        Rotation(
+            self.0
+                .map(|row| inner.0[0] * row.x + inner.0[1] * row.y + inner.0[2] * row.z),
+        )
+    }
+}
+
+/// Adds two positions, or `None` if a coordinate overflows.
+fn checked_add(a: IVec3, b: IVec3) -> Option<IVec3> {
+    Some(IVec3::new(
+        a.x.checked_add(b.x)?,
+        a.y.checked_add(b.y)?,
+        a.z.checked_add(b.z)?,
+    ))
+}
+
+enum Node {
+    Transform {
+        child: i32,
+        translation: IVec3,
+        rotation: Rotation,
+    },
+    Group(Vec<i32>),
+    Shape(Vec<i32>),
+}
+
+struct Model {
+    size: IVec3,
+    voxels: Vec<[u8; 4]>,
+}
+
+impl VoxelMap {
+    /// Imports a MagicaVoxel file. Every model in its scene is placed where
+    /// MagicaVoxel shows it, and the map is just big enough to hold them all.
+    ///
+    /// Voxels become [`TileType::Ground`], and colour index `i` becomes
+    /// material `i - 1`. Files without an `RGBA` chunk use MagicaVoxel's
+    /// default palette. Metal, roughness and emission are read from the
+    /// `MATL` chunks where present.
+    pub fn from_vox(bytes: &[u8]) -> Result<Self, VoxError> {
+        let mut file = Reader {
+            bytes,
+            chunk: *b"VOX ",
+        };
+        if bytes.len() < 8 || file.take(4)? != b"VOX " {
+            return Err(VoxError::NotVox);
+        }
+        let _version = file.i32()?;
+        let main = file.chunks()?;
+        let main = main
+            .iter()
+            .find(|chunk| &chunk.id == b"MAIN")
+            .ok_or(VoxError::NotVox)?;
+
+        let mut models = Vec::new();
+        let mut size = IVec3::ZERO;
+        let mut colors = None;
+        let mut materials = HashMap::new();
+        let mut nodes = HashMap::new();
+        let reader = Reader {
+            bytes: main.children,
+            chunk: main.id,
+        };
+        for chunk in reader.chunks()? {
+            let mut content = chunk.content;
+            match &chunk.id {
+                b"SIZE" => size = IVec3::new(content.i32()?, content.i32()?, content.i32()?),
+                b"XYZI" => {
+                    let voxels = (0..content.len()?)
+                        .map(|_| Ok(content.take(4)?.try_into().unwrap()))
+                        .collect::<Result<_, VoxError>>()?;
+                    models.push(Model { size, voxels });
+                }
+                b"RGBA" => {
+                    let rgba: Vec<[u8; 4]> = (0..256)
+                        .map(|_| Ok(content.take(4)?.try_into().unwrap()))
+                        .collect::<Result<_, VoxError>>()?;
+                    colors = Some(rgba);
+                }
+                b"MATL" => {
+                    let id = content.i32()?;
+                    materials.insert(id, content.dict()?);
+                }
+                b"nTRN" => {
+                    let id = content.i32()?;
+                    let _attributes = content.dict()?;
+                    let child = content.i32()?;
+                    let _reserved = content.i32()?;
+                    let _layer = content.i32()?;
+                    let frames = content.len()?;
+                    let frame = if frames > 0 {
+                        content.dict()?
+                    } else {
+                        HashMap::new()
+                    };
+                    let translation = frame
+                        .get("_t")
+                        .map(|value| {
+                            let mut parts = value.split_whitespace().map(|part| part.parse());
+                            let mut next = || parts.next().and_then(Result::ok).unwrap_or(0);
+                            IVec3::new(next(), next(), next())
+                        })
+                        .unwrap_or_default();
+                    let rotation = frame
+                        .get("_r")
+                        .and_then(|value| value.parse().ok())
+                        .map_or(Rotation::IDENTITY, Rotation::from_byte);
+                    nodes.insert(
+                        id,
+                        Node::Transform {
+                            child,
+                            translation,
+                            rotation,
+                        },
+                    );
+                }
+                b"nGRP" => {
+                    let id = content.i32()?;
+                    let _attributes = content.dict()?;
+                    let children = (0..content.len()?)
+                        .map(|_| content.i32())
+                        .collect::<Result<_, _>>()?;
+                    nodes.insert(id, Node::Group(children));
+                }
+                b"nSHP" => {
+                    let id = content.i32()?;
+                    let _attributes = content.dict()?;
+                    let mut shapes = Vec::new();
+                    for _ in 0..content.len()? {
+                        shapes.push(content.i32()?);
+                        let _attributes = content.dict()?;
+                    }
+                    nodes.insert(id, Node::Shape(shapes));
+                }
+                // Layers, cameras, render settings and the like.
+                _ => {}
+            }
+        }
+
+        // Without a scene graph, every model sits at the origin.
+        let mut placed = Vec::new();
+        if nodes.contains_key(&0) {
+            place(&nodes, 0, IVec3::ZERO, Rotation::IDENTITY, &mut placed, 0)?;
+        } else {
+            placed.extend((0..models.len() as i32).map(|model| (model, None)));
+        }
+
+        let mut voxels = Vec::new();
+        for (model, transform) in placed {
+            let Some(model) = models.get(model as usize) else {
+                continue;
+            };
+            for [x, y, z, index] in &model.voxels {
+                if *index == 0 {
+                    continue;
+                }
+                let position = IVec3::new(*x as i32, *y as i32, *z as i32);
+                let position = match transform {
+                    // MagicaVoxel translates the centre of a model.
+                    Some((translation, rotation)) => rotation
+                        .apply(position - model.size / 2)
+                        .and_then(|offset| checked_add(translation, offset))
+                        .ok_or(VoxError::TooLarge)?,
+                    None => position,
+                };
+                voxels.push((position, *index));
+            }
+        }
+        let (Some(min), Some(max)) = (
+            voxels
+                .iter()
+                .map(|(position, _)| *position)
+                .reduce(IVec3::min),
+            voxels
+                .iter()
+                .map(|(position, _)| *position)
+                .reduce(IVec3::max),
+        ) else {
+            return Err(VoxError::Empty);
+        };
+
+        // Wide enough that no span of `i32` positions overflows.
+        let size = max.as_i64vec3() - min.as_i64vec3() + I64Vec3::ONE;
+        let volume = size
+            .as_u64vec3()
+            .to_array()
+            .into_iter()
+            .try_fold(1u64, u64::checked_mul);
+        if volume.is_none_or(|volume| volume > MAX_VOX_VOLUME) {
+            return Err(VoxError::TooLarge);
+        }
+        let size = size.as_uvec3();
+
+        // MagicaVoxel is Z up with Y going away from the viewer. Ours is Y
+        // up with Z coming towards the viewer.
+        let mut map = VoxelMap::new(Dimensions::new(size.x, size.z, size.y));
+        for (position, index) in voxels {
+            let position = (position - min).as_uvec3();
+            map.set(
+                UVec3::new(position.x, position.z, size.y - 1 - position.y),
+                Voxel::new(TileType::Ground, index - 1),
+            );
+        }
+        map.palette = match colors {
+            Some(colors) => vox_palette(&colors, &materials),
+            None => vox_palette(&default_colors(), &materials),
+        };
+        Ok(map)
+    }
+
+    /// Exports to a MagicaVoxel file, split into models of at most
+    /// [`VOX_MODEL_SIZE`] along each side.
+    ///
+    /// Only the shape and the palette survive: tile types, metadata and
+    /// annotations have nowhere to go. Material 255 has no colour index, so
+    /// maps that use it cannot be exported.
+    pub fn to_vox(&self) -> Result<Vec<u8>, VoxError> {
+        let dimensions = self.dimensions();
+        let size = UVec3::new(dimensions.width, dimensions.length, dimensions.height);
+        let mut blocks: HashMap<UVec3, Vec<[u8; 4]>> = HashMap::new();
+        for (position, voxel) in self.iter() {
+            if voxel.is_empty() {
+                continue;
+            }
+            if voxel.material == u8::MAX {
+                return Err(VoxError::MaterialOutOfRange(voxel.material));
+            }
+            let position = UVec3::new(position.x, size.y - 1 - position.z, position.y);
+            let block = position / VOX_MODEL_SIZE;
+            let [x, y, z] = (position % VOX_MODEL_SIZE)
+                .to_array()
+                .map(|value| value as u8);
+            blocks
+                .entry(block)
+                .or_default()
+                .push([x, y, z, voxel.material + 1]);
+        }
+        let mut blocks: Vec<_> = blocks.into_iter().collect();
+        blocks.sort_by_key(|(block, _)| (block.z, block.y, block.x));
+        if blocks.is_empty() {
+            // MagicaVoxel wants at least one model.
+            blocks.push((UVec3::ZERO, Vec::new()));
+        }
+
+        let mut main = Vec::new();
+        for (block, voxels) in &blocks {
+            let model_size =
+                (size - block * VOX_MODEL_SIZE).clamp(UVec3::ONE, UVec3::splat(VOX_MODEL_SIZE));
+            let mut content = Vec::new();
+            for value in model_size.to_array() {
+                content.extend((value as i32).to_le_bytes());
+            }
+            write_chunk(&mut main, b"SIZE", &content, &[]);
+            let mut content = (voxels.len() as i32).to_le_bytes().to_vec();
+            content.extend(voxels.iter().flatten());
+            write_chunk(&mut main, b"XYZI", &content, &[]);
+        }
+
+        // The root transform holds a group of one transform and shape per model.
+        let transform = |id: i32, child: i32, translation: Option<IVec3>| {
+            let mut content = Vec::new();
+            content.extend(id.to_le_bytes());
+            write_dict(&mut content, &[]);
+            content.extend(child.to_le_bytes());
+            content.extend((-1i32).to_le_bytes());
+            content.extend((-1i32).to_le_bytes());
+            content.extend(1i32.to_le_bytes());
+            match translation {
+                Some(translation) => write_dict(
+                    &mut content,
+                    &[(
+                        "_t",
+                        format!("{} {} {}", translation.x, translation.y, translation.z),
+                    )],
+                ),
+                None => write_dict(&mut content, &[]),
+            }
+            content
+        };
+        write_chunk(&mut main, b"nTRN", &transform(0, 1, None), &[]);
+        let mut group = Vec::new();
+        group.extend(1i32.to_le_bytes());
+        write_dict(&mut group, &[]);
+        group.extend((blocks.len() as i32).to_le_bytes());
+        for index in 0..blocks.len() as i32 {
+            group.extend((2 + index * 2).to_le_bytes());
+        }
+        write_chunk(&mut main, b"nGRP", &group, &[]);
+        for (index, (block, _)) in blocks.iter().enumerate() {
+            let index = index as i32;
+            let offset = block * VOX_MODEL_SIZE;
+            let model_size = (size - offset).clamp(UVec3::ONE, UVec3::splat(VOX_MODEL_SIZE));
+            let translation = (offset + model_size / 2).as_ivec3();
+            write_chunk(
+                &mut main,
+                b"nTRN",
+                &transform(2 + index * 2, 3 + index * 2, Some(translation)),
+                &[],
+            );
+            let mut shape = Vec::new();
+            shape.extend((3 + index * 2).to_le_bytes());
+            write_dict(&mut shape, &[]);
+            shape.extend(1i32.to_le_bytes());
+            shape.extend(index.to_le_bytes());
+            write_dict(&mut shape, &[]);
+            write_chunk(&mut main, b"nSHP", &shape, &[]);
+        }
+
+        let mut rgba = Vec::new();
+        for index in 0..=u8::MAX {
+            let color = match self.palette.get(index) {
+                Some(material) if index < u8::MAX => material.color.to_u8_array(),
+                _ => [0; 4],
+            };
+            rgba.extend(color);
+        }
+        write_chunk(&mut main, b"RGBA", &rgba, &[]);
+        for (index, material) in self.palette.iter().enumerate().take(255) {
+            let mut content = (index as i32 + 1).to_le_bytes().to_vec();
+            write_dict(&mut content, &vox_material(material));
+            write_chunk(&mut main, b"MATL", &content, &[]);
+        }
+
+        let mut file = b"VOX ".to_vec();
+        file.extend(VOX_VERSION.to_le_bytes());
+        write_chunk(&mut file, b"MAIN", &[], &main);
+        Ok(file)
+    }
+
+    pub fn load_vox(path: impl AsRef<Path>) -> Result<Self, VoxError> {
+        VoxelMap::from_vox(&std::fs::read(path)?)
+    }
+
+    pub fn save_vox(&self, path: impl AsRef<Path>) -> Result<(), VoxError> {
+        std::fs::write(path, self.to_vox()?)?;
+        Ok(())
+    }
+}
+
+/// Walks the scene graph from `id`, collecting each model with where it goes.
+/// `depth` stops a malformed graph that loops.
+fn place(
+    nodes: &HashMap<i32, Node>,
+    id: i32,
+    translation: IVec3,
+    rotation: Rotation,
+    placed: &mut Vec<(i32, Option<(IVec3, Rotation)>)>,
+    depth: usize,
+) -> Result<(), VoxError> {
+    if depth > 64 {
+        return Ok(());
+    }
+    match nodes.get(&id) {
+        Some(Node::Transform {
+            child,
+            translation: local,
+            rotation: local_rotation,
+        }) => {
+            let translation = rotation
+                .apply(*local)
+                .and_then(|local| checked_add(translation, local))
+                .ok_or(VoxError::TooLarge)?;
+            place(
+                nodes,
+                *child,
+                translation,
+                rotation.then(local_rotation),
+                placed,
+                depth + 1,
+            )?;
+        }
+        Some(Node::Group(children)) => {
+            for child in children {
+                place(nodes, *child, translation, rotation, placed, depth + 1)?;
+            }
+        }
+        Some(Node::Shape(models)) => {
+            placed.extend(
+                models
+                    .iter()
+                    .map(|model| (*model, Some((translation, rotation)))),
+            );
+        }
+        None => {}
+    }
+    Ok(())
+}
+
+/// MagicaVoxel's built-in palette, for files without an `RGBA` chunk: the
+/// web-safe colours from white down, leaving out black, then ramps of red,
+/// green, blue and grey. `colors[i - 1]` is colour index `i`, as in `RGBA`.
+fn default_colors() -> Vec<[u8; 4]> {
+    let steps = [5, 4, 3, 2, 1, 0].map(|step| step * 0x33);
+    let mut colors = Vec::new();
+    for red in steps {
+        for green in steps {
+            for blue in steps {
+                if [red, green, blue] != [0; 3] {
+                    colors.push([red, green, blue, 255]);
+                }
+            }
+        }
+    }
+    let ramp = [0xee, 0xdd, 0xbb, 0xaa, 0x88, 0x77, 0x55, 0x44, 0x22, 0x11];
+    for channels in [
+        [true, false, false],
+        [false, true, false],
+        [false, false, true],
+        [true; 3],
+    ] {
+        for level in ramp {
+            let [red, green, blue] = channels.map(|on| if on { level } else { 0 });
+            colors.push([red, green, blue, 255]);
+        }
+    }
+    colors
+}
+
+/// Colour index `i` is `colors[i - 1]`, and there is no index 256.
+fn vox_palette(colors: &[[u8; 4]], materials: &HashMap<i32, HashMap<String, String>>) -> Palette {
+    let mut palette = Palette::new();
+    for (index, [red, green, blue, alpha]) in colors.iter().take(255).enumerate() {
+        let color = Srgba::rgba_u8(*red, *green, *blue, *alpha);
+        let mut material = VoxelMaterial::new(color);
+        if let Some(properties) = materials.get(&(index as i32 + 1)) {
+            let value = |name: &str| properties.get(name).and_then(|value| value.parse().ok());
+            if let Some(rough) = value("_rough") {
+                material.roughness = rough;
+            }
+            match properties.get("_type").map(String::as_str) {
+                Some("_metal") => material.metallic = value("_metal").unwrap_or(0.0),
+                Some("_emit") => {
+                    // MagicaVoxel materials glow in their own colour.
+                    let strength = value("_emit").unwrap_or(1.0);
+                    material.emissive = Srgba::new(
+                        color.red * strength,
+                        color.green * strength,
+                        color.blue * strength,
+                        1.0,
+                    );
+                }
+                _ => {}
+            }
+        }
+        palette.push(material);
+    }
+    palette
+}
+
+fn vox_material(material: &VoxelMaterial) -> Vec<(&'static str, String)> {
+    // Emission is the colour times a strength, as in `vox_palette`.
+    let brightest = |color: Srgba| color.red.max(color.green).max(color.blue);
+    let strength = match brightest(material.color) {
+        0.0 => brightest(material.emissive),
+        color => brightest(material.emissive) / color,
+    };
+    let mut properties = vec![("_rough", material.roughness.to_string())];
+    if strength > 0.0 {
+        properties.push(("_type", "_emit".to_string()));
+        properties.push(("_emit", strength.to_string()));
+    } else if material.metallic > 0.0 {
+        properties.push(("_type", "_metal".to_string()));
+        properties.push(("_metal", material.metallic.to_string()));
+    } else {
+        properties.push(("_type", "_diffuse".to_string()));
+    }
+    properties
+}
+
+fn write_chunk(out: &mut Vec<u8>, id: &[u8; 4], content: &[u8], children: &[u8]) {
+    out.extend(id);
+    out.extend((content.len() as i32).to_le_bytes());
+    out.extend((children.len() as i32).to_le_bytes());
+    out.extend(content);
+    out.extend(children);
+}
+
+fn write_dict(out: &mut Vec<u8>, entries: &[(&str, String)]) {
+    out.extend((entries.len() as i32).to_le_bytes());
+    for (key, value) in entries {
+        for string in [*key, value.as_str()] {
+            out.extend((string.len() as i32).to_le_bytes());
+            out.extend(string.as_bytes());
+        }
+    }
+}
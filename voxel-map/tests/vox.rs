@@ -0,0 +1,101 @@
+//! Imports small MagicaVoxel files written by hand and exports them again.
+
+use bevy::prelude::*;
+use voxel_map::{Dimensions, TileType, VoxError, Voxel, VoxelMap};
+
+fn import(bytes: &[u8]) -> VoxelMap {
+    let map = VoxelMap::from_vox(bytes).unwrap();
+    let again = VoxelMap::from_vox(&map.to_vox().unwrap()).unwrap();
+    assert_eq!(again, map, "the map changed going through .vox and back");
+    map
+}
+
+fn solid(map: &VoxelMap) -> Vec<(UVec3, u8)> {
+    map.iter()
+        .filter(|(_, voxel)| !voxel.is_empty())
+        .map(|(position, voxel)| (position, voxel.material))
+        .collect()
+}
+
+/// One 3x2x1 model without a scene graph or a palette.
+#[test]
+fn single_model() {
+    let map = import(include_bytes!("vox/single.vox"));
+    assert_eq!(map.dimensions(), Dimensions::new(3, 1, 2));
+    assert_eq!(
+        map.get(UVec3::new(0, 0, 1)),
+        Some(Voxel::new(TileType::Ground, 0))
+    );
+    assert_eq!(
+        solid(&map),
+        [(UVec3::new(2, 0, 0), 215), (UVec3::new(0, 0, 1), 0)]
+    );
+
+    // MagicaVoxel's default palette starts at white and ends in a grey ramp.
+    assert_eq!(map.palette.len(), 255);
+    assert_eq!(map.palette.get(0).unwrap().color, Srgba::WHITE);
+    assert_eq!(
+        map.palette.get(1).unwrap().color,
+        Srgba::rgb_u8(255, 255, 204)
+    );
+    assert_eq!(
+        map.palette.get(215).unwrap().color,
+        Srgba::rgb_u8(0xee, 0, 0)
+    );
+    assert_eq!(
+        map.palette.get(254).unwrap().color,
+        Srgba::rgb_u8(0x11, 0x11, 0x11)
+    );
+}
+
+/// Two models, the second turned so its Z axis runs along X and moved by
+/// (5, 2, 1).
+#[test]
+fn translated_and_rotated_models() {
+    let map = import(include_bytes!("vox/scene.vox"));
+    assert_eq!(map.dimensions(), Dimensions::new(8, 2, 3));
+    assert_eq!(
+        solid(&map),
+        [
+            (UVec3::new(0, 0, 2), 0),
+            (UVec3::new(1, 0, 2), 1),
+            (UVec3::new(5, 1, 0), 2),
+            (UVec3::new(7, 1, 0), 254),
+        ]
+    );
+    assert_eq!(map.palette.get(0).unwrap().color, Srgba::rgb_u8(255, 0, 0));
+    assert_eq!(map.palette.get(2).unwrap().color, Srgba::rgb_u8(0, 0, 255));
+    assert_eq!(
+        map.palette.get(254).unwrap().color,
+        Srgba::rgb_u8(255, 255, 0)
+    );
+}
+
+/// An emissive and a metal material.
+#[test]
+fn materials() {
+    let map = import(include_bytes!("vox/materials.vox"));
+    let glowing = map.palette.get(0).unwrap();
+    assert_eq!(glowing.roughness, 0.25);
+    assert_eq!(glowing.emissive.red, 2.0);
+    assert_eq!(glowing.emissive.green, glowing.color.green * 2.0);
+    let metal = map.palette.get(1).unwrap();
+    assert_eq!(metal.color, Srgba::rgb_u8(50, 60, 70));
+    assert_eq!(metal.metallic, 0.75);
+    assert_eq!(metal.roughness, 0.5);
+    assert_eq!(metal.emissive, Srgba::BLACK);
+}
+
+/// One voxel placed four billion voxels from another.
+#[test]
+fn rejects_scenes_too_large_to_import() {
+    let bytes = include_bytes!("vox/far_apart.vox");
+    assert!(matches!(VoxelMap::from_vox(bytes), Err(VoxError::TooLarge)));
+}
+
+/// A voxel moved past the largest coordinate a .vox file can hold.
+#[test]
+fn rejects_positions_that_overflow() {
+    let bytes = include_bytes!("vox/overflow.vox");
+    assert!(matches!(VoxelMap::from_vox(bytes), Err(VoxError::TooLarge)));
+}